@@ -1,13 +1,20 @@
-use clap::{App, Arg};
-use easy_fs::{BlockDevice, EasyFileSystem};
+use clap::{App, Arg, ArgMatches};
+use easy_fs::{
+    fsck, set_block_cache_capacity, BlockDevice, EasyFileSystem, TimeSpec, FEATURE_INDIRECT3,
+    FEATURE_LONG_NAMES, NAME_LENGTH_LIMIT,
+};
 use std::fs::{read_dir, File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::PermissionsExt;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Use a block size of 512 bytes
 const BLOCK_SZ: usize = 512;
 const BLOCK_NUM: usize = 131072; //64*2048
+/// Number of blocks cached while packing
+const BLOCK_CACHE_SIZE: usize = 1024;
 
 /// Wrapper for turning a File into a BlockDevice
 struct BlockFile(Mutex<File>);
@@ -29,18 +36,28 @@ impl BlockDevice for BlockFile {
     }
 }
 
-fn main() {
-    easy_fs_pack().expect("Error when packing easy-fs!");
+/// Convert a host time into an easy-fs one
+fn to_time_spec(time: SystemTime) -> TimeSpec {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    TimeSpec {
+        sec: since_epoch.as_secs(),
+        nsec: since_epoch.subsec_nanos() as u64,
+    }
 }
 
-/// Pack a directory into a easy-fs disk image
-fn easy_fs_pack() -> std::io::Result<()> {
+/// The current host time for inode timestamps
+fn host_clock() -> TimeSpec {
+    to_time_spec(SystemTime::now())
+}
+
+fn main() {
     let matches = App::new("EasyFileSystem packer")
         .arg(
             Arg::with_name("source")
                 .short("s")
                 .long("source")
                 .takes_value(true)
+                .required_unless("check")
                 .help("Executable source dir(with backslash)"),
         )
         .arg(
@@ -48,9 +65,75 @@ fn easy_fs_pack() -> std::io::Result<()> {
                 .short("t")
                 .long("target")
                 .takes_value(true)
+                .required_unless("check")
                 .help("Executable target dir(with backslash)"),
         )
+        .arg(
+            Arg::with_name("check")
+                .short("c")
+                .long("check")
+                .takes_value(true)
+                .conflicts_with_all(&["source", "target"])
+                .help("Check an image instead of packing one"),
+        )
+        .arg(
+            Arg::with_name("repair")
+                .long("repair")
+                .requires("check")
+                .help("Repair the problems found by --check"),
+        )
+        .arg(
+            Arg::with_name("large-files")
+                .short("l")
+                .long("large-files")
+                .conflicts_with("check")
+                .help("Let files grow past 8 MiB through a triple indirect block"),
+        )
+        .arg(
+            Arg::with_name("long-names")
+                .short("n")
+                .long("long-names")
+                .conflicts_with("check")
+                .help("Allow names of up to 255 bytes"),
+        )
         .get_matches();
+    if let Some(image_path) = matches.value_of("check") {
+        let clean = easy_fs_check(image_path, matches.is_present("repair"))
+            .expect("Error when checking easy-fs!");
+        if !clean {
+            std::process::exit(1);
+        }
+    } else {
+        easy_fs_pack(&matches).expect("Error when packing easy-fs!");
+    }
+}
+
+/// Check an easy-fs disk image, returning whether it is clean or was
+/// repaired
+fn easy_fs_check(image_path: &str, repair: bool) -> std::io::Result<bool> {
+    let block_file = Arc::new(BlockFile(Mutex::new(
+        OpenOptions::new()
+            .read(true)
+            .write(repair)
+            .open(image_path)?,
+    )));
+    let report = fsck(block_file, repair);
+    for problem in report.problems.iter() {
+        println!("{}", problem);
+    }
+    println!(
+        "{}: {} inodes, {} blocks in use, {} problems{}",
+        image_path,
+        report.inodes,
+        report.blocks,
+        report.problems.len(),
+        if repair && !report.problems.is_empty() { " repaired" } else { "" },
+    );
+    Ok(report.problems.is_empty() || repair)
+}
+
+/// Pack a directory into a easy-fs disk image
+fn easy_fs_pack(matches: &ArgMatches) -> std::io::Result<()> {
     let src_path = matches.value_of("source").unwrap();
     let target_path = matches.value_of("target").unwrap();
     println!("src_path = {}\ntarget_path = {}", src_path, target_path);
@@ -63,7 +146,16 @@ fn easy_fs_pack() -> std::io::Result<()> {
         f.set_len((BLOCK_NUM * BLOCK_SZ) as u64).unwrap();
         f
     })));
-    let efs = EasyFileSystem::create(block_file.clone(), BLOCK_NUM as u32, 1);
+    let mut features = 0;
+    if matches.is_present("large-files") {
+        features |= FEATURE_INDIRECT3;
+    }
+    if matches.is_present("long-names") {
+        features |= FEATURE_LONG_NAMES;
+    }
+    set_block_cache_capacity(BLOCK_CACHE_SIZE);
+    let efs = EasyFileSystem::create_with_features(block_file.clone(), BLOCK_NUM as u32, 1, features);
+    efs.lock().set_clock(host_clock);
     let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
     let apps: Vec<_> = read_dir(src_path)
         .unwrap()
@@ -74,6 +166,14 @@ fn easy_fs_pack() -> std::io::Result<()> {
             name_with_ext
         })
         .collect();
+    if features & FEATURE_LONG_NAMES == 0 {
+        if let Some(app) = apps.iter().find(|app| app.len() > NAME_LENGTH_LIMIT) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is longer than {} bytes, pack with --long-names", app, NAME_LENGTH_LIMIT),
+            ));
+        }
+    }
     for app in apps {
         // load app data (elf) from host file system
         let mut host_file = File::open(format!("{}{}", target_path, app)).unwrap();
@@ -83,6 +183,14 @@ fn easy_fs_pack() -> std::io::Result<()> {
         let inode = root_inode.create(app.as_str()).unwrap();
         // write data to easy-fs
         inode.write_at(0, all_data.as_slice());
+        // keep host times and permissions, so that builds in the guest
+        // can tell what is up to date
+        let metadata = host_file.metadata()?;
+        inode.set_times(
+            Some(to_time_spec(metadata.accessed()?)),
+            Some(to_time_spec(metadata.modified()?)),
+        );
+        inode.set_mode(metadata.permissions().mode());
     }
     // list apps
     for app in root_inode.ls() {
@@ -91,19 +199,34 @@ fn easy_fs_pack() -> std::io::Result<()> {
     Ok(())
 }
 
+/// Open the test image `target/<name>.img` through a new device, which
+/// sees none of the blocks cached for earlier ones
+#[cfg(test)]
+fn open_image(name: &str) -> std::io::Result<Arc<BlockFile>> {
+    let f = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(format!("target/{}.img", name))?;
+    Ok(Arc::new(BlockFile(Mutex::new(f))))
+}
+
+/// Format the test image `target/<name>.img` with `blocks` blocks
+#[cfg(test)]
+fn image(name: &str, blocks: u32, features: u32) -> std::io::Result<Arc<BlockFile>> {
+    let block_file = open_image(name)?;
+    block_file
+        .0
+        .lock()
+        .unwrap()
+        .set_len(blocks as u64 * BLOCK_SZ as u64)?;
+    EasyFileSystem::create_with_features(block_file.clone(), blocks, 1, features);
+    Ok(block_file)
+}
+
 #[test]
 fn efs_test() -> std::io::Result<()> {
-    let block_file = Arc::new(BlockFile(Mutex::new({
-        let f = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open("target/fs.img")?;
-        f.set_len(BLOCK_NUM * BLOCK_SZ).unwrap();
-        f
-    })));
-    EasyFileSystem::create(block_file.clone(), 4096, 1);
-    let efs = EasyFileSystem::open(block_file.clone());
+    let efs = EasyFileSystem::open(image("fs", 8192, 0)?).unwrap();
     let root_inode = EasyFileSystem::root_inode(&efs);
     root_inode.create("filea");
     root_inode.create("fileb");
@@ -153,3 +276,389 @@ fn efs_test() -> std::io::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn efs_dir_test() -> std::io::Result<()> {
+    let efs = EasyFileSystem::open(image("fs_dir", 8192, 0)?).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert!(root_inode.mkdir("a").is_some());
+    assert!(root_inode.mkdir("a").is_none());
+    assert!(root_inode.mkdir("a/b").is_some());
+    assert!(root_inode.mkdir("missing/b").is_none());
+    let file = root_inode.create("/a/b/file").unwrap();
+    file.write_at(0, b"nested");
+    // `.` and `..` resolve to the same file
+    let same_file = root_inode.find("a/./b/../b/file").unwrap();
+    assert_eq!(same_file.inode_id(), file.inode_id());
+    assert!(root_inode.find("a/b/file/x").is_none());
+    assert_eq!(root_inode.find("..").unwrap().inode_id(), 0);
+    // only empty directories can be removed, and their inode is reused
+    assert!(!root_inode.rmdir("a"));
+    let c_id = root_inode.mkdir("a/c").unwrap().inode_id();
+    assert!(root_inode.rmdir("a/c"));
+    assert!(root_inode.find("a/c").is_none());
+    assert_eq!(root_inode.mkdir("a/d").unwrap().inode_id(), c_id);
+    assert_eq!(root_inode.find("a").unwrap().ls(), vec!["b", "d"]);
+    Ok(())
+}
+
+#[test]
+fn efs_link_test() -> std::io::Result<()> {
+    let efs = EasyFileSystem::open(image("fs_link", 8192, 0)?).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("file").unwrap();
+    file.write_at(0, &[1u8; 40 * BLOCK_SZ]);
+    assert!(root_inode.link("file", "link"));
+    assert!(!root_inode.link("file", "link"));
+    assert_eq!(file.nlink(), 2);
+    assert!(root_inode.unlink("file"));
+    assert!(root_inode.unlink("link"));
+    assert!(root_inode.find("link").is_none());
+    // the open file keeps its inode and data
+    assert_eq!(file.nlink(), 0);
+    let mut buffer = [0u8; BLOCK_SZ];
+    assert_eq!(file.read_at(39 * BLOCK_SZ, &mut buffer), BLOCK_SZ);
+    let inode_id = file.inode_id();
+    assert_ne!(root_inode.create("other").unwrap().inode_id(), inode_id);
+    // freed with the last handle
+    drop(file);
+    assert_eq!(root_inode.create("again").unwrap().inode_id(), inode_id);
+    Ok(())
+}
+
+#[test]
+fn efs_time_test() -> std::io::Result<()> {
+    let block_file = image("fs_time", 8192, 0)?;
+    let efs = EasyFileSystem::open(block_file.clone()).unwrap();
+    efs.lock().set_clock(host_clock);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let start = host_clock();
+    let file = root_inode.create("file").unwrap();
+    let created = file.metadata();
+    assert!(created.mtime >= start);
+    assert_eq!(created.mode, easy_fs::DEFAULT_FILE_MODE);
+    file.write_at(0, b"hello");
+    let written = file.metadata();
+    assert!(written.mtime >= created.mtime);
+    assert_eq!(written.atime, created.atime);
+    assert!(root_inode.metadata().mtime >= start);
+    // times set explicitly survive reopening the image
+    let old = TimeSpec { sec: 1_000, nsec: 7 };
+    file.set_times(None, Some(old));
+    file.set_mode(0o100600);
+    drop(file);
+    drop(root_inode);
+    let efs = EasyFileSystem::open(block_file).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.find("file").unwrap();
+    let metadata = file.metadata();
+    assert_eq!(metadata.mtime, old);
+    assert_eq!(metadata.atime, written.atime);
+    assert_eq!(metadata.mode, 0o600);
+    assert_eq!(metadata.size, 5);
+    Ok(())
+}
+
+/// A block device which loses every write after a given number of them,
+/// as if the machine was switched off. The filesystem still running on it
+/// reads the lost writes back, in case the blocks were evicted meanwhile.
+#[cfg(test)]
+struct CrashingBlockFile {
+    block_file: Arc<BlockFile>,
+    writes_left: Mutex<usize>,
+    lost_writes: Mutex<std::collections::HashMap<usize, Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl BlockDevice for CrashingBlockFile {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        match self.lost_writes.lock().unwrap().get(&block_id) {
+            Some(block) => buf.copy_from_slice(block),
+            None => self.block_file.read_block(block_id, buf),
+        }
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut writes_left = self.writes_left.lock().unwrap();
+        if *writes_left > 0 {
+            *writes_left -= 1;
+            self.block_file.write_block(block_id, buf);
+        } else {
+            self.lost_writes.lock().unwrap().insert(block_id, buf.to_vec());
+        }
+    }
+}
+
+#[test]
+fn efs_journal_test() -> std::io::Result<()> {
+    let (mut complete, mut absent) = (0, 0);
+    // crash after every number of writes of a mkdir
+    for writes in 0..32 {
+        let block_file = image("fs_journal", 8192, 0)?;
+        let efs = EasyFileSystem::open(Arc::new(CrashingBlockFile {
+            block_file,
+            writes_left: Mutex::new(writes),
+            lost_writes: Mutex::new(Default::default()),
+        }))
+        .unwrap();
+        let root_inode = EasyFileSystem::root_inode(&efs);
+        root_inode.mkdir("dir");
+        drop(root_inode);
+        // reboot, replaying the journal
+        let efs = EasyFileSystem::open(open_image("fs_journal")?).unwrap();
+        let report = fsck(open_image("fs_journal")?, false);
+        assert!(report.problems.is_empty(), "{:?}", report.problems);
+        let root_inode = EasyFileSystem::root_inode(&efs);
+        // the directory is either complete or not there at all
+        let next_inode_id = match root_inode.find("dir") {
+            Some(dir) => {
+                complete += 1;
+                assert!(dir.ls().is_empty());
+                assert_eq!(dir.find("..").unwrap().inode_id(), 0);
+                assert_eq!(root_inode.nlink(), 3);
+                2
+            }
+            None => {
+                absent += 1;
+                assert!(root_inode.ls().is_empty());
+                assert_eq!(root_inode.nlink(), 2);
+                1
+            }
+        };
+        // and its inode is allocated exactly when it is there
+        assert_eq!(root_inode.mkdir("other").unwrap().inode_id(), next_inode_id);
+    }
+    assert!(complete > 0 && absent > 0);
+    Ok(())
+}
+
+#[test]
+fn efs_old_format_test() -> std::io::Result<()> {
+    use easy_fs::FormatError;
+    let block_file = image("fs_old_format", 8192, 0)?;
+    let mut super_block = [0u8; BLOCK_SZ];
+    block_file.read_block(0, &mut super_block);
+    // the magic of images made before the journal
+    super_block[..4].copy_from_slice(&0x3b800001u32.to_le_bytes());
+    block_file.write_block(0, &super_block);
+    let efs = EasyFileSystem::open(open_image("fs_old_format")?);
+    assert_eq!(efs.err(), Some(FormatError::OldFormat));
+    super_block[..4].copy_from_slice(&[0; 4]);
+    block_file.write_block(0, &super_block);
+    let efs = EasyFileSystem::open(open_image("fs_old_format")?);
+    assert_eq!(efs.err(), Some(FormatError::NotEasyFs));
+    Ok(())
+}
+
+#[test]
+fn efs_fsck_test() -> std::io::Result<()> {
+    use easy_fs::Problem;
+    // image writes below bypass the block cache, so each look at the
+    // image goes through a new device
+    let efs = EasyFileSystem::open(image("fs_fsck", 8192, 0)?).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    root_inode.mkdir("dir").unwrap();
+    let file = root_inode.create("dir/file").unwrap();
+    file.write_at(0, &[1u8; 40 * BLOCK_SZ]);
+    assert!(root_inode.link("dir/file", "link"));
+    assert_eq!(file.inode_id(), 2);
+    drop(file);
+    drop(root_inode);
+    let report = fsck(open_image("fs_fsck")?, false);
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+    assert_eq!(report.inodes, 3);
+    // file data, its indirect block and a block for each directory
+    assert_eq!(report.blocks, 40 + 1 + 2);
+
+    // the super block and journal come first, then one inode bitmap block
+    let inode_bitmap = 1 + 33;
+    let inode_area = inode_bitmap + 1;
+    let image = open_image("fs_fsck")?;
+    let patch = |block_id: usize, f: &dyn Fn(&mut [u8; BLOCK_SZ])| {
+        let mut block = [0u8; BLOCK_SZ];
+        image.read_block(block_id, &mut block);
+        f(&mut block);
+        image.write_block(block_id, &block);
+    };
+    // a bogus link count for the file, after its size and pointers
+    patch(inode_area + 1, &|inodes| inodes[30 * 4] = 7);
+    // an inode allocated for nothing
+    patch(inode_bitmap, &|bitmap| bitmap[0] |= 1 << 5);
+    // a name with a `/` for `link`, the fourth entry of the root
+    let mut root_inode = [0u8; BLOCK_SZ];
+    image.read_block(inode_area, &mut root_inode);
+    let root_data = u32::from_le_bytes([root_inode[4], root_inode[5], root_inode[6], root_inode[7]]);
+    let root_data = root_data as usize;
+    patch(root_data, &|dirents| dirents[3 * 32..3 * 32 + 2].copy_from_slice(b"a/"));
+
+    let report = fsck(open_image("fs_fsck")?, false);
+    assert_eq!(
+        report.problems,
+        vec![
+            Problem::BadDirEntry { dir_id: 0, slot: 3 },
+            Problem::LinkCountMismatch { inode_id: 2, stored: 7, actual: 1 },
+            Problem::InodeLeaked { inode_id: 5 },
+        ]
+    );
+    assert_eq!(fsck(open_image("fs_fsck")?, true).problems.len(), 3);
+    let report = fsck(open_image("fs_fsck")?, false);
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+    let efs = EasyFileSystem::open(open_image("fs_fsck")?).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(root_inode.ls(), vec!["dir"]);
+    assert_eq!(root_inode.find("dir/file").unwrap().nlink(), 1);
+    Ok(())
+}
+
+/// A block device counting the blocks read from it
+#[cfg(test)]
+struct CountingBlockFile {
+    block_file: Arc<BlockFile>,
+    reads: Mutex<usize>,
+}
+
+#[cfg(test)]
+impl BlockDevice for CountingBlockFile {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        *self.reads.lock().unwrap() += 1;
+        self.block_file.read_block(block_id, buf);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.block_file.write_block(block_id, buf);
+    }
+}
+
+#[test]
+fn efs_cache_test() -> std::io::Result<()> {
+    use easy_fs::BlockCacheManager;
+    let block_file = open_image("fs_cache")?;
+    block_file.0.lock().unwrap().set_len(8 * BLOCK_SZ as u64)?;
+    let device = Arc::new(CountingBlockFile {
+        block_file: block_file.clone(),
+        reads: Mutex::new(0),
+    });
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    let reads = || *device.reads.lock().unwrap();
+    // a manager of its own, leaving the global cache to the other tests
+    let mut manager = BlockCacheManager::default();
+    manager.set_capacity(2);
+    // more blocks in use than it keeps, so the cache has to grow
+    let held: Vec<_> = (0..4)
+        .map(|block_id| manager.get_block_cache(block_id, Arc::clone(&block_device)))
+        .collect();
+    for (block_id, cache) in held.iter().enumerate() {
+        cache.lock().modify(0, |value: &mut u64| *value = block_id as u64 + 1);
+    }
+    let cached: Vec<_> = held.iter().map(Arc::downgrade).collect();
+    drop(held);
+    assert_eq!(reads(), 4);
+    // the next block shrinks it back to its capacity
+    manager.get_block_cache(4, Arc::clone(&block_device));
+    assert_eq!(reads(), 5);
+    let evicted: Vec<_> = (0..4).filter(|&block_id| cached[block_id].upgrade().is_none()).collect();
+    assert_eq!(evicted.len(), 3);
+    manager.get_block_cache(4, Arc::clone(&block_device));
+    assert_eq!(reads(), 5);
+    // evicted blocks were written back
+    for block_id in evicted {
+        let mut block = [0u8; BLOCK_SZ];
+        block_file.read_block(block_id, &mut block);
+        assert_eq!(block[0] as usize, block_id + 1);
+    }
+    Ok(())
+}
+
+#[test]
+fn efs_large_file_test() -> std::io::Result<()> {
+    let block_file = image("fs_large", 32768, 0)?;
+    // 10 MiB, well into the indirect3 block
+    let data: Vec<u8> = (0..10 << 20).map(|i: u32| (i / 509) as u8).collect();
+    // without the feature a file stops at the indirect2 block
+    let efs = EasyFileSystem::open(block_file.clone()).unwrap();
+    let max_file_size = efs.lock().max_file_size() as usize;
+    let file = EasyFileSystem::root_inode(&efs).create("file").unwrap();
+    assert_eq!(file.write_at(0, &data), max_file_size);
+    assert_eq!(file.write_at(max_file_size, &data), 0);
+    drop(file);
+    drop(efs);
+    let efs = EasyFileSystem::create_with_features(block_file.clone(), 32768, 1, FEATURE_INDIRECT3);
+    assert!(efs.lock().max_file_size() as usize > data.len());
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("file").unwrap();
+    assert_eq!(file.write_at(0, &data), data.len());
+    drop(file);
+    drop(root_inode);
+    drop(efs);
+    let efs = EasyFileSystem::open(block_file.clone()).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.find("file").unwrap();
+    let mut buffer = vec![0u8; data.len()];
+    assert_eq!(file.read_at(0, &mut buffer), data.len());
+    assert!(buffer == data);
+    let report = fsck(block_file.clone(), false);
+    assert!(report.problems.is_empty());
+    assert_eq!(report.blocks, 1 + file.metadata().blocks as usize);
+    // freed over several transactions
+    file.clear();
+    assert_eq!(fsck(block_file.clone(), false).blocks, 1);
+    assert_eq!(file.write_at(9 << 20, &data[..BLOCK_SZ]), BLOCK_SZ);
+    assert!(root_inode.unlink("file"));
+    drop(file);
+    let report = fsck(block_file, false);
+    assert!(report.problems.is_empty());
+    assert_eq!(report.blocks, 1);
+    Ok(())
+}
+
+#[test]
+fn efs_long_name_test() -> std::io::Result<()> {
+    let block_file = image("fs_long_name", 8192, 0)?;
+    let name = |len: usize| -> String { (0..len).map(|i| (b'a' + (i % 26) as u8) as char).collect() };
+    // without the feature names stay short
+    let efs = EasyFileSystem::open(block_file.clone()).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert!(root_inode.create(&name(27)).is_some());
+    assert!(root_inode.create(&name(28)).is_none());
+    drop(root_inode);
+    drop(efs);
+
+    let efs = EasyFileSystem::create_with_features(block_file.clone(), 8192, 1, FEATURE_LONG_NAMES);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let lens = [27, 28, 59, 60, 3, 255];
+    for &len in lens.iter() {
+        root_inode.create(&name(len)).unwrap().write_at(0, name(len).as_bytes());
+    }
+    assert!(root_inode.create(&name(256)).is_none());
+    assert!(root_inode.create(&name(60)).is_none());
+    assert_eq!(root_inode.ls(), lens.iter().map(|&len| name(len)).collect::<Vec<_>>());
+    // a path through a directory with a long name
+    let dir = format!("{}/", name(100));
+    root_inode.mkdir(&dir).unwrap();
+    assert!(root_inode.create(&format!("{}{}", dir, name(200))).is_some());
+    assert!(!root_inode.rmdir(&dir));
+    assert!(root_inode.unlink(&format!("/{}{}", dir, name(200))));
+    assert!(root_inode.rmdir(&dir));
+    // the slots of removed long names are reused where they fit
+    assert!(root_inode.unlink(&name(59)));
+    let size = root_inode.metadata().size;
+    root_inode.create("short").unwrap();
+    root_inode.create(&name(40)).unwrap();
+    assert_eq!(root_inode.metadata().size, size);
+    drop(root_inode);
+    drop(efs);
+
+    let report = fsck(block_file.clone(), false);
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+    let efs = EasyFileSystem::open(block_file).unwrap();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(
+        root_inode.ls(),
+        vec![name(27), name(28), "short".to_string(), name(60), name(3), name(255), name(40)]
+    );
+    let mut buffer = [0u8; 255];
+    for &len in [28, 60, 255].iter() {
+        let file = root_inode.find(&name(len)).unwrap();
+        assert_eq!(file.read_at(0, &mut buffer), len);
+        assert_eq!(&buffer[..len], name(len).as_bytes());
+    }
+    Ok(())
+}
@@ -35,26 +35,28 @@ impl Bitmap {
     /// Allocate a new block from a block device
     pub fn alloc(&self, block_device: &Arc<dyn BlockDevice>) -> Option<usize> {
         for block_id in 0..self.blocks {
-            let pos = get_block_cache(
+            let bitmap_block = get_block_cache(
                 block_id + self.start_block_id as usize,
                 Arc::clone(block_device),
-            ).lock().modify(0, |bitmap_block: &mut BitmapBlock| {
-                if let Some((bits64_pos, inner_pos)) = bitmap_block
+            );
+            let mut bitmap_block = bitmap_block.lock();
+            // only modify the block with a free bit, so that a transaction
+            // does not log every full block passed on the way
+            let free_bit = bitmap_block.read(0, |bitmap_block: &BitmapBlock| {
+                bitmap_block
                     .iter()
                     .enumerate()
                     .find(|(_, bits64)| **bits64 != u64::MAX)
                     .map(|(bits64_pos, bits64)| {
                         (bits64_pos, bits64.trailing_ones() as usize)
-                    }) {
-                    // modify cache
-                    bitmap_block[bits64_pos] |= 1u64 << inner_pos;
-                    Some(block_id * BLOCK_BITS + bits64_pos * 64 + inner_pos as usize)
-                } else {
-                    None
-                }
+                    })
             });
-            if pos.is_some() {
-                return pos;
+            if let Some((bits64_pos, inner_pos)) = free_bit {
+                // modify cache
+                bitmap_block.modify(0, |bitmap_block: &mut BitmapBlock| {
+                    bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+                });
+                return Some(block_id * BLOCK_BITS + bits64_pos * 64 + inner_pos as usize);
             }
         }
         None
@@ -70,6 +72,30 @@ impl Bitmap {
             bitmap_block[bits64_pos] -= 1u64 << inner_pos;
         });
     }
+    /// Whether a bit is allocated
+    pub fn is_allocated(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) -> bool {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(
+            block_pos + self.start_block_id,
+            Arc::clone(block_device)
+        ).lock().read(0, |bitmap_block: &BitmapBlock| {
+            bitmap_block[bits64_pos] & (1u64 << inner_pos) > 0
+        })
+    }
+    /// Mark a bit allocated or free, whatever it was
+    pub fn set_allocated(&self, block_device: &Arc<dyn BlockDevice>, bit: usize, allocated: bool) {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(
+            block_pos + self.start_block_id,
+            Arc::clone(block_device)
+        ).lock().modify(0, |bitmap_block: &mut BitmapBlock| {
+            if allocated {
+                bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+            } else {
+                bitmap_block[bits64_pos] &= !(1u64 << inner_pos);
+            }
+        });
+    }
     /// Get the max number of allocatable blocks
     pub fn maximum(&self) -> usize {
         self.blocks * BLOCK_BITS
@@ -2,8 +2,8 @@ use super::{
     BLOCK_SZ,
     BlockDevice,
 };
-use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 use spin::Mutex;
 
@@ -76,16 +76,123 @@ impl Drop for BlockCache {
     }
 }
 
-/// Use a block cache of 16 blocks
+/// Use a block cache of 16 blocks unless configured otherwise
 const BLOCK_CACHE_SIZE: usize = 16;
 
+/// Identify a block device by the address it lives at, which cannot be
+/// reused while a cached block keeps the device alive
+fn device_id(block_device: &Arc<dyn BlockDevice>) -> usize {
+    Arc::as_ptr(block_device) as *const () as usize
+}
+
+/// A cached block in the clock of the manager
+struct Slot {
+    block_id: usize,
+    device: usize,
+    cache: Arc<Mutex<BlockCache>>,
+    /// Whether the block was used since the clock hand last passed it
+    referenced: bool,
+}
+
+/// Cache of blocks, evicting with the CLOCK algorithm and finding blocks
+/// through a hash index
 pub struct BlockCacheManager {
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    /// Number of blocks to keep, exceeded only while every block is in use
+    capacity: usize,
+    /// Cached blocks, which keep their position until evicted
+    slots: Vec<Slot>,
+    /// Next slot to consider for eviction
+    hand: usize,
+    /// Positions in `slots`, bucketed by the hash of block and device
+    index: Vec<Vec<usize>>,
 }
 
 impl BlockCacheManager {
     pub fn new() -> Self {
-        Self { queue: VecDeque::new() }
+        let mut manager = Self {
+            capacity: 0,
+            slots: Vec::new(),
+            hand: 0,
+            index: Vec::new(),
+        };
+        manager.set_capacity(BLOCK_CACHE_SIZE);
+        manager
+    }
+
+    /// Change the number of blocks to keep, evicting blocks beyond it
+    /// that are not in use
+    pub fn set_capacity(&mut self, capacity: usize) {
+        assert!(capacity > 0);
+        self.capacity = capacity;
+        self.hand = 0;
+        self.index = (0..capacity * 2).map(|_| Vec::new()).collect();
+        for (pos, slot) in self.slots.iter().enumerate() {
+            let bucket = Self::bucket(self.index.len(), slot.block_id, slot.device);
+            self.index[bucket].push(pos);
+        }
+        self.trim(capacity);
+    }
+
+    /// Evict blocks not in use until at most `len` are left
+    fn trim(&mut self, len: usize) {
+        let mut pos = 0;
+        while self.slots.len() > len && pos < self.slots.len() {
+            if self.is_evictable(pos) {
+                self.remove_slot(pos);
+            } else {
+                pos += 1;
+            }
+        }
+    }
+
+    /// Remove a slot, moving the last one into its place
+    fn remove_slot(&mut self, pos: usize) {
+        let slot = &self.slots[pos];
+        let bucket = Self::bucket(self.index.len(), slot.block_id, slot.device);
+        self.index[bucket].retain(|&other| other != pos);
+        let last = self.slots.len() - 1;
+        if pos != last {
+            let moved = &self.slots[last];
+            let bucket = Self::bucket(self.index.len(), moved.block_id, moved.device);
+            for other in self.index[bucket].iter_mut().filter(|other| **other == last) {
+                *other = pos;
+            }
+        }
+        // a modified block is written back as it is dropped
+        self.slots.swap_remove(pos);
+        if self.hand >= self.slots.len() {
+            self.hand = 0;
+        }
+    }
+
+    /// Bucket of the index for a block of a device
+    fn bucket(buckets: usize, block_id: usize, device: usize) -> usize {
+        (block_id ^ device.rotate_left(16)).wrapping_mul(0x9e37_79b9) % buckets
+    }
+
+    /// Whether a slot can be evicted: no one else holds its block, and it
+    /// is not waiting for a transaction of its device to commit
+    fn is_evictable(&self, pos: usize) -> bool {
+        let slot = &self.slots[pos];
+        Arc::strong_count(&slot.cache) == 1
+            && !(slot.cache.lock().modified && in_transaction(slot.device))
+    }
+
+    /// Find a slot to evict, sweeping the clock at most twice
+    fn find_victim(&mut self) -> Option<usize> {
+        for _ in 0..self.slots.len() * 2 {
+            let pos = self.hand;
+            self.hand = (self.hand + 1) % self.slots.len();
+            if !self.is_evictable(pos) {
+                continue;
+            }
+            if self.slots[pos].referenced {
+                self.slots[pos].referenced = false;
+            } else {
+                return Some(pos);
+            }
+        }
+        None
     }
 
     pub fn get_block_cache(
@@ -93,30 +200,55 @@ impl BlockCacheManager {
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
     ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue
+        let device = device_id(&block_device);
+        let bucket = Self::bucket(self.index.len(), block_id, device);
+        let slots = &mut self.slots;
+        if let Some(&pos) = self.index[bucket]
             .iter()
-            .find(|pair| pair.0 == block_id) {
-                Arc::clone(&pair.1)
+            .find(|&&pos| slots[pos].block_id == block_id && slots[pos].device == device) {
+            slots[pos].referenced = true;
+            return Arc::clone(&slots[pos].cache);
+        }
+        // load block into mem
+        let block_cache = Arc::new(Mutex::new(
+            BlockCache::new(block_id, Arc::clone(&block_device))
+        ));
+        let slot = Slot {
+            block_id,
+            device,
+            cache: Arc::clone(&block_cache),
+            referenced: true,
+        };
+        // give back the blocks taken while every block was in use
+        self.trim(self.capacity);
+        // substitute, or grow while every block is in use
+        let victim = if self.slots.len() < self.capacity {
+            None
         } else {
-            // substitute
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                // from front to tail
-                if let Some((idx, _)) = self.queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1) {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
-                }
+            self.find_victim()
+        };
+        let pos = match victim {
+            Some(pos) => {
+                let old = &self.slots[pos];
+                let old_bucket = Self::bucket(self.index.len(), old.block_id, old.device);
+                self.index[old_bucket].retain(|&other| other != pos);
+                // a modified block is written back as it is dropped
+                self.slots[pos] = slot;
+                pos
             }
-            // load block into mem and push back
-            let block_cache = Arc::new(Mutex::new(
-                BlockCache::new(block_id, Arc::clone(&block_device))
-            ));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
-            block_cache
-        }
+            None => {
+                self.slots.push(slot);
+                self.slots.len() - 1
+            }
+        };
+        self.index[bucket].push(pos);
+        block_cache
+    }
+}
+
+impl Default for BlockCacheManager {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -125,6 +257,28 @@ lazy_static! {
     pub static ref BLOCK_CACHE_MANAGER: Mutex<BlockCacheManager> = Mutex::new(
         BlockCacheManager::new()
     );
+    /// Devices with an open transaction, whose modified blocks stay in the
+    /// cache until it commits. Only checked with the block locked, so a
+    /// block is never written back in the middle of a transaction.
+    static ref TRANSACTIONS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+}
+
+/// Whether a device has an open transaction
+fn in_transaction(device: usize) -> bool {
+    TRANSACTIONS.lock().contains(&device)
+}
+
+/// Cached blocks of a device, or of every device if `None`, with their
+/// block ids and devices. The manager is released before the blocks are
+/// locked, as whoever holds a block may be waiting for the manager.
+fn cached_blocks(device: Option<usize>) -> Vec<(usize, usize, Arc<Mutex<BlockCache>>)> {
+    BLOCK_CACHE_MANAGER
+        .lock()
+        .slots
+        .iter()
+        .filter(|slot| device.is_none() || device == Some(slot.device))
+        .map(|slot| (slot.block_id, slot.device, Arc::clone(&slot.cache)))
+        .collect()
 }
 
 /// Get the block cache corresponding to the given block id and block device
@@ -135,10 +289,53 @@ pub fn get_block_cache(
     BLOCK_CACHE_MANAGER.lock().get_block_cache(block_id, block_device)
 }
 
-/// Sync all block cache to block device
+/// Set the number of blocks the block cache keeps
+pub fn set_block_cache_capacity(capacity: usize) {
+    BLOCK_CACHE_MANAGER.lock().set_capacity(capacity);
+}
+
+/// Sync all modified block cache to block device, except blocks of an
+/// open transaction, which are written back as it ends
 pub fn block_cache_sync_all() {
-    let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, cache) in manager.queue.iter() {
+    for (_, device, cache) in cached_blocks(None) {
+        let mut cache = cache.lock();
+        if cache.modified && !in_transaction(device) {
+            cache.sync();
+        }
+    }
+}
+
+/// Write back all modified blocks of a device, then keep its blocks
+/// modified from now on in the cache until the transaction ends
+pub fn block_cache_begin_transaction(block_device: &Arc<dyn BlockDevice>) {
+    let device = device_id(block_device);
+    assert!(!in_transaction(device), "Nested transaction!");
+    for (_, _, cache) in cached_blocks(Some(device)) {
+        cache.lock().sync();
+    }
+    TRANSACTIONS.lock().push(device);
+}
+
+/// Blocks of a device modified in its current transaction, by block id
+pub fn block_cache_transaction_blocks(
+    block_device: &Arc<dyn BlockDevice>,
+) -> Vec<(usize, Arc<Mutex<BlockCache>>)> {
+    let device = device_id(block_device);
+    assert!(in_transaction(device));
+    let mut blocks: Vec<_> = cached_blocks(Some(device))
+        .into_iter()
+        .filter(|(_, _, cache)| cache.lock().modified)
+        .map(|(block_id, _, cache)| (block_id, cache))
+        .collect();
+    blocks.sort_by_key(|(block_id, _)| *block_id);
+    blocks
+}
+
+/// Write back the blocks of the current transaction of a device and end it
+pub fn block_cache_end_transaction(block_device: &Arc<dyn BlockDevice>) {
+    let device = device_id(block_device);
+    for (_, _, cache) in cached_blocks(Some(device)) {
         cache.lock().sync();
     }
+    TRANSACTIONS.lock().retain(|&other| other != device);
 }
@@ -1,14 +1,25 @@
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use spin::Mutex;
 use super::{
     BlockDevice,
     Bitmap,
     SuperBlock,
+    FsLayout,
+    FormatError,
+    JournalHeader,
     DiskInode,
     DiskInodeType,
     Inode,
+    TimeSpec,
+    JOURNAL_BLOCKS,
+    JOURNAL_CAPACITY,
     get_block_cache,
     block_cache_sync_all,
+    block_cache_begin_transaction,
+    block_cache_transaction_blocks,
+    block_cache_end_transaction,
 };
 use crate::BLOCK_SZ;
 
@@ -17,8 +28,21 @@ pub struct EasyFileSystem {
     pub block_device: Arc<dyn BlockDevice>,
     pub inode_bitmap: Bitmap,
     pub data_bitmap: Bitmap,
+    journal_start_block: u32,
     inode_area_start_block: u32,
     data_area_start_block: u32,
+    /// The largest size a file can reach, as the features allow
+    max_file_size: u32,
+    /// The longest name a directory entry can hold
+    name_length_limit: usize,
+    /// Number of vfs inodes handed out for each inode, which keep an
+    /// unlinked inode from being freed
+    open_handles: BTreeMap<u32, usize>,
+    /// Source of the current time for inode timestamps
+    clock: fn() -> TimeSpec,
+    /// Data blocks freed in the current transaction, to be cleared once
+    /// it commits
+    freed_blocks: Vec<u32>,
 }
 
 /// A data block of block size
@@ -30,26 +54,48 @@ impl EasyFileSystem {
         block_device: Arc<dyn BlockDevice>,
         total_blocks: u32,
         inode_bitmap_blocks: u32,
+    ) -> Arc<Mutex<Self>> {
+        Self::create_with_features(block_device, total_blocks, inode_bitmap_blocks, 0)
+    }
+    /// Create a filesystem from a block device, with the given feature
+    /// flags, such as `FEATURE_INDIRECT3`
+    pub fn create_with_features(
+        block_device: Arc<dyn BlockDevice>,
+        total_blocks: u32,
+        inode_bitmap_blocks: u32,
+        features: u32,
     ) -> Arc<Mutex<Self>> {
         // calculate block size of areas & create bitmaps
-        let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks as usize);
+        // the journal follows the super block
+        let inode_bitmap_start_block = 1 + JOURNAL_BLOCKS;
+        let inode_bitmap = Bitmap::new(
+            inode_bitmap_start_block as usize,
+            inode_bitmap_blocks as usize,
+        );
         let inode_num = inode_bitmap.maximum();
         let inode_area_blocks =
             ((inode_num * core::mem::size_of::<DiskInode>() + BLOCK_SZ - 1) / BLOCK_SZ) as u32;
         let inode_total_blocks = inode_bitmap_blocks + inode_area_blocks;
-        let data_total_blocks = total_blocks - 1 - inode_total_blocks;
+        let data_total_blocks = total_blocks - inode_bitmap_start_block - inode_total_blocks;
         let data_bitmap_blocks = (data_total_blocks + 4096) / 4097;
         let data_area_blocks = data_total_blocks - data_bitmap_blocks;
         let data_bitmap = Bitmap::new(
-            (1 + inode_bitmap_blocks + inode_area_blocks) as usize,
+            (inode_bitmap_start_block + inode_total_blocks) as usize,
             data_bitmap_blocks as usize,
         );
         let mut efs = Self {
             block_device: Arc::clone(&block_device),
             inode_bitmap,
             data_bitmap,
-            inode_area_start_block: 1 + inode_bitmap_blocks,
-            data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            journal_start_block: 1,
+            inode_area_start_block: inode_bitmap_start_block + inode_bitmap_blocks,
+            data_area_start_block:
+                inode_bitmap_start_block + inode_total_blocks + data_bitmap_blocks,
+            max_file_size: 0,
+            name_length_limit: 0,
+            open_handles: BTreeMap::new(),
+            clock: TimeSpec::default,
+            freed_blocks: Vec::new(),
         };
         // clear all blocks
         for i in 0..total_blocks {
@@ -63,17 +109,22 @@ impl EasyFileSystem {
             });
         }
         // initialize SuperBlock
-        get_block_cache(0, Arc::clone(&block_device))
+        let (max_file_size, name_length_limit) = get_block_cache(0, Arc::clone(&block_device))
         .lock()
         .modify(0, |super_block: &mut SuperBlock| {
-            super_block.initialize(
+            super_block.initialize(FsLayout {
                 total_blocks,
+                journal_blocks: JOURNAL_BLOCKS,
                 inode_bitmap_blocks,
                 inode_area_blocks,
                 data_bitmap_blocks,
                 data_area_blocks,
-            );
+                features,
+            });
+            (super_block.max_file_size(), super_block.name_length_limit())
         });
+        efs.max_file_size = max_file_size;
+        efs.name_length_limit = name_length_limit;
         // write back immediately
         // create a inode for root node "/"
         assert_eq!(efs.alloc_inode(), 0);
@@ -84,48 +135,192 @@ impl EasyFileSystem {
         )
         .lock()
         .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
-            disk_inode.initialize(DiskInodeType::Directory);
+            disk_inode.initialize(DiskInodeType::Directory, TimeSpec::default());
         });
+        let efs = Arc::new(Mutex::new(efs));
+        // the root directory is its own parent
+        let root_inode = Self::root_inode(&efs);
+        root_inode.add_dir_entries(0, &mut efs.lock());
+        drop(root_inode);
         block_cache_sync_all();
-        Arc::new(Mutex::new(efs))
+        efs
     }
-    /// Open a block device as a filesystem
-    pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
+    /// Open a block device as a filesystem, replaying a transaction
+    /// committed to the journal but not yet in place. Images made before
+    /// the journal are refused rather than misread, and have to be packed
+    /// again.
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> Result<Arc<Mutex<Self>>, FormatError> {
         // read SuperBlock
-        get_block_cache(0, Arc::clone(&block_device))
+        let efs = get_block_cache(0, Arc::clone(&block_device))
             .lock()
             .read(0, |super_block: &SuperBlock| {
-                assert!(super_block.is_valid(), "Error loading EFS!");
+                super_block.check()?;
+                let inode_bitmap_start_block = 1 + super_block.journal_blocks;
                 let inode_total_blocks =
                     super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
-                let efs = Self {
+                Ok(Self {
                     block_device,
                     inode_bitmap: Bitmap::new(
-                        1,
+                        inode_bitmap_start_block as usize,
                         super_block.inode_bitmap_blocks as usize
                     ),
                     data_bitmap: Bitmap::new(
-                        (1 + inode_total_blocks) as usize,
+                        (inode_bitmap_start_block + inode_total_blocks) as usize,
                         super_block.data_bitmap_blocks as usize,
                     ),
-                    inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
-                    data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
-                };
-                Arc::new(Mutex::new(efs))
-            })
+                    journal_start_block: 1,
+                    inode_area_start_block:
+                        inode_bitmap_start_block + super_block.inode_bitmap_blocks,
+                    data_area_start_block: inode_bitmap_start_block
+                        + inode_total_blocks
+                        + super_block.data_bitmap_blocks,
+                    max_file_size: super_block.max_file_size(),
+                    name_length_limit: super_block.name_length_limit(),
+                    open_handles: BTreeMap::new(),
+                    clock: TimeSpec::default,
+                    freed_blocks: Vec::new(),
+                })
+            })?;
+        efs.replay_journal();
+        Ok(Arc::new(Mutex::new(efs)))
+    }
+    /// Copy the blocks of a committed transaction from the journal to
+    /// their places, then empty the journal. Replaying twice is harmless,
+    /// so a crash in here is too.
+    fn replay_journal(&self) {
+        let header = get_block_cache(
+            self.journal_start_block as usize,
+            Arc::clone(&self.block_device),
+        );
+        let block_ids = header
+            .lock()
+            .read(0, |header: &JournalHeader| header.committed().to_vec());
+        if block_ids.is_empty() {
+            return;
+        }
+        let mut logged_block = [0u8; BLOCK_SZ];
+        for (i, &block_id) in block_ids.iter().enumerate() {
+            self.block_device
+                .read_block(self.journal_start_block as usize + 1 + i, &mut logged_block);
+            get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| {
+                    data_block.copy_from_slice(&logged_block);
+                });
+        }
+        block_cache_sync_all();
+        let mut header = header.lock();
+        header.modify(0, |header: &mut JournalHeader| header.clear());
+        header.sync();
+    }
+    /// Start a transaction: the blocks it modifies reach the disk all
+    /// together on `commit`, or not at all
+    pub fn begin(&mut self) {
+        block_cache_begin_transaction(&self.block_device);
+    }
+    /// Commit the current transaction. Its blocks are logged in the
+    /// journal, which the header then marks complete, before they are
+    /// written in place.
+    pub fn commit(&mut self) {
+        let blocks = block_cache_transaction_blocks(&self.block_device);
+        // a single block is written in place at once anyway
+        let logged = blocks.len() > 1;
+        if logged {
+            assert!(blocks.len() <= JOURNAL_CAPACITY, "Transaction too large for the journal!");
+            for (i, (_, cache)) in blocks.iter().enumerate() {
+                cache.lock().read(0, |data_block: &DataBlock| {
+                    self.block_device.write_block(
+                        (self.journal_start_block + 1) as usize + i,
+                        data_block,
+                    );
+                });
+            }
+            let block_ids: Vec<u32> = blocks
+                .iter()
+                .map(|&(block_id, _)| block_id as u32)
+                .collect();
+            self.modify_journal_header(|header| header.commit(&block_ids));
+        }
+        drop(blocks);
+        block_cache_end_transaction(&self.block_device);
+        if logged {
+            self.modify_journal_header(|header| header.clear());
+        }
+        // freed blocks are only cleared once nothing on disk refers to them
+        for block_id in core::mem::take(&mut self.freed_blocks) {
+            get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| {
+                    data_block.iter_mut().for_each(|p| { *p = 0; })
+                });
+        }
+        block_cache_sync_all();
+    }
+    /// Whether the open transaction can modify `blocks` more blocks and
+    /// still fit in the journal
+    pub fn transaction_fits(&self, blocks: usize) -> bool {
+        block_cache_transaction_blocks(&self.block_device).len() + blocks <= JOURNAL_CAPACITY
+    }
+    /// Update the journal header and write it to disk immediately
+    fn modify_journal_header(&self, f: impl FnOnce(&mut JournalHeader)) {
+        let header = get_block_cache(
+            self.journal_start_block as usize,
+            Arc::clone(&self.block_device),
+        );
+        let mut header = header.lock();
+        header.modify(0, f);
+        header.sync();
     }
     /// Get the root inode of the filesystem
     pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
         let block_device = Arc::clone(&efs.lock().block_device);
         // acquire efs lock temporarily
         let (block_id, block_offset) = efs.lock().get_disk_inode_pos(0);
+        efs.lock().open_handle(0);
         // release efs lock
         Inode::new(
+            0,
             block_id,
             block_offset,
             Arc::clone(efs),
             block_device,
         )
+        .into_handle()
+    }
+    /// Set the source of the current time, which reads as the epoch until
+    /// one is given
+    pub fn set_clock(&mut self, clock: fn() -> TimeSpec) {
+        self.clock = clock;
+    }
+    /// The current time
+    pub fn now(&self) -> TimeSpec {
+        (self.clock)()
+    }
+    /// The largest size a file can reach
+    pub fn max_file_size(&self) -> u32 {
+        self.max_file_size
+    }
+    /// The longest name a directory entry can hold
+    pub fn name_length_limit(&self) -> usize {
+        self.name_length_limit
+    }
+    /// Count a new vfs inode handed out for an inode
+    pub fn open_handle(&mut self, inode_id: u32) {
+        *self.open_handles.entry(inode_id).or_insert(0) += 1;
+    }
+    /// Drop a vfs inode of an inode, returning how many remain
+    pub fn close_handle(&mut self, inode_id: u32) -> usize {
+        let count = self.open_handles.get_mut(&inode_id).unwrap();
+        *count -= 1;
+        let remaining = *count;
+        if remaining == 0 {
+            self.open_handles.remove(&inode_id);
+        }
+        remaining
+    }
+    /// Whether any vfs inode of an inode is still around
+    pub fn is_open(&self, inode_id: u32) -> bool {
+        self.open_handles.contains_key(&inode_id)
     }
     /// Get inode by id
     pub fn get_disk_inode_pos(&self, inode_id: u32) -> (u32, usize) {
@@ -142,20 +337,18 @@ impl EasyFileSystem {
     pub fn alloc_inode(&mut self) -> u32 {
         self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
     }
+    /// Deallocate an inode
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        self.inode_bitmap.dealloc(&self.block_device, inode_id as usize)
+    }
     /// Allocate a data block
     pub fn alloc_data(&mut self) -> u32 {
         self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
     }
-    /// Deallocate a data block
+    /// Deallocate a data block, which is cleared when the current
+    /// transaction commits
     pub fn dealloc_data(&mut self, block_id: u32) {
-        get_block_cache(
-            block_id as usize,
-            Arc::clone(&self.block_device)
-        )
-        .lock()
-        .modify(0, |data_block: &mut DataBlock| {
-            data_block.iter_mut().for_each(|p| { *p = 0; })
-        });
+        self.freed_blocks.push(block_id);
         self.data_bitmap.dealloc(
             &self.block_device,
             (block_id - self.data_area_start_block) as usize
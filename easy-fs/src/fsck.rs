@@ -0,0 +1,413 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+use core::ops::Range;
+use super::{
+    BlockDevice,
+    Bitmap,
+    DirEntry,
+    DiskInode,
+    EasyFileSystem,
+    JournalHeader,
+    SuperBlock,
+    BLOCK_SZ,
+    DIRENT_SZ,
+    get_block_cache,
+    block_cache_sync_all,
+};
+
+/// A problem found in a filesystem by `fsck`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The super block is not one of easy-fs, nothing else is checked
+    BadSuperBlock,
+    /// The root inode is not a directory, nothing else is checked
+    BadRoot,
+    /// The journal holds a committed transaction not yet in place
+    UnreplayedJournal,
+    /// The size is beyond what the inode can address, a directory's is
+    /// not a whole number of entries, or pointers are set beyond it
+    SizeMismatch { inode_id: u32 },
+    /// A block pointer outside the data area
+    BlockOutOfRange { inode_id: u32, block_id: u32 },
+    /// A block already in use by this or another inode
+    BlockClaimedTwice { inode_id: u32, block_id: u32 },
+    /// An entry with a bad name, inode number, or a second name for a
+    /// directory
+    BadDirEntry { dir_id: u32, slot: usize },
+    /// The `.` or `..` of a directory missing
+    MissingDotEntry { dir_id: u32 },
+    /// The link count differs from the entries naming the inode
+    LinkCountMismatch { inode_id: u32, stored: u32, actual: u32 },
+    /// A reachable inode free in the inode bitmap
+    InodeNotAllocated { inode_id: u32 },
+    /// An allocated inode not reachable from the root
+    InodeLeaked { inode_id: u32 },
+    /// A block in use free in the data bitmap
+    BlockNotAllocated { block_id: u32 },
+    /// An allocated block not in use
+    BlockLeaked { block_id: u32 },
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadSuperBlock => write!(f, "bad super block"),
+            Self::BadRoot => write!(f, "root inode is not a directory"),
+            Self::UnreplayedJournal => write!(f, "journal holds a committed transaction"),
+            Self::SizeMismatch { inode_id } => {
+                write!(f, "inode {}: size does not match its blocks", inode_id)
+            }
+            Self::BlockOutOfRange { inode_id, block_id } => {
+                write!(f, "inode {}: block {} outside the data area", inode_id, block_id)
+            }
+            Self::BlockClaimedTwice { inode_id, block_id } => {
+                write!(f, "inode {}: block {} already in use", inode_id, block_id)
+            }
+            Self::BadDirEntry { dir_id, slot } => {
+                write!(f, "directory {}: bad entry {}", dir_id, slot)
+            }
+            Self::MissingDotEntry { dir_id } => {
+                write!(f, "directory {}: missing `.` or `..`", dir_id)
+            }
+            Self::LinkCountMismatch { inode_id, stored, actual } => write!(
+                f,
+                "inode {}: link count {} instead of {}",
+                inode_id, stored, actual
+            ),
+            Self::InodeNotAllocated { inode_id } => {
+                write!(f, "inode {}: in use but free in the bitmap", inode_id)
+            }
+            Self::InodeLeaked { inode_id } => {
+                write!(f, "inode {}: allocated but unreachable", inode_id)
+            }
+            Self::BlockNotAllocated { block_id } => {
+                write!(f, "block {}: in use but free in the bitmap", block_id)
+            }
+            Self::BlockLeaked { block_id } => {
+                write!(f, "block {}: allocated but unused", block_id)
+            }
+        }
+    }
+}
+
+/// Outcome of `fsck`
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    /// Problems found, all fixed if repairing except missing `.` or `..`
+    pub problems: Vec<Problem>,
+    /// Number of inodes reachable from the root
+    pub inodes: usize,
+    /// Number of blocks in use by them
+    pub blocks: usize,
+}
+
+/// Check a filesystem by walking every inode reachable from the root, and
+/// repair what is found if `repair`. Inodes lose the blocks from their
+/// first bad one on, bad entries are cleared, and the link counts and
+/// bitmaps are rebuilt from what is reachable.
+pub fn fsck(block_device: Arc<dyn BlockDevice>, repair: bool) -> FsckReport {
+    let mut checker = match Checker::new(block_device, repair) {
+        Some(checker) => checker,
+        None => {
+            return FsckReport {
+                problems: alloc::vec![Problem::BadSuperBlock],
+                ..FsckReport::default()
+            };
+        }
+    };
+    checker.check_journal();
+    if checker.check_tree() {
+        checker.check_link_counts();
+        checker.check_bitmaps();
+    }
+    if repair {
+        block_cache_sync_all();
+    }
+    FsckReport {
+        inodes: checker.links.len(),
+        blocks: checker.owners.len(),
+        problems: checker.problems,
+    }
+}
+
+/// State of a check over a filesystem
+struct Checker {
+    block_device: Arc<dyn BlockDevice>,
+    repair: bool,
+    inode_bitmap: Bitmap,
+    data_bitmap: Bitmap,
+    journal_start_block: u32,
+    inode_area_start_block: u32,
+    inode_count: u32,
+    data_area_start_block: u32,
+    data_area_blocks: u32,
+    max_file_size: u32,
+    name_length_limit: usize,
+    problems: Vec<Problem>,
+    /// The inode using each block in use
+    owners: BTreeMap<u32, u32>,
+    /// Number of entries naming each reachable inode
+    links: BTreeMap<u32, u32>,
+}
+
+impl Checker {
+    /// Lay out the filesystem as told by its super block
+    fn new(block_device: Arc<dyn BlockDevice>, repair: bool) -> Option<Self> {
+        get_block_cache(0, Arc::clone(&block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| {
+                if !super_block.is_valid() {
+                    return None;
+                }
+                let inode_bitmap_start_block = 1 + super_block.journal_blocks;
+                let inode_area_start_block =
+                    inode_bitmap_start_block + super_block.inode_bitmap_blocks;
+                let data_bitmap_start_block =
+                    inode_area_start_block + super_block.inode_area_blocks;
+                let inodes_per_block = (BLOCK_SZ / core::mem::size_of::<DiskInode>()) as u32;
+                let inode_bitmap = Bitmap::new(
+                    inode_bitmap_start_block as usize,
+                    super_block.inode_bitmap_blocks as usize,
+                );
+                let inode_count = (inode_bitmap.maximum() as u32)
+                    .min(super_block.inode_area_blocks * inodes_per_block);
+                Some(Self {
+                    block_device: Arc::clone(&block_device),
+                    repair,
+                    inode_bitmap,
+                    data_bitmap: Bitmap::new(
+                        data_bitmap_start_block as usize,
+                        super_block.data_bitmap_blocks as usize,
+                    ),
+                    journal_start_block: 1,
+                    inode_area_start_block,
+                    inode_count,
+                    data_area_start_block: data_bitmap_start_block
+                        + super_block.data_bitmap_blocks,
+                    data_area_blocks: super_block.data_area_blocks,
+                    max_file_size: super_block.max_file_size(),
+                    name_length_limit: super_block.name_length_limit(),
+                    problems: Vec::new(),
+                    owners: BTreeMap::new(),
+                    links: BTreeMap::new(),
+                })
+            })
+    }
+    /// Call a function over a disk inode to read it
+    fn read_inode<V>(&self, inode_id: u32, f: impl FnOnce(&DiskInode) -> V) -> V {
+        let (block_id, offset) = self.disk_inode_pos(inode_id);
+        get_block_cache(block_id, Arc::clone(&self.block_device))
+            .lock()
+            .read(offset, f)
+    }
+    /// Call a function over a disk inode to modify it
+    fn modify_inode<V>(&self, inode_id: u32, f: impl FnOnce(&mut DiskInode) -> V) -> V {
+        let (block_id, offset) = self.disk_inode_pos(inode_id);
+        get_block_cache(block_id, Arc::clone(&self.block_device))
+            .lock()
+            .modify(offset, f)
+    }
+    /// Block ids of the data area
+    fn data_area(&self) -> Range<u32> {
+        self.data_area_start_block..self.data_area_start_block + self.data_area_blocks
+    }
+    fn disk_inode_pos(&self, inode_id: u32) -> (usize, usize) {
+        let inode_size = core::mem::size_of::<DiskInode>();
+        let inodes_per_block = (BLOCK_SZ / inode_size) as u32;
+        let block_id = self.inode_area_start_block + inode_id / inodes_per_block;
+        (block_id as usize, (inode_id % inodes_per_block) as usize * inode_size)
+    }
+    /// Replay a committed transaction left in the journal if repairing
+    fn check_journal(&mut self) {
+        let committed = get_block_cache(
+            self.journal_start_block as usize,
+            Arc::clone(&self.block_device),
+        )
+        .lock()
+        .read(0, |header: &JournalHeader| !header.committed().is_empty());
+        if committed {
+            self.problems.push(Problem::UnreplayedJournal);
+            if self.repair {
+                // the super block was checked before
+                EasyFileSystem::open(Arc::clone(&self.block_device)).unwrap();
+            }
+        }
+    }
+    /// Walk the directory tree from the root, returning false if there is
+    /// no root directory to walk
+    fn check_tree(&mut self) -> bool {
+        if !self.read_inode(0, |disk_inode| disk_inode.is_dir()) {
+            self.problems.push(Problem::BadRoot);
+            return false;
+        }
+        self.links.insert(0, 0);
+        self.check_blocks(0);
+        // directories with their parents
+        let mut dirs = VecDeque::new();
+        dirs.push_back((0, 0));
+        while let Some((dir_id, parent_id)) = dirs.pop_front() {
+            self.check_dir(dir_id, parent_id, &mut dirs);
+        }
+        true
+    }
+    /// Claim the blocks of an inode, cutting it short at the first block
+    /// outside the data area or already claimed
+    fn check_blocks(&mut self, inode_id: u32) {
+        let disk_inode = self.read_inode(inode_id, |disk_inode| *disk_inode);
+        let size_mismatch = !disk_inode.size_is_valid(self.max_file_size)
+            || disk_inode.has_stale_pointers()
+            || (disk_inode.is_dir() && disk_inode.size as usize % DIRENT_SZ != 0);
+        if size_mismatch {
+            self.problems.push(Problem::SizeMismatch { inode_id });
+        }
+        let data_area = self.data_area();
+        let owners = &mut self.owners;
+        let mut bad_block = None;
+        let visited = disk_inode.walk_blocks(&self.block_device, |block_id| {
+            if !data_area.contains(&block_id) {
+                bad_block = Some(Problem::BlockOutOfRange { inode_id, block_id });
+                return false;
+            }
+            if owners.contains_key(&block_id) {
+                bad_block = Some(Problem::BlockClaimedTwice { inode_id, block_id });
+                return false;
+            }
+            owners.insert(block_id, inode_id);
+            true
+        });
+        if bad_block.is_none() && !size_mismatch {
+            return;
+        }
+        self.problems.extend(bad_block);
+        if self.repair {
+            self.modify_inode(inode_id, |disk_inode| {
+                disk_inode.truncate_blocks(visited);
+                if disk_inode.is_dir() {
+                    disk_inode.size -= disk_inode.size % DIRENT_SZ as u32;
+                }
+            });
+        }
+    }
+    /// Check the entries of a directory, and walk the inodes they name
+    fn check_dir(&mut self, dir_id: u32, parent_id: u32, dirs: &mut VecDeque<(u32, u32)>) {
+        let disk_inode = self.read_inode(dir_id, |disk_inode| *disk_inode);
+        // only entries within blocks that can be read
+        let data_area = self.data_area();
+        let readable_blocks = disk_inode
+            .walk_blocks(&self.block_device, |block_id| data_area.contains(&block_id));
+        let size = (disk_inode.size as usize).min(readable_blocks as usize * BLOCK_SZ);
+        let (mut has_dot, mut has_dot_dot) = (false, false);
+        let mut slot = 0;
+        while slot < size / DIRENT_SZ {
+            let (dirent, name) = disk_inode.read_dirent(slot, &self.block_device);
+            if dirent.is_free() {
+                slot += 1;
+                continue;
+            }
+            let inode_id = dirent.inode_number();
+            let name_is_valid = dirent.name_is_valid(&name)
+                && name.len() <= self.name_length_limit
+                && slot + dirent.slots() <= size / DIRENT_SZ;
+            let fixed_entry = if !name_is_valid {
+                Some(DirEntry::empty())
+            } else if name == b"." || name == b".." {
+                let expected = if name == b"." {
+                    has_dot = true;
+                    dir_id
+                } else {
+                    has_dot_dot = true;
+                    parent_id
+                };
+                *self.links.get_mut(&expected).unwrap() += 1;
+                if inode_id == expected {
+                    None
+                } else {
+                    Some(DirEntry::new(core::str::from_utf8(&name).unwrap(), expected))
+                }
+            } else if inode_id >= self.inode_count
+                || (self.links.contains_key(&inode_id)
+                    && self.read_inode(inode_id, |disk_inode| disk_inode.is_dir()))
+            {
+                // no such inode, or another name of a directory, which has
+                // a single name
+                Some(DirEntry::empty())
+            } else if let Some(links) = self.links.get_mut(&inode_id) {
+                *links += 1;
+                None
+            } else {
+                self.links.insert(inode_id, 1);
+                self.check_blocks(inode_id);
+                if self.read_inode(inode_id, |disk_inode| disk_inode.is_dir()) {
+                    dirs.push_back((inode_id, dir_id));
+                }
+                None
+            };
+            // the rest of a bad long name is checked entry by entry
+            let slots = if name_is_valid { dirent.slots() } else { 1 };
+            if let Some(fixed_entry) = fixed_entry {
+                self.problems.push(Problem::BadDirEntry { dir_id, slot });
+                if self.repair {
+                    // a removed entry is cleared with the rest of its name
+                    let bytes = if fixed_entry.is_free() {
+                        alloc::vec![0u8; slots * DIRENT_SZ]
+                    } else {
+                        Vec::from(fixed_entry.as_bytes())
+                    };
+                    self.modify_inode(dir_id, |dir_inode| {
+                        dir_inode.write_at(slot * DIRENT_SZ, &bytes, &self.block_device);
+                    });
+                }
+            }
+            slot += slots;
+        }
+        if !(has_dot && has_dot_dot) {
+            self.problems.push(Problem::MissingDotEntry { dir_id });
+        }
+    }
+    /// Compare the link counts with the entries found
+    fn check_link_counts(&mut self) {
+        for (&inode_id, &actual) in self.links.iter() {
+            let stored = self.read_inode(inode_id, |disk_inode| disk_inode.nlink);
+            if stored != actual {
+                self.problems.push(Problem::LinkCountMismatch { inode_id, stored, actual });
+                if self.repair {
+                    self.modify_inode(inode_id, |disk_inode| disk_inode.nlink = actual);
+                }
+            }
+        }
+    }
+    /// Compare the bitmaps with the inodes and blocks in use
+    fn check_bitmaps(&mut self) {
+        for inode_id in 0..self.inode_count {
+            let in_use = self.links.contains_key(&inode_id);
+            if self.inode_bitmap.is_allocated(&self.block_device, inode_id as usize) == in_use {
+                continue;
+            }
+            self.problems.push(if in_use {
+                Problem::InodeNotAllocated { inode_id }
+            } else {
+                Problem::InodeLeaked { inode_id }
+            });
+            if self.repair {
+                self.inode_bitmap.set_allocated(&self.block_device, inode_id as usize, in_use);
+            }
+        }
+        for bit in 0..self.data_area_blocks {
+            let block_id = self.data_area_start_block + bit;
+            let in_use = self.owners.contains_key(&block_id);
+            if self.data_bitmap.is_allocated(&self.block_device, bit as usize) == in_use {
+                continue;
+            }
+            self.problems.push(if in_use {
+                Problem::BlockNotAllocated { block_id }
+            } else {
+                Problem::BlockLeaked { block_id }
+            });
+            if self.repair {
+                self.data_bitmap.set_allocated(&self.block_device, bit as usize, in_use);
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use core::fmt::{Debug, Formatter, Result};
+use core::fmt::{Debug, Display, Formatter, Result};
 use super::{
     BLOCK_SZ,
     BlockDevice,
@@ -8,78 +8,210 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 /// Magic number for sanity check
-const EFS_MAGIC: u32 = 0x3b800001;
+const EFS_MAGIC: u32 = 0x3b800002;
+/// Magic number of images made before the journal. Their layout differs
+/// throughout, so they are refused.
+const OLD_EFS_MAGIC: u32 = 0x3b800001;
+/// Magic number of a journal holding a committed transaction
+const JOURNAL_MAGIC: u32 = 0x4a524e4c;
+/// The max number of blocks logged by a transaction
+pub const JOURNAL_CAPACITY: usize = 32;
+/// Number of blocks of the journal, a header followed by the logged blocks
+pub const JOURNAL_BLOCKS: u32 = 1 + JOURNAL_CAPACITY as u32;
+/// Feature flag: files grow past the indirect2 block into a triple
+/// indirect one, which readers without it would not follow
+pub const FEATURE_INDIRECT3: u32 = 1;
+/// Feature flag: a name too long for one directory entry carries on in
+/// the entries after it
+pub const FEATURE_LONG_NAMES: u32 = 2;
+/// Feature flags understood by this implementation
+const SUPPORTED_FEATURES: u32 = FEATURE_INDIRECT3 | FEATURE_LONG_NAMES;
 /// The max number of direct inodes
-const INODE_DIRECT_COUNT: usize = 28;
+const INODE_DIRECT_COUNT: usize = 27;
+/// Permission bits of a new file
+pub const DEFAULT_FILE_MODE: u32 = 0o644;
+/// Permission bits of a new directory
+pub const DEFAULT_DIR_MODE: u32 = 0o755;
 /// The max length of inode name
-const NAME_LENGTH_LIMIT: usize = 27;
+pub const NAME_LENGTH_LIMIT: usize = 27;
+/// The max length of inode name with `FEATURE_LONG_NAMES`
+pub const LONG_NAME_LENGTH_LIMIT: usize = 255;
 /// The max number of indirect1 inodes
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
 /// The max number of indirect2 inodes
 const INODE_INDIRECT2_COUNT: usize = INODE_INDIRECT1_COUNT * INODE_INDIRECT1_COUNT;
+/// The max number of indirect3 inodes
+const INODE_INDIRECT3_COUNT: usize = INODE_INDIRECT2_COUNT * INODE_INDIRECT1_COUNT;
 /// The upper bound of direct inode index
 const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
 /// The upper bound of indirect1 inode index
 const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
 /// The upper bound of indirect2 inode index
-#[allow(unused)]
 const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
+/// The upper bound of indirect3 inode index
+const INDIRECT3_BOUND: usize = INDIRECT2_BOUND + INODE_INDIRECT3_COUNT;
 
 /// Super block of a filesystem
 #[repr(C)]
 pub struct SuperBlock {
     magic: u32,
     pub total_blocks: u32,
+    pub journal_blocks: u32,
     pub inode_bitmap_blocks: u32,
     pub inode_area_blocks: u32,
     pub data_bitmap_blocks: u32,
     pub data_area_blocks: u32,
+    /// Feature flags chosen when the filesystem was created
+    pub features: u32,
 }
 
 impl Debug for SuperBlock {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         f.debug_struct("SuperBlock")
             .field("total_blocks", &self.total_blocks)
+            .field("journal_blocks", &self.journal_blocks)
             .field("inode_bitmap_blocks", &self.inode_bitmap_blocks)
             .field("inode_area_blocks", &self.inode_area_blocks)
             .field("data_bitmap_blocks", &self.data_bitmap_blocks)
             .field("data_area_blocks", &self.data_area_blocks)
+            .field("features", &self.features)
             .finish()
     }
 }
 
+/// Why a block device does not hold a filesystem that can be opened
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    /// No easy-fs super block at all
+    NotEasyFs,
+    /// An image of the format before the journal, which has to be packed
+    /// again
+    OldFormat,
+    /// Feature flags unknown here
+    UnsupportedFeatures(u32),
+}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::NotEasyFs => write!(f, "not an easy-fs image"),
+            Self::OldFormat => write!(f, "easy-fs image of an older format, pack it again"),
+            Self::UnsupportedFeatures(features) => {
+                write!(f, "easy-fs image with unsupported features {:#x}", features)
+            }
+        }
+    }
+}
+
+/// Where the areas of a filesystem lie, in blocks, and its feature flags
+#[derive(Clone, Copy)]
+pub struct FsLayout {
+    pub total_blocks: u32,
+    pub journal_blocks: u32,
+    pub inode_bitmap_blocks: u32,
+    pub inode_area_blocks: u32,
+    pub data_bitmap_blocks: u32,
+    pub data_area_blocks: u32,
+    pub features: u32,
+}
+
 impl SuperBlock {
     /// Initialize a super block
-    pub fn initialize(
-        &mut self,
-        total_blocks: u32,
-        inode_bitmap_blocks: u32,
-        inode_area_blocks: u32,
-        data_bitmap_blocks: u32,
-        data_area_blocks: u32,
-    ) {
+    pub fn initialize(&mut self, layout: FsLayout) {
         *self = Self {
             magic: EFS_MAGIC,
-            total_blocks,
-            inode_bitmap_blocks,
-            inode_area_blocks,
-            data_bitmap_blocks,
-            data_area_blocks,
+            total_blocks: layout.total_blocks,
+            journal_blocks: layout.journal_blocks,
+            inode_bitmap_blocks: layout.inode_bitmap_blocks,
+            inode_area_blocks: layout.inode_area_blocks,
+            data_bitmap_blocks: layout.data_bitmap_blocks,
+            data_area_blocks: layout.data_area_blocks,
+            features: layout.features,
         }
     }
-    /// Check if a super block is valid using efs magic
+    /// Check if a super block is valid using efs magic, and asks for no
+    /// feature unknown here
     pub fn is_valid(&self) -> bool {
-        self.magic == EFS_MAGIC
+        self.check().is_ok()
+    }
+    /// Like `is_valid`, telling why a super block cannot be used
+    pub fn check(&self) -> core::result::Result<(), FormatError> {
+        match self.magic {
+            EFS_MAGIC if self.features & !SUPPORTED_FEATURES != 0 => {
+                Err(FormatError::UnsupportedFeatures(self.features & !SUPPORTED_FEATURES))
+            }
+            EFS_MAGIC => Ok(()),
+            OLD_EFS_MAGIC => Err(FormatError::OldFormat),
+            _ => Err(FormatError::NotEasyFs),
+        }
+    }
+    /// The largest size a file can reach
+    pub fn max_file_size(&self) -> u32 {
+        let data_blocks = if self.features & FEATURE_INDIRECT3 != 0 {
+            INDIRECT3_BOUND
+        } else {
+            INDIRECT2_BOUND
+        };
+        (data_blocks * BLOCK_SZ) as u32
+    }
+    /// The longest name a directory entry can hold
+    pub fn name_length_limit(&self) -> usize {
+        if self.features & FEATURE_LONG_NAMES != 0 {
+            LONG_NAME_LENGTH_LIMIT
+        } else {
+            NAME_LENGTH_LIMIT
+        }
+    }
+}
+
+/// Header block of the journal, naming the home blocks of the logged
+/// blocks that follow it. It only counts once written with the magic, after
+/// all logged blocks.
+#[repr(C)]
+pub struct JournalHeader {
+    magic: u32,
+    count: u32,
+    blocks: [u32; JOURNAL_CAPACITY],
+}
+
+impl JournalHeader {
+    /// Record a committed transaction logging the given home blocks
+    pub fn commit(&mut self, blocks: &[u32]) {
+        assert!(blocks.len() <= JOURNAL_CAPACITY);
+        self.magic = JOURNAL_MAGIC;
+        self.count = blocks.len() as u32;
+        self.blocks[..blocks.len()].copy_from_slice(blocks);
+    }
+    /// Mark the journal empty, once its transaction is in place
+    pub fn clear(&mut self) {
+        self.magic = 0;
+        self.count = 0;
+    }
+    /// Home blocks of a committed transaction still to be replayed
+    pub fn committed(&self) -> &[u32] {
+        if self.magic == JOURNAL_MAGIC && self.count as usize <= JOURNAL_CAPACITY {
+            &self.blocks[..self.count as usize]
+        } else {
+            &[]
+        }
     }
 }
 
 /// Type of a disk inode
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum DiskInodeType {
     File,
     Directory,
 }
 
+/// A point in time, counted from the Unix epoch
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeSpec {
+    pub sec: u64,
+    pub nsec: u64,
+}
+
 /// A indirect block
 type IndirectBlock = [u32; BLOCK_SZ / 4];
 /// A data block
@@ -87,23 +219,60 @@ type DataBlock = [u8; BLOCK_SZ];
 
 /// A disk inode
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct DiskInode {
     pub size: u32,
     pub direct: [u32; INODE_DIRECT_COUNT],
     pub indirect1: u32,
     pub indirect2: u32,
+    /// Number of directory entries naming this inode, counting the `.` of
+    /// a directory and the `..` of each subdirectory
+    pub nlink: u32,
+    /// Permission bits, the file type is kept in `type_`
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Time of last read
+    pub atime: TimeSpec,
+    /// Time of last change to the content
+    pub mtime: TimeSpec,
+    /// Time of last change to the content or the inode itself
+    pub ctime: TimeSpec,
     type_: DiskInodeType,
+    /// Only used with `FEATURE_INDIRECT3`, zero otherwise
+    pub indirect3: u32,
+    _reserved: [u8; 64],
 }
 
+// two disk inodes to a block
+const _: () = assert!(core::mem::size_of::<DiskInode>() == 256);
+
 impl DiskInode {
     /// Initialize a disk inode, as well as all direct inodes under it
-    /// indirect1 and indirect2 block are allocated only when they are needed
-    pub fn initialize(&mut self, type_: DiskInodeType) {
+    /// indirect1, indirect2 and indirect3 block are allocated only when they are needed
+    /// A file starts with the one link from its parent, a directory also
+    /// with its own `.`; all three times are set to `now`
+    pub fn initialize(&mut self, type_: DiskInodeType, now: TimeSpec) {
         self.size = 0;
         self.direct.iter_mut().for_each(|v| *v = 0);
         self.indirect1 = 0;
         self.indirect2 = 0;
+        self.indirect3 = 0;
+        let is_dir = type_ == DiskInodeType::Directory;
+        self.nlink = if is_dir { 2 } else { 1 };
+        self.mode = if is_dir { DEFAULT_DIR_MODE } else { DEFAULT_FILE_MODE };
+        self.uid = 0;
+        self.gid = 0;
+        self.atime = now;
+        self.mtime = now;
+        self.ctime = now;
         self.type_ = type_;
+        self._reserved = [0; 64];
+    }
+    /// Record a change to the content at `now`
+    pub fn touch(&mut self, now: TimeSpec) {
+        self.mtime = now;
+        self.ctime = now;
     }
     /// Whether this inode is a directory
     pub fn is_dir(&self) -> bool {
@@ -114,6 +283,127 @@ impl DiskInode {
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
+    /// Whether the size is within the largest a file can reach
+    pub fn size_is_valid(&self, max_file_size: u32) -> bool {
+        self.size <= max_file_size
+    }
+    /// Whether a block pointer beyond the size is left set
+    pub fn has_stale_pointers(&self) -> bool {
+        let data_blocks = self.data_blocks() as usize;
+        self.direct.iter().skip(data_blocks).any(|&block_id| block_id != 0)
+            || (data_blocks <= DIRECT_BOUND && self.indirect1 != 0)
+            || (data_blocks <= INDIRECT1_BOUND && self.indirect2 != 0)
+            || (data_blocks <= INDIRECT2_BOUND && self.indirect3 != 0)
+    }
+    /// Call `f` over the blocks of the inode in order, each index block
+    /// before the blocks it points to. An index block is only read once
+    /// `f` accepted it. Returns how many data blocks were visited before
+    /// the first block `f` rejected.
+    pub fn walk_blocks(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        mut f: impl FnMut(u32) -> bool,
+    ) -> u32 {
+        let read_indirect = |block_id: u32| {
+            get_block_cache(block_id as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect_block: &IndirectBlock| *indirect_block)
+        };
+        let data_blocks =
+            ((self.size as usize + BLOCK_SZ - 1) / BLOCK_SZ).min(INDIRECT3_BOUND);
+        let mut visited = 0usize;
+        // direct
+        while visited < data_blocks.min(DIRECT_BOUND) {
+            if !f(self.direct[visited]) {
+                return visited as u32;
+            }
+            visited += 1;
+        }
+        if visited == data_blocks {
+            return visited as u32;
+        }
+        // indirect1
+        if !f(self.indirect1) {
+            return visited as u32;
+        }
+        let indirect1 = read_indirect(self.indirect1);
+        while visited < data_blocks.min(INDIRECT1_BOUND) {
+            if !f(indirect1[visited - DIRECT_BOUND]) {
+                return visited as u32;
+            }
+            visited += 1;
+        }
+        if visited == data_blocks {
+            return visited as u32;
+        }
+        // indirect2
+        if !f(self.indirect2) {
+            return visited as u32;
+        }
+        let indirect2 = read_indirect(self.indirect2);
+        let mut indirect1 = [0u32; INODE_INDIRECT1_COUNT];
+        while visited < data_blocks.min(INDIRECT2_BOUND) {
+            let last = visited - INDIRECT1_BOUND;
+            if last % INODE_INDIRECT1_COUNT == 0 {
+                let block_id = indirect2[last / INODE_INDIRECT1_COUNT];
+                if !f(block_id) {
+                    return visited as u32;
+                }
+                indirect1 = read_indirect(block_id);
+            }
+            if !f(indirect1[last % INODE_INDIRECT1_COUNT]) {
+                return visited as u32;
+            }
+            visited += 1;
+        }
+        if visited == data_blocks {
+            return visited as u32;
+        }
+        // indirect3
+        if !f(self.indirect3) {
+            return visited as u32;
+        }
+        let indirect3 = read_indirect(self.indirect3);
+        let mut indirect2 = [0u32; INODE_INDIRECT1_COUNT];
+        while visited < data_blocks {
+            let last = visited - INDIRECT2_BOUND;
+            if last % INODE_INDIRECT2_COUNT == 0 {
+                let block_id = indirect3[last / INODE_INDIRECT2_COUNT];
+                if !f(block_id) {
+                    return visited as u32;
+                }
+                indirect2 = read_indirect(block_id);
+            }
+            if last % INODE_INDIRECT1_COUNT == 0 {
+                let block_id = indirect2[last % INODE_INDIRECT2_COUNT / INODE_INDIRECT1_COUNT];
+                if !f(block_id) {
+                    return visited as u32;
+                }
+                indirect1 = read_indirect(block_id);
+            }
+            if !f(indirect1[last % INODE_INDIRECT1_COUNT]) {
+                return visited as u32;
+            }
+            visited += 1;
+        }
+        visited as u32
+    }
+    /// Cut the inode down to its first `data_blocks` data blocks and drop
+    /// the pointers beyond them. The blocks cut off are not deallocated.
+    pub fn truncate_blocks(&mut self, data_blocks: u32) {
+        self.size = self.size.min(data_blocks * BLOCK_SZ as u32);
+        let data_blocks = data_blocks as usize;
+        self.direct.iter_mut().skip(data_blocks).for_each(|block_id| *block_id = 0);
+        if data_blocks <= DIRECT_BOUND {
+            self.indirect1 = 0;
+        }
+        if data_blocks <= INDIRECT1_BOUND {
+            self.indirect2 = 0;
+        }
+        if data_blocks <= INDIRECT2_BOUND {
+            self.indirect3 = 0;
+        }
+    }
     /// Get the number of data blocks corresponding to size
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
@@ -133,7 +423,17 @@ impl DiskInode {
         if data_blocks > INDIRECT1_BOUND {
             total += 1;
             // sub indirect1
-            total += (data_blocks - INDIRECT1_BOUND + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
+            total += (data_blocks.min(INDIRECT2_BOUND) - INDIRECT1_BOUND + INODE_INDIRECT1_COUNT - 1)
+                / INODE_INDIRECT1_COUNT;
+        }
+        // indirect3
+        if data_blocks > INDIRECT2_BOUND {
+            total += 1;
+            let last = data_blocks - INDIRECT2_BOUND;
+            // sub indirect2
+            total += (last + INODE_INDIRECT2_COUNT - 1) / INODE_INDIRECT2_COUNT;
+            // sub sub indirect1
+            total += (last + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
         }
         total as u32
     }
@@ -153,7 +453,7 @@ impl DiskInode {
                 .read(0, |indirect_block: &IndirectBlock| {
                     indirect_block[inner_id - INODE_DIRECT_COUNT]
                 })
-        } else {
+        } else if inner_id < INDIRECT2_BOUND {
             let last = inner_id - INDIRECT1_BOUND;
             let indirect1 = get_block_cache(
                 self.indirect2 as usize,
@@ -171,6 +471,32 @@ impl DiskInode {
             .read(0, |indirect1: &IndirectBlock| {
                 indirect1[last % INODE_INDIRECT1_COUNT]
             })
+        } else {
+            let last = inner_id - INDIRECT2_BOUND;
+            let indirect2 = get_block_cache(
+                self.indirect3 as usize,
+                Arc::clone(block_device)
+            )
+            .lock()
+            .read(0, |indirect3: &IndirectBlock| {
+                indirect3[last / INODE_INDIRECT2_COUNT]
+            });
+            let indirect1 = get_block_cache(
+                indirect2 as usize,
+                Arc::clone(block_device)
+            )
+            .lock()
+            .read(0, |indirect2: &IndirectBlock| {
+                indirect2[last % INODE_INDIRECT2_COUNT / INODE_INDIRECT1_COUNT]
+            });
+            get_block_cache(
+                indirect1 as usize,
+                Arc::clone(block_device)
+            )
+            .lock()
+            .read(0, |indirect1: &IndirectBlock| {
+                indirect1[last % INODE_INDIRECT1_COUNT]
+            })
         }
     }
     /// Inncrease the size of current disk inode
@@ -224,8 +550,8 @@ impl DiskInode {
         // fill indirect2 from (a0, b0) -> (a1, b1)
         let mut a0 = current_blocks as usize / INODE_INDIRECT1_COUNT;
         let mut b0 = current_blocks as usize % INODE_INDIRECT1_COUNT;
-        let a1 = total_blocks as usize / INODE_INDIRECT1_COUNT;
-        let b1 = total_blocks as usize % INODE_INDIRECT1_COUNT;
+        let a1 = total_blocks.min(INODE_INDIRECT2_COUNT as u32) as usize / INODE_INDIRECT1_COUNT;
+        let b1 = total_blocks.min(INODE_INDIRECT2_COUNT as u32) as usize % INODE_INDIRECT1_COUNT;
         // alloc low-level indirect1
         get_block_cache(
             self.indirect2 as usize,
@@ -254,93 +580,111 @@ impl DiskInode {
                 }
             }
         });
-    }
-    /// Clear size to zero and return blocks that should be deallocated
-    /// and clear the block contents to zero later
-    pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
-        let mut v: Vec<u32> = Vec::new();
-        let mut data_blocks = self.data_blocks() as usize;
-        self.size = 0;
-        let mut current_blocks = 0usize;
-        // direct
-        while current_blocks < data_blocks.min(INODE_DIRECT_COUNT) {
-            v.push(self.direct[current_blocks]);
-            self.direct[current_blocks] = 0;
-            current_blocks += 1;
-        }
-        // indirect1 block
-        if data_blocks > INODE_DIRECT_COUNT {
-            v.push(self.indirect1);
-            data_blocks -= INODE_DIRECT_COUNT;
-            current_blocks = 0;
-        } else {
-            return v;
-        }
-        // indirect1
-        get_block_cache(
-            self.indirect1 as usize,
-            Arc::clone(block_device),
-        )
-        .lock()
-        .modify(0, |indirect1: &mut IndirectBlock| {
-            while current_blocks < data_blocks.min(INODE_INDIRECT1_COUNT) {
-                v.push(indirect1[current_blocks]);
-                //indirect1[current_blocks] = 0;
-                current_blocks += 1;
+        current_blocks = current_blocks.max(total_blocks.min(INODE_INDIRECT2_COUNT as u32));
+        // alloc indirect3
+        if total_blocks > INODE_INDIRECT2_COUNT as u32 {
+            if current_blocks == INODE_INDIRECT2_COUNT as u32 {
+                self.indirect3 = new_blocks.next().unwrap();
             }
-        });
-        self.indirect1 = 0;
-        // indirect2 block
-        if data_blocks > INODE_INDIRECT1_COUNT {
-            v.push(self.indirect2);
-            data_blocks -= INODE_INDIRECT1_COUNT;
+            current_blocks -= INODE_INDIRECT2_COUNT as u32;
+            total_blocks -= INODE_INDIRECT2_COUNT as u32;
         } else {
-            return v;
+            return;
         }
-        // indirect2
-        assert!(data_blocks <= INODE_INDIRECT2_COUNT);
-        let a1 = data_blocks / INODE_INDIRECT1_COUNT;
-        let b1 = data_blocks % INODE_INDIRECT1_COUNT;
+        // fill indirect3, allocating low-level indirect2 and indirect1
         get_block_cache(
-            self.indirect2 as usize,
-            Arc::clone(block_device),
+            self.indirect3 as usize,
+            Arc::clone(block_device)
         )
         .lock()
-        .modify(0, |indirect2: &mut IndirectBlock| {
-            // full indirect1 blocks
-            for i in 0..a1 {
-                v.push(indirect2[i]);
+        .modify(0, |indirect3: &mut IndirectBlock| {
+            while current_blocks < total_blocks {
+                let last = current_blocks as usize;
+                let a0 = last / INODE_INDIRECT2_COUNT;
+                let b0 = last % INODE_INDIRECT2_COUNT / INODE_INDIRECT1_COUNT;
+                let c0 = last % INODE_INDIRECT1_COUNT;
+                if last % INODE_INDIRECT2_COUNT == 0 {
+                    indirect3[a0] = new_blocks.next().unwrap();
+                }
                 get_block_cache(
-                    indirect2[i] as usize,
-                    Arc::clone(block_device),
+                    indirect3[a0] as usize,
+                    Arc::clone(block_device)
                 )
                 .lock()
-                .modify(0, |indirect1: &mut IndirectBlock| {
-                    for j in 0..INODE_INDIRECT1_COUNT {
-                        v.push(indirect1[j]);
-                        //indirect1[j] = 0;
+                .modify(0, |indirect2: &mut IndirectBlock| {
+                    if c0 == 0 {
+                        indirect2[b0] = new_blocks.next().unwrap();
                     }
+                    get_block_cache(
+                        indirect2[b0] as usize,
+                        Arc::clone(block_device)
+                    )
+                    .lock()
+                    .modify(0, |indirect1: &mut IndirectBlock| {
+                        indirect1[c0] = new_blocks.next().unwrap();
+                    });
                 });
-                //indirect2[i] = 0;
+                current_blocks += 1;
             }
-            // last indirect1 block
-            if b1 > 0 {
-                v.push(indirect2[a1]);
-                get_block_cache(
-                    indirect2[a1] as usize,
-                    Arc::clone(block_device),
-                )
+        });
+    }
+    /// Decrease the size of current disk inode, and return the data and
+    /// index blocks no longer used, to be deallocated and cleared later
+    pub fn decrease_size(&mut self, new_size: u32, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        assert!(new_size <= self.size);
+        let read_entry = |block_id: u32, index: usize| {
+            get_block_cache(block_id as usize, Arc::clone(block_device))
                 .lock()
-                .modify(0, |indirect1: &mut IndirectBlock| {
-                    for j in 0..b1 {
-                        v.push(indirect1[j]);
-                        //indirect1[j] = 0;
-                    }
-                });
-                //indirect2[a1] = 0;
+                .read(0, |indirect_block: &IndirectBlock| indirect_block[index])
+        };
+        let mut v: Vec<u32> = Vec::new();
+        let total_blocks = Self::_data_blocks(new_size) as usize;
+        let mut current_blocks = self.data_blocks() as usize;
+        // from the last block down, each index block after the blocks under it
+        while current_blocks > total_blocks {
+            current_blocks -= 1;
+            let inner_id = current_blocks;
+            if inner_id < DIRECT_BOUND {
+                v.push(self.direct[inner_id]);
+                self.direct[inner_id] = 0;
+            } else if inner_id < INDIRECT1_BOUND {
+                v.push(read_entry(self.indirect1, inner_id - DIRECT_BOUND));
+                if inner_id == DIRECT_BOUND {
+                    v.push(self.indirect1);
+                    self.indirect1 = 0;
+                }
+            } else if inner_id < INDIRECT2_BOUND {
+                let last = inner_id - INDIRECT1_BOUND;
+                let indirect1 = read_entry(self.indirect2, last / INODE_INDIRECT1_COUNT);
+                v.push(read_entry(indirect1, last % INODE_INDIRECT1_COUNT));
+                if last % INODE_INDIRECT1_COUNT == 0 {
+                    v.push(indirect1);
+                }
+                if last == 0 {
+                    v.push(self.indirect2);
+                    self.indirect2 = 0;
+                }
+            } else {
+                let last = inner_id - INDIRECT2_BOUND;
+                let indirect2 = read_entry(self.indirect3, last / INODE_INDIRECT2_COUNT);
+                let indirect1 = read_entry(
+                    indirect2,
+                    last % INODE_INDIRECT2_COUNT / INODE_INDIRECT1_COUNT,
+                );
+                v.push(read_entry(indirect1, last % INODE_INDIRECT1_COUNT));
+                if last % INODE_INDIRECT1_COUNT == 0 {
+                    v.push(indirect1);
+                }
+                if last % INODE_INDIRECT2_COUNT == 0 {
+                    v.push(indirect2);
+                }
+                if last == 0 {
+                    v.push(self.indirect3);
+                    self.indirect3 = 0;
+                }
             }
-        });
-        self.indirect2 = 0;
+        }
+        self.size = new_size;
         v
     }
     /// Read data from current disk inode
@@ -381,6 +725,21 @@ impl DiskInode {
         }
         read_size
     }
+    /// Read the directory entry starting at the `slot`-th entry of a
+    /// directory, with its name, which a long name carries on in the
+    /// entries after it
+    pub fn read_dirent(&self, slot: usize, block_device: &Arc<dyn BlockDevice>) -> (DirEntry, Vec<u8>) {
+        let mut dirent = DirEntry::empty();
+        self.read_at(slot * DIRENT_SZ, dirent.as_bytes_mut(), block_device);
+        let mut name = Vec::from(dirent.name_bytes());
+        if let Some(len) = dirent.long_name_len() {
+            let start = name.len();
+            name.resize(len.max(start), 0);
+            let read_size = self.read_at((slot + 1) * DIRENT_SZ, &mut name[start..], block_device);
+            name.truncate(start + read_size);
+        }
+        (dirent, name)
+    }
     /// Write data into current disk inode
     /// size must be adjusted properly beforehand
     pub fn write_at(
@@ -420,7 +779,9 @@ impl DiskInode {
     }
 }
 
-/// A directory entry
+/// A directory entry. A name longer than `NAME_LENGTH_LIMIT` keeps that
+/// much here with its length in the last byte, where a short name is
+/// always terminated, and the rest in the entries after this one.
 #[repr(C)]
 pub struct DirEntry {
     name: [u8; NAME_LENGTH_LIMIT + 1],
@@ -438,15 +799,51 @@ impl DirEntry {
             inode_number: 0,
         }
     }
-    /// Crate a directory entry from name and inode number
+    /// Crate a directory entry from name and inode number, the first of
+    /// the entries of a long name
     pub fn new(name: &str, inode_number: u32) -> Self {
         let mut bytes = [0u8; NAME_LENGTH_LIMIT + 1];
-        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        if name.len() > NAME_LENGTH_LIMIT {
+            bytes[..NAME_LENGTH_LIMIT].copy_from_slice(&name.as_bytes()[..NAME_LENGTH_LIMIT]);
+            bytes[NAME_LENGTH_LIMIT] = name.len() as u8;
+        } else {
+            bytes[..name.len()].copy_from_slice(name.as_bytes());
+        }
         Self {
             name: bytes,
             inode_number,
         }
     }
+    /// Serialize the entries of a name of any length, this one followed
+    /// by the rest of a long name
+    pub fn encode(name: &str, inode_number: u32) -> Vec<u8> {
+        let mut bytes = Vec::from(Self::new(name, inode_number).as_bytes());
+        if name.len() > NAME_LENGTH_LIMIT {
+            bytes.extend_from_slice(&name.as_bytes()[NAME_LENGTH_LIMIT..]);
+            bytes.resize(Self::slots_for(name.len()) * DIRENT_SZ, 0);
+        }
+        bytes
+    }
+    /// Number of entries taken by a name of the given length
+    pub fn slots_for(name_len: usize) -> usize {
+        if name_len > NAME_LENGTH_LIMIT {
+            1 + (name_len - NAME_LENGTH_LIMIT + DIRENT_SZ - 1) / DIRENT_SZ
+        } else {
+            1
+        }
+    }
+    /// Number of entries taken by this entry, those carrying on a long
+    /// name included
+    pub fn slots(&self) -> usize {
+        self.long_name_len().map_or(1, Self::slots_for)
+    }
+    /// Length of a long name carrying on in the entries after this one
+    pub fn long_name_len(&self) -> Option<usize> {
+        match self.name[NAME_LENGTH_LIMIT] {
+            0 => None,
+            len => Some(len as usize),
+        }
+    }
     /// Serialize into bytes
     pub fn as_bytes(&self) -> &[u8] {
         unsafe {
@@ -465,13 +862,31 @@ impl DirEntry {
             )
         }
     }
-    /// Get name of the entry
-    pub fn name(&self) -> &str {
-        let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
-        core::str::from_utf8(&self.name[..len]).unwrap()
+    /// Get the name of the entry, only the start of a long one
+    pub fn name_bytes(&self) -> &[u8] {
+        let len = self.name[..NAME_LENGTH_LIMIT]
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(NAME_LENGTH_LIMIT);
+        &self.name[..len]
     }
     /// Get inode number of the entry
     pub fn inode_number(&self) -> u32 {
         self.inode_number
     }
+    /// Whether the entry is an unused slot, e.g. left by a removed entry
+    pub fn is_free(&self) -> bool {
+        self.name[0] == 0
+    }
+    /// Whether a name read with the entry is whole, valid UTF-8 and
+    /// without a `/` or NUL
+    pub fn name_is_valid(&self, name: &[u8]) -> bool {
+        let whole = match self.long_name_len() {
+            Some(len) => len > NAME_LENGTH_LIMIT && name.len() == len,
+            None => !name.is_empty(),
+        };
+        whole
+            && !name.iter().any(|&byte| byte == b'/' || byte == 0)
+            && core::str::from_utf8(name).is_ok()
+    }
 }
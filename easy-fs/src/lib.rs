@@ -8,12 +8,31 @@ mod efs;
 mod bitmap;
 mod vfs;
 mod block_cache;
+mod fsck;
 
 /// Use a block size of 512 bytes
 pub const BLOCK_SZ: usize = 512;
 pub use block_dev::BlockDevice;
 pub use efs::EasyFileSystem;
-pub use vfs::Inode;
+pub use vfs::{Inode, Metadata};
+pub use fsck::{fsck, FsckReport, Problem};
+pub use block_cache::{set_block_cache_capacity, BlockCacheManager};
+pub use layout::{
+    FormatError,
+    TimeSpec,
+    DEFAULT_FILE_MODE,
+    DEFAULT_DIR_MODE,
+    FEATURE_INDIRECT3,
+    FEATURE_LONG_NAMES,
+    NAME_LENGTH_LIMIT,
+    LONG_NAME_LENGTH_LIMIT,
+};
 use layout::*;
 use bitmap::Bitmap;
-use block_cache::{get_block_cache, block_cache_sync_all};
+use block_cache::{
+    get_block_cache,
+    block_cache_sync_all,
+    block_cache_begin_transaction,
+    block_cache_transaction_blocks,
+    block_cache_end_transaction,
+};
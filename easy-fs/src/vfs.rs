@@ -5,6 +5,8 @@ use super::{
     DirEntry,
     EasyFileSystem,
     DIRENT_SZ,
+    BLOCK_SZ,
+    TimeSpec,
     get_block_cache,
     block_cache_sync_all,
 };
@@ -15,27 +17,154 @@ use spin::{Mutex, MutexGuard};
 
 /// Virtual filesystem layer over easy-fs
 pub struct Inode {
+    inode_id: u32,
     block_id: usize,
     block_offset: usize,
     fs: Arc<Mutex<EasyFileSystem>>,
     block_device: Arc<dyn BlockDevice>,
+    /// Whether this is handed out and counted by the filesystem, rather
+    /// than used only while the filesystem is locked
+    handle: bool,
+}
+
+/// A file grows or shrinks by this many data blocks at a time
+const RESIZE_STEP_BLOCKS: usize = 8;
+/// The most blocks one resize step modifies: the inode, at most five index
+/// blocks, and a bitmap block for each data and index block allocated or
+/// freed, as a transaction is committed before it could overflow the
+/// journal
+const RESIZE_STEP_MODIFIED: usize = 1 + 5 + RESIZE_STEP_BLOCKS + 5;
+
+/// Metadata of an inode
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+    pub inode_id: u32,
+    pub is_dir: bool,
+    pub size: u32,
+    /// Number of blocks holding data and indexes
+    pub blocks: u32,
+    pub nlink: u32,
+    /// Permission bits
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub atime: TimeSpec,
+    pub mtime: TimeSpec,
+    pub ctime: TimeSpec,
+}
+
+/// Whether a name can be given to a new entry of a directory
+fn name_is_valid(name: &str, fs: &EasyFileSystem) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && name.len() <= fs.name_length_limit()
+        && !name.contains('\0')
+}
+
+/// Split a path into its directory, empty for the current one, and its
+/// last component
+fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches('/');
+    match path.rfind('/') {
+        Some(idx) => (&path[..idx + 1], &path[idx + 1..]),
+        None => ("", path),
+    }
 }
 
 impl Inode {
     /// Create a vfs inode
     pub fn new(
+        inode_id: u32,
         block_id: u32,
         block_offset: usize,
         fs: Arc<Mutex<EasyFileSystem>>,
         block_device: Arc<dyn BlockDevice>,
     ) -> Self {
         Self {
+            inode_id,
             block_id: block_id as usize,
             block_offset,
             fs,
             block_device,
+            handle: false,
         }
     }
+    /// Mark as a handle, already counted by `EasyFileSystem::open_handle`
+    pub(crate) fn into_handle(mut self) -> Self {
+        self.handle = true;
+        self
+    }
+    /// Hand out an inode, keeping it alive even if it is unlinked
+    fn handle_of(inode: Self, fs: &mut EasyFileSystem) -> Arc<Inode> {
+        fs.open_handle(inode.inode_id);
+        Arc::new(inode.into_handle())
+    }
+    /// Get the inode number
+    pub fn inode_id(&self) -> u32 {
+        self.inode_id
+    }
+    /// Whether this inode is a directory
+    pub fn is_dir(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+    /// Get the number of hard links
+    pub fn nlink(&self) -> u32 {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.nlink)
+    }
+    /// Get the metadata
+    pub fn metadata(&self) -> Metadata {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| Metadata {
+            inode_id: self.inode_id,
+            is_dir: disk_inode.is_dir(),
+            size: disk_inode.size,
+            blocks: DiskInode::total_blocks(disk_inode.size),
+            nlink: disk_inode.nlink,
+            mode: disk_inode.mode,
+            uid: disk_inode.uid,
+            gid: disk_inode.gid,
+            atime: disk_inode.atime,
+            mtime: disk_inode.mtime,
+            ctime: disk_inode.ctime,
+        })
+    }
+    /// Set the access and modification times, leaving those given as
+    /// `None` unchanged
+    pub fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) {
+        let fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            if let Some(atime) = atime {
+                disk_inode.atime = atime;
+            }
+            if let Some(mtime) = mtime {
+                disk_inode.mtime = mtime;
+            }
+            disk_inode.ctime = fs.now();
+        });
+        block_cache_sync_all();
+    }
+    /// Set the permission bits
+    pub fn set_mode(&self, mode: u32) {
+        let fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.mode = mode & 0o7777;
+            disk_inode.ctime = fs.now();
+        });
+        block_cache_sync_all();
+    }
+    /// Set the owner and group
+    pub fn set_owner(&self, uid: u32, gid: u32) {
+        let fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.uid = uid;
+            disk_inode.gid = gid;
+            disk_inode.ctime = fs.now();
+        });
+        block_cache_sync_all();
+    }
     /// Call a function over a disk inode to read it
     fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> V {
         get_block_cache(
@@ -50,47 +179,149 @@ impl Inode {
             Arc::clone(&self.block_device)
         ).lock().modify(self.block_offset, f)
     }
-    /// Find inode under a disk inode by name
-    fn find_inode_id(
+    /// Get a vfs inode of the same filesystem by inode number
+    fn inode_of(&self, inode_id: u32, fs: &EasyFileSystem) -> Self {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        Self::new(
+            inode_id,
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        )
+    }
+    /// Call `f` over the directory entries in use under a disk inode,
+    /// with the index of their first entry and their name, until it
+    /// returns something
+    fn find_map_dirent<V>(
         &self,
-        name: &str,
         disk_inode: &DiskInode,
-    ) -> Option<u32> {
+        mut f: impl FnMut(usize, &DirEntry, &[u8]) -> Option<V>,
+    ) -> Option<V> {
         // assert it is a directory
         assert!(disk_inode.is_dir());
         let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-        let mut dirent = DirEntry::empty();
-        for i in 0..file_count {
-            assert_eq!(
-                disk_inode.read_at(
-                    DIRENT_SZ * i,
-                    dirent.as_bytes_mut(),
-                    &self.block_device,
-                ),
-                DIRENT_SZ,
-            );
-            if dirent.name() == name {
-                return Some(dirent.inode_number() as u32);
+        let mut i = 0;
+        while i < file_count {
+            let (dirent, name) = disk_inode.read_dirent(i, &self.block_device);
+            if !dirent.is_free() {
+                if let Some(v) = f(i, &dirent, &name) {
+                    return Some(v);
+                }
             }
+            i += dirent.slots();
         }
         None
     }
-    /// Find inode under current inode by name
-    pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
-        let fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(name, disk_inode)
-            .map(|inode_id| {
-                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-                Arc::new(Self::new(
-                    block_id,
-                    block_offset,
-                    self.fs.clone(),
-                    self.block_device.clone(),
-                ))
-            })
+    /// Find the directory entry of a name under a disk inode,
+    /// returning its index and inode number
+    fn find_dirent(
+        &self,
+        name: &str,
+        disk_inode: &DiskInode,
+    ) -> Option<(usize, u32)> {
+        self.find_map_dirent(disk_inode, |i, dirent, dirent_name| {
+            if dirent_name == name.as_bytes() {
+                Some((i, dirent.inode_number()))
+            } else {
+                None
+            }
         })
     }
+    /// Find inode under a disk inode by name
+    fn find_inode_id(
+        &self,
+        name: &str,
+        disk_inode: &DiskInode,
+    ) -> Option<u32> {
+        self.find_dirent(name, disk_inode).map(|(_, inode_id)| inode_id)
+    }
+    /// Resolve a path relative to current inode, or to the root if it
+    /// starts with `/`. Every component but the last must be a directory.
+    fn lookup(&self, path: &str, fs: &EasyFileSystem) -> Option<Self> {
+        let mut inode = if path.starts_with('/') {
+            self.inode_of(0, fs)
+        } else {
+            self.inode_of(self.inode_id, fs)
+        };
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let inode_id = inode.read_disk_inode(|disk_inode| {
+                if !disk_inode.is_dir() {
+                    return None;
+                }
+                inode.find_inode_id(name, disk_inode)
+            })?;
+            inode = self.inode_of(inode_id, fs);
+        }
+        Some(inode)
+    }
+    /// Find inode by path, e.g. `name`, `dir/name` or `/dir/name`
+    pub fn find(&self, path: &str) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        self.lookup(path, &fs)
+            .map(|inode| Self::handle_of(inode, &mut fs))
+    }
+    /// Find the directory entry of a path, returning the directory
+    /// holding it, the index of the entry and its inode number
+    fn lookup_dirent(&self, path: &str, fs: &EasyFileSystem) -> Option<(Self, usize, u32)> {
+        let (dir, name) = split_path(path);
+        if name.is_empty() || name == "." || name == ".." {
+            return None;
+        }
+        let parent = self.lookup(dir, fs)?;
+        let (slot, inode_id) = parent.read_disk_inode(|parent_inode| {
+            if parent_inode.is_dir() {
+                parent.find_dirent(name, parent_inode)
+            } else {
+                None
+            }
+        })?;
+        Some((parent, slot, inode_id))
+    }
+    /// Clear the directory entry starting at the `i`-th entry of current
+    /// inode, with all those of a long name
+    fn remove_dirent(&self, i: usize, now: TimeSpec) {
+        self.modify_disk_inode(|dir_inode| {
+            let (dirent, _) = dir_inode.read_dirent(i, &self.block_device);
+            dir_inode.write_at(
+                i * DIRENT_SZ,
+                &alloc::vec![0u8; dirent.slots() * DIRENT_SZ],
+                &self.block_device,
+            );
+            dir_inode.touch(now);
+        });
+    }
+    /// Free the inode and its data blocks if no link and no handle is
+    /// left
+    fn release(&self, fs: &mut EasyFileSystem) {
+        if self.read_disk_inode(|disk_inode| disk_inode.nlink) > 0 || fs.is_open(self.inode_id) {
+            return;
+        }
+        self.decrease_size(0, fs);
+        fs.dealloc_inode(self.inode_id);
+    }
+    /// Decrease the size of current inode within the open transaction,
+    /// committing it and starting another whenever the next step might
+    /// not fit in the journal
+    fn decrease_size(&self, new_size: u32, fs: &mut EasyFileSystem) {
+        loop {
+            if !fs.transaction_fits(RESIZE_STEP_MODIFIED) {
+                fs.commit();
+                fs.begin();
+            }
+            let shrunk = self.modify_disk_inode(|disk_inode| {
+                let step = (RESIZE_STEP_BLOCKS * BLOCK_SZ) as u32;
+                let size = new_size.max(disk_inode.size.saturating_sub(step));
+                for data_block in disk_inode.decrease_size(size, &self.block_device) {
+                    fs.dealloc_data(data_block);
+                }
+                disk_inode.size <= new_size
+            });
+            if shrunk {
+                break;
+            }
+        }
+    }
     /// Increase the size of a disk inode
     fn increase_size(
         &self,
@@ -108,104 +339,255 @@ impl Inode {
         }
         disk_inode.increase_size(new_size, v, &self.block_device);
     }
-    /// Create inode under current inode by name
-    pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+    /// Add a directory entry to current inode, reusing free slots if
+    /// enough of them are in a row
+    fn add_dirent(
+        &self,
+        name: &str,
+        inode_id: u32,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) {
+        self.modify_disk_inode(|dir_inode| {
+            let file_count = (dir_inode.size as usize) / DIRENT_SZ;
+            let slots = DirEntry::slots_for(name.len());
+            // the first free slot after the entries seen so far
+            let mut free_slot = 0;
+            let slot = self
+                .find_map_dirent(dir_inode, |i, dirent, _| {
+                    if i - free_slot >= slots {
+                        return Some(free_slot);
+                    }
+                    free_slot = i + dirent.slots();
+                    None
+                })
+                .unwrap_or(free_slot);
+            if slot + slots > file_count {
+                // increase size
+                self.increase_size(((slot + slots) * DIRENT_SZ) as u32, dir_inode, fs);
+            }
+            // write dirent
+            dir_inode.write_at(
+                slot * DIRENT_SZ,
+                &DirEntry::encode(name, inode_id),
+                &self.block_device,
+            );
+            dir_inode.touch(fs.now());
+        });
+    }
+    /// Add the `.` and `..` entries to a new directory
+    pub(crate) fn add_dir_entries(
+        &self,
+        parent_id: u32,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) {
+        self.add_dirent(".", self.inode_id, fs);
+        self.add_dirent("..", parent_id, fs);
+    }
+    /// Create an inode of the given type by path
+    fn create_inode(&self, path: &str, type_: DiskInodeType) -> Option<Arc<Inode>> {
         let mut fs = self.fs.lock();
-        if self.modify_disk_inode(|root_inode| {
-            // assert it is a directory
-            assert!(root_inode.is_dir());
-            // has the file been created?
-            self.find_inode_id(name, root_inode)
-        }).is_some() {
+        let (dir, name) = split_path(path);
+        if !name_is_valid(name, &fs) {
             return None;
         }
-        // create a new file
-        // alloc a inode with an indirect block
+        let parent = self.lookup(dir, &fs)?;
+        if parent.read_disk_inode(|parent_inode| {
+            // the parent must be a directory without this name
+            !parent_inode.is_dir() || parent.find_inode_id(name, parent_inode).is_some()
+        }) {
+            return None;
+        }
+        // create a new inode
+        fs.begin();
         let new_inode_id = fs.alloc_inode();
-        // initialize inode
-        let (new_inode_block_id, new_inode_block_offset) 
-            = fs.get_disk_inode_pos(new_inode_id);
-        get_block_cache(
-            new_inode_block_id as usize,
-            Arc::clone(&self.block_device)
-        ).lock().modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-            new_inode.initialize(DiskInodeType::File);
-        });
-        self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
+        let new_inode = self.inode_of(new_inode_id, &fs);
+        let is_dir = type_ == DiskInodeType::Directory;
+        new_inode.modify_disk_inode(|disk_inode| {
+            disk_inode.initialize(type_, fs.now());
         });
-
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        block_cache_sync_all();
-        // return inode
-        Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
-            self.fs.clone(),
-            self.block_device.clone(),
-        )))
+        if is_dir {
+            new_inode.add_dir_entries(parent.inode_id, &mut fs);
+            // the `..` of the new directory
+            parent.modify_disk_inode(|parent_inode| parent_inode.nlink += 1);
+        }
+        parent.add_dirent(name, new_inode_id, &mut fs);
+        fs.commit();
+        Some(Self::handle_of(new_inode, &mut fs))
         // release efs lock automatically by compiler
     }
-    /// List inodes under current inode
+    /// Create a file by path, which must not exist yet
+    pub fn create(&self, path: &str) -> Option<Arc<Inode>> {
+        self.create_inode(path, DiskInodeType::File)
+    }
+    /// Create a directory by path, which must not exist yet
+    pub fn mkdir(&self, path: &str) -> Option<Arc<Inode>> {
+        self.create_inode(path, DiskInodeType::Directory)
+    }
+    /// Create a hard link `new_path` to the file `old_path`
+    pub fn link(&self, old_path: &str, new_path: &str) -> bool {
+        let mut fs = self.fs.lock();
+        let inode = match self.lookup(old_path, &fs) {
+            Some(inode) => inode,
+            None => return false,
+        };
+        // directories cannot be linked
+        if inode.read_disk_inode(|disk_inode| disk_inode.is_dir()) {
+            return false;
+        }
+        let (dir, name) = split_path(new_path);
+        if !name_is_valid(name, &fs) {
+            return false;
+        }
+        let parent = match self.lookup(dir, &fs) {
+            Some(parent) => parent,
+            None => return false,
+        };
+        if parent.read_disk_inode(|parent_inode| {
+            !parent_inode.is_dir() || parent.find_inode_id(name, parent_inode).is_some()
+        }) {
+            return false;
+        }
+        fs.begin();
+        parent.add_dirent(name, inode.inode_id, &mut fs);
+        let now = fs.now();
+        inode.modify_disk_inode(|disk_inode| {
+            disk_inode.nlink += 1;
+            disk_inode.ctime = now;
+        });
+        fs.commit();
+        true
+    }
+    /// Remove a directory entry of a file by path. The file is freed with
+    /// its last link, or once the last handle to it is dropped.
+    pub fn unlink(&self, path: &str) -> bool {
+        let mut fs = self.fs.lock();
+        let (parent, slot, inode_id) = match self.lookup_dirent(path, &fs) {
+            Some(dirent) => dirent,
+            None => return false,
+        };
+        let inode = self.inode_of(inode_id, &fs);
+        if inode.read_disk_inode(|disk_inode| disk_inode.is_dir()) {
+            return false;
+        }
+        fs.begin();
+        let now = fs.now();
+        parent.remove_dirent(slot, now);
+        inode.modify_disk_inode(|disk_inode| {
+            disk_inode.nlink -= 1;
+            disk_inode.ctime = now;
+        });
+        inode.release(&mut fs);
+        fs.commit();
+        true
+    }
+    /// Remove an empty directory by path, freeing its inode and blocks
+    /// once no handle to it is left
+    pub fn rmdir(&self, path: &str) -> bool {
+        let mut fs = self.fs.lock();
+        let (parent, slot, inode_id) = match self.lookup_dirent(path, &fs) {
+            Some(dirent) => dirent,
+            None => return false,
+        };
+        let inode = self.inode_of(inode_id, &fs);
+        let removable = inode.read_disk_inode(|disk_inode| {
+            disk_inode.is_dir() && inode.find_map_dirent(disk_inode, |_, _, name| {
+                if name == b"." || name == b".." {
+                    None
+                } else {
+                    Some(())
+                }
+            }).is_none()
+        });
+        if !removable {
+            return false;
+        }
+        fs.begin();
+        parent.remove_dirent(slot, fs.now());
+        // the `..` of the removed directory
+        parent.modify_disk_inode(|parent_inode| parent_inode.nlink -= 1);
+        inode.modify_disk_inode(|disk_inode| disk_inode.nlink = 0);
+        inode.release(&mut fs);
+        fs.commit();
+        true
+    }
+    /// List inodes under current inode, except `.` and `..`
     pub fn ls(&self) -> Vec<String> {
         let _fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
-            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
             let mut v: Vec<String> = Vec::new();
-            for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    disk_inode.read_at(
-                        i * DIRENT_SZ,
-                        dirent.as_bytes_mut(),
-                        &self.block_device,
-                    ),
-                    DIRENT_SZ,
-                );
-                v.push(String::from(dirent.name()));
-            }
+            self.find_map_dirent(disk_inode, |_, _, name| {
+                if name != b"." && name != b".." {
+                    v.push(String::from_utf8(name.to_vec()).unwrap());
+                }
+                None::<()>
+            });
             v
         })
     }
-    /// Read data from current inode
+    /// Read data from current inode, updating its access time
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
-        let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
+        let fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.atime = fs.now();
             disk_inode.read_at(offset, buf, &self.block_device)
         })
     }
-    /// Write data to current inode
+    /// Write data to current inode. The file grows in transactions, each
+    /// modifying no more blocks than the journal holds, before the data is
+    /// written. Data past the largest size a file can reach is left
+    /// unwritten.
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
         let mut fs = self.fs.lock();
+        let max_file_size = fs.max_file_size() as usize;
+        if offset >= max_file_size {
+            return 0;
+        }
+        let new_size = (offset + buf.len()).min(max_file_size) as u32;
+        fs.begin();
+        loop {
+            if !fs.transaction_fits(RESIZE_STEP_MODIFIED) {
+                fs.commit();
+                fs.begin();
+            }
+            let grown = self.modify_disk_inode(|disk_inode| {
+                let step = (RESIZE_STEP_BLOCKS * BLOCK_SZ) as u32;
+                let size = new_size.min(disk_inode.size.saturating_add(step));
+                self.increase_size(size, disk_inode, &mut fs);
+                disk_inode.touch(fs.now());
+                disk_inode.size >= new_size
+            });
+            if grown {
+                break;
+            }
+        }
+        fs.commit();
         let size = self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
             disk_inode.write_at(offset, buf, &self.block_device)
         });
         block_cache_sync_all();
         size
     }
-    /// Clear the data in current inode
+    /// Clear the data in current inode, from the end in transactions
     pub fn clear(&self) {
         let mut fs = self.fs.lock();
-        self.modify_disk_inode(|disk_inode| {
-            let size = disk_inode.size;
-            let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
-            assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
-            for data_block in data_blocks_dealloc.into_iter() {
-                fs.dealloc_data(data_block);
-            }
-        });
-        block_cache_sync_all();
+        fs.begin();
+        self.decrease_size(0, &mut fs);
+        self.modify_disk_inode(|disk_inode| disk_inode.touch(fs.now()));
+        fs.commit();
+    }
+}
+
+impl Drop for Inode {
+    /// Free an unlinked inode with its last handle
+    fn drop(&mut self) {
+        if !self.handle {
+            return;
+        }
+        let mut fs = self.fs.lock();
+        if fs.close_handle(self.inode_id) == 0 {
+            fs.begin();
+            self.release(&mut fs);
+            fs.commit();
+        }
     }
 }
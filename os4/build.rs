@@ -1,13 +1,41 @@
-use std::fs::{read_dir, File};
+use std::collections::HashMap;
+use std::fs::{read_dir, read_to_string, File};
 use std::io::{Result, Write};
 
 fn main() {
     println!("cargo:rerun-if-changed=../user/src/");
     println!("cargo:rerun-if-changed={}", TARGET_PATH);
+    println!("cargo:rerun-if-changed={}", ARGS_PATH);
+    println!("cargo:rerun-if-env-changed=KERNEL_SYMS");
     insert_app_data().unwrap();
+    insert_kernel_symbols().unwrap();
 }
 
 static TARGET_PATH: &str = "../user/build/elf/";
+/// Optional app table arguments, one `app_name arg1 arg2 ...` per line.
+static ARGS_PATH: &str = "app_args.txt";
+
+/// Read `ARGS_PATH` into a map from app name to its space-separated arguments.
+fn read_app_args() -> HashMap<String, String> {
+    let mut args = HashMap::new();
+    if let Ok(content) = read_to_string(ARGS_PATH) {
+        for line in content.lines() {
+            let mut words = line.split_whitespace();
+            if let Some(app) = words.next() {
+                if app.starts_with('#') {
+                    continue;
+                }
+                args.insert(app.to_string(), words.collect::<Vec<_>>().join(" "));
+            }
+        }
+    }
+    args
+}
+
+/// Escape a string for an assembler `.string` directive.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
 
 fn insert_app_data() -> Result<()> {
     let mut f = File::create("src/link_app.S").unwrap();
@@ -38,6 +66,27 @@ _num_app:
     }
     writeln!(f, r#"    .quad app_{}_end"#, apps.len() - 1)?;
 
+    let app_args = read_app_args();
+    writeln!(
+        f,
+        r#"
+    .global _app_names
+_app_names:"#
+    )?;
+    for app in apps.iter() {
+        writeln!(f, r#"    .string "{}""#, escape(app))?;
+    }
+    writeln!(
+        f,
+        r#"
+    .global _app_args
+_app_args:"#
+    )?;
+    for app in apps.iter() {
+        let args = app_args.get(app).map(String::as_str).unwrap_or("");
+        writeln!(f, r#"    .string "{}""#, escape(args))?;
+    }
+
     for (idx, app) in apps.iter().enumerate() {
         println!("app_{}: {}", idx, app);
         writeln!(
@@ -55,3 +104,83 @@ app_{0}_end:"#,
     }
     Ok(())
 }
+
+/// Strip the `::h0123456789abcdef` hash that rustc appends to symbol names.
+fn strip_hash(name: &str) -> &str {
+    match name.rfind("::h") {
+        Some(pos)
+            if name.len() - pos == 19
+                && name[pos + 3..].chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            &name[..pos]
+        }
+        _ => name,
+    }
+}
+
+/// Generate `src/ksyms.S`, the table of text symbols used to symbolize
+/// panic backtraces. The Makefile links the kernel once, dumps its symbols
+/// with `nm` into the file named by `KERNEL_SYMS`, then links again; code
+/// addresses do not move because the table lives after `.text`. Without
+/// the dump the table is empty and backtraces show raw addresses.
+fn insert_kernel_symbols() -> Result<()> {
+    let mut symbols: Vec<(usize, String)> = Vec::new();
+    if let Ok(path) = std::env::var("KERNEL_SYMS") {
+        println!("cargo:rerun-if-changed={}", path);
+        if let Ok(content) = read_to_string(&path) {
+            for line in content.lines() {
+                // "<address> <type> <demangled name, may contain spaces>"
+                let mut parts = line.splitn(3, ' ');
+                let (addr, kind, name) = match (parts.next(), parts.next(), parts.next()) {
+                    (Some(addr), Some(kind), Some(name)) => (addr, kind, name),
+                    _ => continue,
+                };
+                // skip data and the assembler's local labels
+                if (kind != "t" && kind != "T") || name.starts_with(".L") || name.starts_with('$') {
+                    continue;
+                }
+                if let Ok(addr) = usize::from_str_radix(addr, 16) {
+                    symbols.push((addr, strip_hash(name).to_string()));
+                }
+            }
+        }
+    }
+    symbols.sort();
+    symbols.dedup_by_key(|(addr, _)| *addr);
+
+    let mut f = File::create("src/ksyms.S").unwrap();
+    writeln!(
+        f,
+        r#"
+    .section .rodata
+    .align 3
+    .global _ksyms_num
+_ksyms_num:
+    .quad {}
+    .global _ksyms_addrs
+_ksyms_addrs:"#,
+        symbols.len()
+    )?;
+    for (addr, _) in symbols.iter() {
+        writeln!(f, "    .quad {:#x}", addr)?;
+    }
+    writeln!(
+        f,
+        r#"    .global _ksyms_name_offsets
+_ksyms_name_offsets:"#
+    )?;
+    let mut offset = 0;
+    for (_, name) in symbols.iter() {
+        writeln!(f, "    .quad {}", offset)?;
+        offset += name.len() + 1;
+    }
+    writeln!(
+        f,
+        r#"    .global _ksyms_names
+_ksyms_names:"#
+    )?;
+    for (_, name) in symbols.iter() {
+        writeln!(f, r#"    .string "{}""#, escape(name))?;
+    }
+    Ok(())
+}
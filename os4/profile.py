@@ -0,0 +1,86 @@
+"""Symbolize the `[profile] <app> <pc> <count>` lines printed by a kernel
+built with PROFILE=1 against the app ELFs in ../user/build/elf, and the
+pcs in the kernel against the kernel ELF ($KERNEL_ELF).
+
+Usage: python3 profile.py [LOG]    (reads stdin without LOG)
+"""
+import bisect
+import collections
+import os
+import re
+import subprocess
+import sys
+
+elf_dir = "../user/build/elf"
+kernel_elf = os.getenv("KERNEL_ELF", "target/riscv64gc-unknown-none-elf/release/os")
+# the kernel is linked at 0x80200000, apps far below it
+kernel_base = 0x80000000
+nm = os.getenv("NM", "rust-nm")
+sample_line = re.compile(r"\[profile\] (\S+) (0x[0-9a-f]+) (\d+)")
+rust_hash = re.compile(r"::h[0-9a-f]{16}$")
+top = 10
+
+
+def load_symbols(elf):
+    """Sorted start addresses and names of the text symbols of `elf`."""
+    if not os.path.exists(elf):
+        return [], []
+    output = subprocess.run(
+        [nm, "--defined-only", "--numeric-sort", "--demangle", elf],
+        capture_output=True,
+        text=True,
+    ).stdout
+    addrs, names = [], []
+    for line in output.splitlines():
+        parts = line.split(" ", 2)
+        if len(parts) == 3 and parts[1] in ("t", "T"):
+            addrs.append(int(parts[0], 16))
+            names.append(rust_hash.sub("", parts[2]))
+    return addrs, names
+
+
+def symbolize(symbols, pc):
+    addrs, names = symbols
+    i = bisect.bisect_right(addrs, pc) - 1
+    if i < 0:
+        return "??"
+    return "%s+%#x" % (names[i], pc - addrs[i])
+
+
+def symbolize_sample(app_symbols, kernel_symbols, pc):
+    """A pc of the app or, taken during a syscall, of the kernel."""
+    if pc >= kernel_base:
+        return "[kernel] " + symbolize(kernel_symbols, pc)
+    return symbolize(app_symbols, pc)
+
+
+def main():
+    log = open(sys.argv[1]) if len(sys.argv) > 1 else sys.stdin
+    samples = collections.defaultdict(collections.Counter)
+    for line in log:
+        match = sample_line.search(line)
+        if match:
+            app, pc, count = match.groups()
+            samples[app][int(pc, 16)] += int(count)
+    if not samples:
+        print("no [profile] lines found, was the kernel built with PROFILE=1?")
+        return
+    kernel_symbols = load_symbols(kernel_elf)
+    for app in sorted(samples):
+        app_symbols = load_symbols(os.path.join(elf_dir, app + ".elf"))
+        total = sum(samples[app].values())
+        functions = collections.Counter()
+        for pc, count in samples[app].items():
+            functions[symbolize_sample(app_symbols, kernel_symbols, pc).rsplit("+", 1)[0]] += count
+        print("%s: %d samples" % (app, total))
+        if not app_symbols[0]:
+            print("  (no symbols, %s/%s.elf not found)" % (elf_dir, app))
+        for name, count in functions.most_common(top):
+            print("  %5.1f%% %6d  %s" % (100 * count / total, count, name))
+        print("  hottest pcs:")
+        for pc, count in samples[app].most_common(top):
+            print("  %5.1f%% %6d  %#x %s" % (100 * count / total, count, pc, symbolize_sample(app_symbols, kernel_symbols, pc)))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,118 @@
+//! panic时的内核栈回溯。
+//!
+//! 内核以`-Cforce-frame-pointers=yes`编译，每个栈帧中`fp - 8`处是返回地址，
+//! `fp - 16`处是上一帧的`fp`。返回地址按构建时嵌入的符号表（`ksyms.S`，
+//! 由`build.rs`根据第一遍链接的`nm`输出生成）翻译为函数名。
+
+use crate::config::{kernel_stack_position, BOOT_STACK_SIZE, KERNEL_STACK_SIZE, MAX_HARTS, PAGE_SIZE, TRAMPOLINE};
+use crate::console::print_raw;
+
+core::arch::global_asm!(include_str!("ksyms.S"));
+
+const MAX_FRAMES: usize = 64;
+
+extern "C" {
+    fn _ksyms_num();
+    fn _ksyms_addrs();
+    fn _ksyms_name_offsets();
+    fn _ksyms_names();
+}
+
+fn symbol_count() -> usize {
+    unsafe { (_ksyms_num as usize as *const usize).read_volatile() }
+}
+
+fn symbol_name(index: usize) -> &'static str {
+    unsafe {
+        let offset = *(_ksyms_name_offsets as usize as *const usize).add(index);
+        let start = (_ksyms_names as usize + offset) as *const u8;
+        let mut len = 0;
+        while *start.add(len) != 0 {
+            len += 1;
+        }
+        core::str::from_utf8_unchecked(core::slice::from_raw_parts(start, len))
+    }
+}
+
+/// 查找包含`addr`的函数，返回函数名和`addr`相对函数起始处的偏移
+pub fn lookup(addr: usize) -> Option<(&'static str, usize)> {
+    let addrs = unsafe {
+        core::slice::from_raw_parts(_ksyms_addrs as usize as *const usize, symbol_count())
+    };
+    let index = match addrs.binary_search(&addr) {
+        Ok(index) => index,
+        Err(0) => return None,
+        Err(index) => index - 1,
+    };
+    Some((symbol_name(index), addr - addrs[index]))
+}
+
+/// `sp`所在内核栈的范围：某个hart的启动栈，或某个任务的内核栈
+fn stack_bounds(sp: usize) -> Option<(usize, usize)> {
+    extern "C" {
+        fn boot_stack();
+    }
+    let boot_bottom = boot_stack as usize;
+    if (boot_bottom..=boot_bottom + BOOT_STACK_SIZE * MAX_HARTS).contains(&sp) {
+        let hart = (sp - boot_bottom).saturating_sub(1) / BOOT_STACK_SIZE;
+        let bottom = boot_bottom + hart * BOOT_STACK_SIZE;
+        return Some((bottom, bottom + BOOT_STACK_SIZE));
+    }
+    if sp > TRAMPOLINE {
+        return None;
+    }
+    let app_id = (TRAMPOLINE - sp) / (KERNEL_STACK_SIZE + PAGE_SIZE);
+    let (bottom, top) = kernel_stack_position(app_id);
+    if (bottom..=top).contains(&sp) {
+        Some((bottom, top))
+    } else {
+        None
+    }
+}
+
+/// 沿帧指针链打印当前控制流的调用栈
+#[inline(never)]
+pub fn print_backtrace() {
+    let (mut fp, sp): (usize, usize);
+    unsafe {
+        core::arch::asm!("mv {}, s0", out(reg) fp);
+        core::arch::asm!("mv {}, sp", out(reg) sp);
+    }
+    print_raw(format_args!("[kernel] Backtrace:\n"));
+    if symbol_count() == 0 {
+        print_raw(format_args!("[kernel]   (no symbol table, build the kernel with `make` to embed one)\n"));
+    } else if !matches!(lookup(crate::rust_main as usize), Some(("rust_main", 0))) {
+        print_raw(format_args!("[kernel]   (symbol table does not match this kernel, names may be wrong)\n"));
+    }
+    let (bottom, top) = match stack_bounds(sp) {
+        Some(bounds) => bounds,
+        None => {
+            print_raw(format_args!("[kernel]   sp {:#x} is not on a known kernel stack\n", sp));
+            return;
+        }
+    };
+    for depth in 0..MAX_FRAMES {
+        if fp % 8 != 0 || fp < bottom + 16 || fp > top {
+            break;
+        }
+        let (ra, prev_fp) = unsafe { (*((fp - 8) as *const usize), *((fp - 16) as *const usize)) };
+        if ra == 0 {
+            break;
+        }
+        // `ra`指向call的下一条指令，减一才落在调用者内部
+        match lookup(ra - 1) {
+            Some((name, offset)) => print_raw(format_args!(
+                "[kernel]   #{:<2} {:#018x} {}+{:#x}\n",
+                depth,
+                ra,
+                name,
+                offset + 1
+            )),
+            None => print_raw(format_args!("[kernel]   #{:<2} {:#018x} ?\n", depth, ra)),
+        }
+        if prev_fp <= fp {
+            break;
+        }
+        fp = prev_fp;
+    }
+}
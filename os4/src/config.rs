@@ -1,7 +1,12 @@
 //! Constants used in rCore
 
+/// Initially mapped size of a user stack.
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// Maximum size a user stack may grow to on page faults.
+pub const USER_STACK_LIMIT: usize = 4096 * 256;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 20;
+/// Size of each hart's boot stack, reserved by `entry.asm`.
+pub const BOOT_STACK_SIZE: usize = 4096 * 16;
 pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
 pub const MEMORY_END: usize = 0x80800000;
 pub const PAGE_SIZE: usize = 0x1000;
@@ -18,3 +23,15 @@ pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
 }
 
 pub const CLOCK_FREQ: usize = 12500000;
+/// Memory-mapped device registers on QEMU virt, identically mapped in kernel space.
+pub const MMIO: &[(usize, usize)] = &[
+    (PLIC_BASE, 0x40_0000),
+    (UART_BASE, 0x1000),
+];
+pub const PLIC_BASE: usize = 0x0c00_0000;
+pub const UART_BASE: usize = 0x1000_0000;
+/// PLIC interrupt source of the UART.
+pub const UART_IRQ: u32 = 10;
+/// Maximum number of harts; `entry.asm` reserves a boot stack for each of them
+/// and parks any hart beyond.
+pub const MAX_HARTS: usize = 4;
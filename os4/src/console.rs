@@ -1,25 +1,51 @@
 /*！
-    本模块实现了 print 和 println 宏
+    本模块实现了 print 和 println 宏，以及带行规程的控制台输入
 */
 
-use crate::sbi::console_putchar;
+use crate::drivers::uart;
+use crate::sync::SpinLock;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
 use core::fmt::{self, Write};
+use lazy_static::lazy_static;
 
 struct Stdout;
+/// 不经过发送缓冲区，直接轮询串口输出
+struct RawStdout;
+
+/// 防止多个hart的输出交错在一起
+static STDOUT_LOCK: SpinLock<()> = SpinLock::new("STDOUT", ());
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            console_putchar(c as usize);
-        }
+        uart::write(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl Write for RawStdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        uart::write_raw(s.as_bytes());
         Ok(())
     }
 }
 
 pub fn print(args: fmt::Arguments) {
+    let _guard = STDOUT_LOCK.lock();
     Stdout.write_fmt(args).unwrap();
 }
 
+/// 不经过任何锁直接打印，供锁诊断和panic处理使用
+pub fn print_raw(args: fmt::Arguments) {
+    RawStdout.write_fmt(args).unwrap();
+}
+
+/// 回显输入的原始字节
+fn echo(bytes: &[u8]) {
+    let _guard = STDOUT_LOCK.lock();
+    uart::write(bytes);
+}
+
 #[macro_export]
 macro_rules! print {
     ($fmt: literal $(, $($arg: tt)+)?) => {
@@ -33,3 +59,76 @@ macro_rules! println {
         $crate::console::print(format_args!(concat!($fmt, "\n") $(, $($arg)+)?));
     }
 }
+
+/// 正在编辑的一行最多容纳的字节数，超出的输入被丢弃
+const MAX_LINE: usize = 256;
+const BS: u8 = 0x08;
+const DEL: u8 = 0x7f;
+
+/// 控制台输入的行规程：回显输入、处理退格，按下回车后整行才能被读到
+struct Stdin {
+    /// 正在编辑、尚未提交的一行
+    line: Vec<u8>,
+    /// 已提交、等待读取的字节
+    ready: VecDeque<u8>,
+}
+
+lazy_static! {
+    static ref STDIN: SpinLock<Stdin> = SpinLock::new("STDIN", Stdin {
+        line: Vec::new(),
+        ready: VecDeque::new(),
+    });
+}
+
+impl Stdin {
+    /// 取走串口收到的所有字符
+    fn poll(&mut self) {
+        while let Some(c) = uart::getchar() {
+            self.input(c);
+        }
+    }
+    fn input(&mut self, c: u8) {
+        match c {
+            b'\r' | b'\n' => {
+                echo(b"\n");
+                self.ready.extend(self.line.drain(..));
+                self.ready.push_back(b'\n');
+            }
+            BS | DEL => {
+                if self.line.pop().is_some() {
+                    echo(&[BS, b' ', BS]);
+                }
+            }
+            _ if self.line.len() < MAX_LINE => {
+                self.line.push(c);
+                echo(&[c]);
+            }
+            _ => {}
+        }
+    }
+    /// 读出至多一行，不会越过换行符
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut n = 0;
+        while n < buf.len() {
+            match self.ready.pop_front() {
+                Some(c) => {
+                    buf[n] = c;
+                    n += 1;
+                    if c == b'\n' {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        n
+    }
+}
+
+/// 从控制台读取已提交的输入写入`buf`，返回读到的字节数；
+/// 尚无完整的一行时返回0，由调用者决定是否让出处理器
+pub fn read_stdin(buf: &mut [u8]) -> usize {
+    let mut stdin = STDIN.lock();
+    stdin.poll();
+    stdin.read(buf)
+}
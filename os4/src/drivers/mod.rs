@@ -0,0 +1,30 @@
+//! 设备驱动：QEMU virt上的NS16550A串口及PLIC
+
+mod plic;
+pub mod uart;
+
+use crate::config::UART_IRQ;
+
+/// 启动hart调用：初始化串口并设置中断优先级
+pub fn init() {
+    uart::init();
+    plic::set_priority(UART_IRQ, 1);
+    init_hart();
+}
+
+/// 每个hart调用：在本hart的S态上下文中打开串口中断
+pub fn init_hart() {
+    plic::enable(UART_IRQ);
+    plic::set_threshold(0);
+}
+
+/// 处理PLIC送来的所有外部中断
+pub fn handle_external_interrupt() {
+    while let Some(irq) = plic::claim() {
+        match irq {
+            UART_IRQ => uart::handle_interrupt(),
+            _ => warn!("[kernel] unexpected external interrupt {}", irq),
+        }
+        plic::complete(irq);
+    }
+}
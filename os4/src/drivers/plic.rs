@@ -0,0 +1,57 @@
+//! 平台级中断控制器（PLIC）。QEMU virt上hart `h`的S态上下文编号为`2h + 1`。
+
+use crate::config::PLIC_BASE;
+use crate::smp::hart_id;
+
+const PRIORITY: usize = 0x0;
+const ENABLE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const THRESHOLD: usize = 0x0;
+const CLAIM: usize = 0x4;
+
+fn context() -> usize {
+    2 * hart_id() + 1
+}
+
+fn reg(offset: usize) -> *mut u32 {
+    (PLIC_BASE + offset) as *mut u32
+}
+
+/// 优先级为0的中断源不会被送达
+pub fn set_priority(irq: u32, priority: u32) {
+    unsafe {
+        reg(PRIORITY + 4 * irq as usize).write_volatile(priority);
+    }
+}
+
+/// 允许中断源`irq`送到本hart
+pub fn enable(irq: u32) {
+    let enable = reg(ENABLE + ENABLE_STRIDE * context() + 4 * (irq as usize / 32));
+    unsafe {
+        enable.write_volatile(enable.read_volatile() | 1 << (irq % 32));
+    }
+}
+
+/// 只有优先级高于阈值的中断才会送到本hart
+pub fn set_threshold(threshold: u32) {
+    unsafe {
+        reg(CONTEXT + CONTEXT_STRIDE * context() + THRESHOLD).write_volatile(threshold);
+    }
+}
+
+/// 认领一个待处理的中断，没有时返回`None`
+pub fn claim() -> Option<u32> {
+    match unsafe { reg(CONTEXT + CONTEXT_STRIDE * context() + CLAIM).read_volatile() } {
+        0 => None,
+        irq => Some(irq),
+    }
+}
+
+/// 通知PLIC中断已处理完毕
+pub fn complete(irq: u32) {
+    unsafe {
+        reg(CONTEXT + CONTEXT_STRIDE * context() + CLAIM).write_volatile(irq);
+    }
+}
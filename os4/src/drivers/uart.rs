@@ -0,0 +1,180 @@
+//! NS16550A串口驱动。
+//!
+//! 收发各有一个环形缓冲区：接收中断把字符搬进接收缓冲区；输出先进入发送缓冲区，
+//! 发送保持寄存器空闲时立即写出，写不完的部分由“发送空”中断继续发送。
+//! 内核态不响应中断，因此发送缓冲区满时直接轮询发送。
+
+use crate::config::UART_BASE;
+use crate::sync::SpinLock;
+
+const RBR: usize = 0;
+const THR: usize = 0;
+const IER: usize = 1;
+const FCR: usize = 2;
+const LCR: usize = 3;
+const LSR: usize = 5;
+
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const IER_TX_EMPTY: u8 = 1 << 1;
+const FCR_ENABLE: u8 = 1 << 0;
+const FCR_CLEAR: u8 = 0b11 << 1;
+/// 8位数据位，无校验，1位停止位
+const LCR_8N1: u8 = 0b11;
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_TX_IDLE: u8 = 1 << 5;
+
+const BUFFER_SIZE: usize = 4096;
+
+struct RingBuffer {
+    buf: [u8; BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    const fn new() -> Self {
+        RingBuffer {
+            buf: [0; BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    fn is_full(&self) -> bool {
+        self.len == BUFFER_SIZE
+    }
+    /// 缓冲区满时丢弃`c`并返回false
+    fn push(&mut self, c: u8) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.buf[(self.head + self.len) % BUFFER_SIZE] = c;
+        self.len += 1;
+        true
+    }
+    fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+        let c = self.buf[self.head];
+        self.head = (self.head + 1) % BUFFER_SIZE;
+        self.len -= 1;
+        Some(c)
+    }
+}
+
+struct Uart {
+    base: usize,
+    rx: RingBuffer,
+    tx: RingBuffer,
+}
+
+static UART: SpinLock<Uart> = SpinLock::new("UART", Uart::new(UART_BASE));
+
+impl Uart {
+    const fn new(base: usize) -> Self {
+        Uart {
+            base,
+            rx: RingBuffer::new(),
+            tx: RingBuffer::new(),
+        }
+    }
+    fn read_reg(&self, reg: usize) -> u8 {
+        unsafe { ((self.base + reg) as *const u8).read_volatile() }
+    }
+    fn write_reg(&self, reg: usize, value: u8) {
+        unsafe { ((self.base + reg) as *mut u8).write_volatile(value) }
+    }
+    /// 波特率沿用固件的设置
+    fn init(&mut self) {
+        self.write_reg(IER, 0);
+        self.write_reg(LCR, LCR_8N1);
+        self.write_reg(FCR, FCR_ENABLE | FCR_CLEAR);
+        self.write_reg(IER, IER_RX_AVAILABLE);
+    }
+    /// 把已到达的字符搬进接收缓冲区，缓冲区满时丢弃
+    fn receive(&mut self) {
+        while self.read_reg(LSR) & LSR_DATA_READY != 0 {
+            let c = self.read_reg(RBR);
+            self.rx.push(c);
+        }
+    }
+    /// 在发送保持寄存器空闲时尽量发送，仍有剩余时打开“发送空”中断
+    fn transmit(&mut self) {
+        while !self.tx.is_empty() && self.read_reg(LSR) & LSR_TX_IDLE != 0 {
+            let c = self.tx.pop().unwrap();
+            self.write_reg(THR, c);
+        }
+        let ier = if self.tx.is_empty() {
+            IER_RX_AVAILABLE
+        } else {
+            IER_RX_AVAILABLE | IER_TX_EMPTY
+        };
+        self.write_reg(IER, ier);
+    }
+    fn putchar(&mut self, c: u8) {
+        while self.tx.is_full() {
+            self.transmit();
+        }
+        self.tx.push(c);
+    }
+    fn flush(&mut self) {
+        while !self.tx.is_empty() {
+            self.transmit();
+        }
+    }
+}
+
+pub fn init() {
+    UART.lock().init();
+}
+
+/// 输出一串字节，缓冲区放不下时等待发送
+pub fn write(bytes: &[u8]) {
+    let mut uart = UART.lock();
+    for &c in bytes {
+        uart.putchar(c);
+    }
+    uart.transmit();
+}
+
+/// 取出一个已收到的字符
+pub fn getchar() -> Option<u8> {
+    let mut uart = UART.lock();
+    uart.receive();
+    uart.rx.pop()
+}
+
+/// 等待发送缓冲区中的数据全部写出，关机前调用
+pub fn flush() {
+    UART.lock().flush();
+}
+
+/// 不等待锁地写出发送缓冲区，供panic处理在直接输出之前调用
+pub fn try_flush() {
+    if let Some(mut uart) = UART.try_lock() {
+        uart.flush();
+    }
+}
+
+pub fn handle_interrupt() {
+    let mut uart = UART.lock();
+    uart.receive();
+    uart.transmit();
+}
+
+/// 绕过锁和发送缓冲区直接轮询写出，供持锁时也要输出的诊断使用。
+/// 此时缓冲区中尚未发出的内容会排在这些输出之后。
+pub fn write_raw(bytes: &[u8]) {
+    let reg = |offset: usize| (UART_BASE + offset) as *mut u8;
+    for &c in bytes {
+        unsafe {
+            while reg(LSR).read_volatile() & LSR_TX_IDLE == 0 {
+                core::hint::spin_loop();
+            }
+            reg(THR).write_volatile(c);
+        }
+    }
+}
@@ -1,12 +1,26 @@
     .section .text.entry
     .globl _start
 _start:
-    la sp, boot_stack_top
+    # a0 = hartid, kept in tp for the whole lifetime of the hart
+    mv tp, a0
+    # harts beyond MAX_HARTS have no boot stack, park them
+    li t0, {max_harts}
+    bgeu a0, t0, park
+    # each hart uses its own boot stack: boot_stack + (hartid + 1) * BOOT_STACK_SIZE
+    addi t0, a0, 1
+    li t1, {boot_stack_size}
+    mul t0, t0, t1
+    la sp, boot_stack
+    add sp, sp, t0
     call rust_main
+park:
+    wfi
+    j park
 
     .section .bss.stack
     .globl boot_stack
 boot_stack:
-    .space 4096 * 16
+    # BOOT_STACK_SIZE bytes for each of MAX_HARTS harts
+    .space {boot_stack_size} * {max_harts}
     .globl boot_stack_top
-boot_stack_top:
\ No newline at end of file
+boot_stack_top:
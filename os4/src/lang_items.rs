@@ -1,17 +1,48 @@
+use crate::backtrace::print_backtrace;
+use crate::console::print_raw;
+use crate::drivers::uart;
 use crate::sbi::shutdown;
+use crate::smp::{hart_id, is_online};
+use crate::task::try_current_trap_cx;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
 
+/// 处理panic的过程中再次panic时不再打印诊断信息，直接关机
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// panic时输出锁可能正被持有，因此一律使用`print_raw`
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    if PANICKING.swap(true, Ordering::AcqRel) {
+        print_raw(format_args!("[kernel] Panicked again while panicking, shutting down\n"));
+        shutdown(true)
+    }
+    uart::try_flush();
     if let Some(location) = info.location() {
-        println!(
-            "Panicked at {}:{} {}",
+        print_raw(format_args!(
+            "[kernel] Hart {} panicked at {}:{} {}\n",
+            hart_id(),
             location.file(),
             location.line(),
             info.message().unwrap()
-        );
+        ));
     } else {
-        println!("[kernel] Panicked: {}", info.message().unwrap());
+        print_raw(format_args!(
+            "[kernel] Hart {} panicked: {}\n",
+            hart_id(),
+            info.message().unwrap()
+        ));
+    }
+    // 上线之前任务管理器可能还未初始化，访问它会触发应用装载
+    if is_online() {
+        match try_current_trap_cx() {
+            Some((task_id, trap_cx)) => print_raw(format_args!(
+                "[kernel] Current task: {}, trap context:\n{:?}",
+                task_id, trap_cx
+            )),
+            None => print_raw(format_args!("[kernel] No current task (or its lock is held)\n")),
+        }
     }
-    shutdown()
+    print_backtrace();
+    shutdown(true)
 }
@@ -1,3 +1,6 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
 pub fn get_num_app() -> usize {
     extern "C" {
         fn _num_app();
@@ -20,3 +23,41 @@ pub fn get_app_data(app_id: usize) -> &'static [u8] {
         )
     }
 }
+
+/// 取出从`table`开始、以'\0'分隔的第`n`个字符串
+fn get_nth_str(table: usize, n: usize) -> &'static str {
+    let mut start = table as *const u8;
+    unsafe {
+        for _ in 0..n {
+            while start.read_volatile() != 0 {
+                start = start.add(1);
+            }
+            start = start.add(1);
+        }
+        let mut end = start;
+        while end.read_volatile() != 0 {
+            end = end.add(1);
+        }
+        let len = end as usize - start as usize;
+        core::str::from_utf8(core::slice::from_raw_parts(start, len)).unwrap()
+    }
+}
+
+pub fn get_app_name(app_id: usize) -> &'static str {
+    extern "C" {
+        fn _app_names();
+    }
+    assert!(app_id < get_num_app());
+    get_nth_str(_app_names as usize, app_id)
+}
+
+/// argv of an app: its name followed by the arguments from the app table.
+pub fn get_app_argv(app_id: usize) -> Vec<&'static str> {
+    extern "C" {
+        fn _app_args();
+    }
+    assert!(app_id < get_num_app());
+    let mut argv = vec![get_app_name(app_id)];
+    argv.extend(get_nth_str(_app_args as usize, app_id).split_whitespace());
+    argv
+}
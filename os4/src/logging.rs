@@ -1,41 +1,247 @@
+//! 内核日志。
+//!
+//! 每条记录都写入内存中的环形缓冲区，带有时间戳、hart和任务编号以及模块路径，
+//! 可通过`syslog`系统调用读出（用户程序`dmesg`）。记录哪些日志由按模块设置的
+//! 级别过滤决定，可在运行时修改；其中不低于编译时`LOG`级别的日志同时输出到控制台。
+
+use core::fmt::{self, Write};
 use log::{self, Level, LevelFilter, Log, Metadata, Record};
+use crate::smp::{hart_id, is_online};
+use crate::sync::SpinLock;
+use crate::task::try_current_task_id;
+use crate::timer::get_time_us;
+
+/// 日志缓冲区的大小，写满后丢弃最早的整行
+pub const LOG_BUFFER_SIZE: usize = 16 * 1024;
+/// 最多同时存在的模块过滤规则数
+const MAX_FILTERS: usize = 16;
+/// 过滤规则中模块路径的最大长度
+pub const MAX_MODULE_LEN: usize = 64;
+
+/// 日志缓冲区，始终从某一行的开头开始。
+/// 堆初始化之前就会有日志，所以使用定长数组
+struct LogBuffer {
+    buf: [u8; LOG_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl LogBuffer {
+    const fn new() -> Self {
+        LogBuffer {
+            buf: [0; LOG_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+    fn byte(&self, index: usize) -> u8 {
+        self.buf[(self.head + index) % LOG_BUFFER_SIZE]
+    }
+    /// 丢弃最早的一行
+    fn drop_line(&mut self) {
+        while self.len > 0 {
+            let c = self.byte(0);
+            self.head = (self.head + 1) % LOG_BUFFER_SIZE;
+            self.len -= 1;
+            if c == b'\n' {
+                break;
+            }
+        }
+    }
+    fn push(&mut self, c: u8) {
+        if self.len == LOG_BUFFER_SIZE {
+            self.drop_line();
+        }
+        self.buf[(self.head + self.len) % LOG_BUFFER_SIZE] = c;
+        self.len += 1;
+    }
+    /// 复制最新的内容到`out`，放不下时从能放下的第一整行开始
+    fn copy_to(&self, out: &mut [u8]) -> usize {
+        let mut start = self.len.saturating_sub(out.len());
+        if start > 0 {
+            while start < self.len && self.byte(start - 1) != b'\n' {
+                start += 1;
+            }
+        }
+        let n = self.len - start;
+        for (i, c) in out[..n].iter_mut().enumerate() {
+            *c = self.byte(start + i);
+        }
+        n
+    }
+    fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+impl Write for LogBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.bytes() {
+            self.push(c);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Filter {
+    module: [u8; MAX_MODULE_LEN],
+    len: usize,
+    level: LevelFilter,
+}
+
+impl Filter {
+    fn module(&self) -> &str {
+        core::str::from_utf8(&self.module[..self.len]).unwrap()
+    }
+    /// `target`是该模块本身或其子模块
+    fn matches(&self, target: &str) -> bool {
+        let module = self.module();
+        match target.strip_prefix(module) {
+            Some(rest) => rest.is_empty() || rest.starts_with("::"),
+            None => false,
+        }
+    }
+}
 
-struct SimpleLogger;
+struct Filters {
+    /// 没有匹配规则的模块使用的级别
+    default: LevelFilter,
+    /// 同时输出到控制台的级别，来自编译时的`LOG`
+    console: LevelFilter,
+    rules: [Option<Filter>; MAX_FILTERS],
+}
 
-impl Log for SimpleLogger {
-    fn enabled(&self, _metadata: &Metadata) -> bool {
-        true
+impl Filters {
+    /// 最长匹配的规则决定`target`的级别
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.rules
+            .iter()
+            .flatten()
+            .filter(|rule| rule.matches(target))
+            .max_by_key(|rule| rule.len)
+            .map_or(self.default, |rule| rule.level)
+    }
+    /// 任何模块可能记录的最高级别，交给`log`做快速过滤
+    fn max_level(&self) -> LevelFilter {
+        self.rules
+            .iter()
+            .flatten()
+            .map(|rule| rule.level)
+            .fold(self.default, LevelFilter::max)
+    }
+}
+
+static LOG_BUFFER: SpinLock<LogBuffer> = SpinLock::new("LOG_BUFFER", LogBuffer::new());
+static FILTERS: SpinLock<Filters> = SpinLock::new("LOG_FILTERS", Filters {
+    default: LevelFilter::Info,
+    console: LevelFilter::Off,
+    rules: [None; MAX_FILTERS],
+});
+
+struct KernelLogger;
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= FILTERS.lock().level_for(metadata.target())
     }
     fn log(&self, record: &Record) {
         if !self.enabled(record.metadata()) {
             return;
         }
-        let color = match record.level() {
-            Level::Error => 31, // Red
-            Level::Warn => 93,  // BrightYellow
-            Level::Info => 34,  // Blue
-            Level::Debug => 32, // Green
-            Level::Trace => 90, // BrightBlack
-        };
-        println!(
-            "\u{1B}[{}m[{:>5}] {}\u{1B}[0m",
-            color,
-            record.level(),
-            record.args(),
-        );
+        let us = get_time_us();
+        let hart = hart_id();
+        // 上线之前任务管理器还不存在
+        let task = if is_online() { try_current_task_id() } else { None };
+        let module = record.module_path().unwrap_or_else(|| record.target());
+        {
+            let mut buffer = LOG_BUFFER.lock();
+            write!(buffer, "[{:>5}.{:06}] {:>5} hart{}/", us / 1_000_000, us % 1_000_000, record.level(), hart).unwrap();
+            match task {
+                Some(id) => write!(buffer, "task{}", id).unwrap(),
+                None => write!(buffer, "-").unwrap(),
+            }
+            writeln!(buffer, " {}: {}", module, record.args()).unwrap();
+        }
+        if record.level() <= FILTERS.lock().console {
+            let color = match record.level() {
+                Level::Error => 31, // Red
+                Level::Warn => 93,  // BrightYellow
+                Level::Info => 34,  // Blue
+                Level::Debug => 32, // Green
+                Level::Trace => 90, // BrightBlack
+            };
+            println!(
+                "\u{1B}[{}m[{:>5}] {}\u{1B}[0m",
+                color,
+                record.level(),
+                record.args(),
+            );
+        }
     }
     fn flush(&self) {}
 }
 
 pub fn init() {
-    static LOGGER: SimpleLogger = SimpleLogger;
+    static LOGGER: KernelLogger = KernelLogger;
     log::set_logger(&LOGGER).unwrap();
-    log::set_max_level(match option_env!("LOG") {
+    let console = match option_env!("LOG") {
         Some("ERROR") => LevelFilter::Error,
         Some("WARN") => LevelFilter::Warn,
         Some("INFO") => LevelFilter::Info,
         Some("DEBUG") => LevelFilter::Debug,
         Some("TRACE") => LevelFilter::Trace,
         _ => LevelFilter::Off,
-    });
+    };
+    let mut filters = FILTERS.lock();
+    filters.console = console;
+    filters.default = filters.default.max(console);
+    log::set_max_level(filters.max_level());
+}
+
+/// 设置`module`及其子模块记录的级别，`module`为空时设置默认级别。
+/// 模块路径过长或规则已满时返回false
+pub fn set_level(module: &str, level: LevelFilter) -> bool {
+    let mut filters = FILTERS.lock();
+    if module.is_empty() {
+        filters.default = level;
+    } else if module.len() > MAX_MODULE_LEN {
+        return false;
+    } else if let Some(rule) = filters.rules.iter_mut().flatten().find(|rule| rule.module() == module) {
+        rule.level = level;
+    } else if let Some(slot) = filters.rules.iter_mut().find(|rule| rule.is_none()) {
+        let mut rule = Filter {
+            module: [0; MAX_MODULE_LEN],
+            len: module.len(),
+            level,
+        };
+        rule.module[..module.len()].copy_from_slice(module.as_bytes());
+        *slot = Some(rule);
+    } else {
+        return false;
+    }
+    log::set_max_level(filters.max_level());
+    true
+}
+
+/// 不经过级别过滤，直接向缓冲区追加一行带时间戳的记录
+pub fn record(args: fmt::Arguments) {
+    let us = get_time_us();
+    let mut buffer = LOG_BUFFER.lock();
+    writeln!(buffer, "[{:>5}.{:06}] {}", us / 1_000_000, us % 1_000_000, args).unwrap();
+}
+
+/// 把缓冲区中最新的日志复制到`buf`，返回复制的字节数
+pub fn read_log(buf: &mut [u8]) -> usize {
+    LOG_BUFFER.lock().copy_to(buf)
+}
+
+pub fn clear_log() {
+    LOG_BUFFER.lock().clear();
+}
+
+/// 缓冲区中已有日志的字节数
+pub fn log_len() -> usize {
+    LOG_BUFFER.lock().len
 }
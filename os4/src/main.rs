@@ -2,6 +2,7 @@
 #![no_main]
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
+#![feature(asm_const)]
 
 #[macro_use]
 extern crate bitflags;
@@ -10,21 +11,30 @@ extern crate log;
 
 extern crate alloc;
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
 #[macro_use]
 mod console;
+mod backtrace;
 mod config;
+mod drivers;
 mod lang_items;
 mod loader;
 mod logging;
 mod mm;
 mod sbi;
+mod smp;
 mod sync;
 mod syscall;
 mod task;
 mod timer;
 mod trap;
 
-core::arch::global_asm!(include_str!("entry.asm"));
+core::arch::global_asm!(
+    include_str!("entry.asm"),
+    max_harts = const config::MAX_HARTS,
+    boot_stack_size = const config::BOOT_STACK_SIZE,
+);
 core::arch::global_asm!(include_str!("link_app.S"));
 
 fn clear_bss() {
@@ -38,19 +48,60 @@ fn clear_bss() {
     }
 }
 
+/// 第一个进入内核的hart负责全局初始化，其余hart等它完成后再上线。
+/// 二者放在.data而非.bss中，以免被`clear_bss`清掉。
+#[link_section = ".data"]
+static BOOT_HART_CLAIMED: AtomicBool = AtomicBool::new(false);
+#[link_section = ".data"]
+static BOOT_DONE: AtomicBool = AtomicBool::new(false);
+
 #[no_mangle]
-pub fn rust_main() -> ! {
+pub fn rust_main(hartid: usize) -> ! {
+    if BOOT_HART_CLAIMED
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok()
+    {
+        boot_hart_main()
+    } else {
+        while !BOOT_DONE.load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+        other_hart_main(hartid)
+    }
+}
+
+fn boot_hart_main() -> ! {
     clear_bss();
     logging::init();
     println!("[kernel] Hello, world!");
+    let (major, minor) = sbi::sbi_spec_version();
+    info!("[kernel] SBI specification v{}.{}", major, minor);
     mm::init();
     println!("[kernel] back to world!");
     mm::remap_test();
+    drivers::init();
     trap::init();
     //trap::enable_interrupt();
     trap::enable_timer_interrupt();
+    trap::enable_soft_interrupt();
+    trap::enable_external_interrupt();
+    timer::set_next_trigger();
+    task::init();
+    smp::mark_online();
+    BOOT_DONE.store(true, Ordering::Release);
+    smp::start_other_harts();
+    task::run_tasks();
+}
+
+fn other_hart_main(hartid: usize) -> ! {
+    mm::init_hart();
+    drivers::init_hart();
+    trap::init();
+    trap::enable_timer_interrupt();
+    trap::enable_soft_interrupt();
+    trap::enable_external_interrupt();
     timer::set_next_trigger();
-    println!("aaaaa");
-    task::run_first_task();
-    panic!("Unreachable in rust_main!");
+    smp::mark_online();
+    info!("[kernel] hart {} online", hartid);
+    task::run_tasks();
 }
@@ -1,7 +1,7 @@
 
 use super::{PhysAddr, PhysPageNum};
 use crate::config::MEMORY_END;
-use crate::sync::UPSafeCell;
+use crate::sync::SpinLock;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
 use lazy_static::lazy_static;
@@ -18,25 +18,25 @@ pub struct StackFrameAllocator {
 }
 
 lazy_static! {
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<StackFrameAllocator>
-        = unsafe { UPSafeCell::new(StackFrameAllocator::new())};
+    pub static ref FRAME_ALLOCATOR: SpinLock<StackFrameAllocator>
+        = SpinLock::new("FRAME_ALLOCATOR", StackFrameAllocator::new());
 }
 
 pub fn init_frame_allocator() {
     extern "C" {
         fn ekernel();
     }
-    FRAME_ALLOCATOR.exclusive_access().init(
+    FRAME_ALLOCATOR.lock().init(
         PhysAddr(ekernel as usize).ceil(),
         PhysAddr(MEMORY_END).floor(),
     )
 }
 pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR.exclusive_access()
+    FRAME_ALLOCATOR.lock()
         .alloc().map(FrameTracker::new)
 }
 pub fn frame_dealloc(ppn: PhysPageNum) {
-    FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
+    FRAME_ALLOCATOR.lock().dealloc(ppn);
 }
 
 //--------------------impl structs----------------------//
@@ -1,10 +1,11 @@
 use alloc::rc::Rc;
 use lazy_static::lazy_static;
-use xmas_elf;
+use xmas_elf::{header, program::{self, ProgramHeader}};
 use riscv::register::satp;
-use crate::config::{TRAMPOLINE, PAGE_SIZE, MEMORY_END,
-    USER_STACK_SIZE, TRAP_CONTEXT};
-use crate::sync::UPSafeCell;
+use crate::config::{TRAMPOLINE, PAGE_SIZE, MEMORY_END, MMIO,
+    USER_STACK_SIZE, USER_STACK_LIMIT, TRAP_CONTEXT};
+use crate::sync::SpinLock;
+use crate::timer::get_time;
 use super::{PhysPageNum, VirtAddr, PageTable, VPNRange, 
     VirtPageNum, FrameTracker, PTEFlags, StepByOne,
     PageTableEntry, PhysAddr, frame_alloc};
@@ -35,11 +36,70 @@ pub struct MapArea {
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// 用户栈下方的保护页，栈最多增长到它的上一页
+    stack_guard: VirtPageNum,
+    /// 用户栈顶（不含），栈区为`[stack_guard + 1, stack_top)`
+    stack_top: VirtPageNum,
+}
+
+/// 用户态缺页交给`MemorySet::grow_stack`处理后的结果
+    #[derive(Copy, Clone, PartialEq, Debug)]
+pub enum StackFault {
+    /// 栈已向下增长，重新执行访存指令即可
+    Grown,
+    /// 访问落在保护页上，栈溢出
+    Overflow,
+    /// 与用户栈无关的缺页
+    NotStack,
+}
+
+/// auxv中用到的键，取值与Linux一致
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_ENTRY: usize = 9;
+const AT_RANDOM: usize = 25;
+
+/// 装载ELF失败的原因
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ElfError {
+    /// xmas_elf无法解析
+    Malformed(&'static str),
+    /// 不是64位可执行文件
+    Unsupported,
+    NoLoadSegment,
+    /// 段的文件内容超出ELF数据范围
+    SegmentOutOfFile,
+    /// `p_filesz` > `p_memsz`
+    BadSegmentSize,
+    /// `p_align`不是2的幂，或`p_vaddr`与`p_offset`模`p_align`不等
+    Misaligned,
+    /// 段的地址溢出或与TrapContext/跳板重叠
+    BadAddress,
+    /// 两个段落在同一页内
+    Overlap,
+    /// 入口不在任何可执行段中
+    BadEntry,
+    /// 参数和环境变量放不进初始栈
+    ArgsTooLong,
+}
+
+/// `from_elf`为构造初始栈和TrapContext返回的信息
+pub struct ElfInfo {
+    /// 用户栈顶
+    pub user_sp: VirtAddr,
+    pub entry: VirtAddr,
+    /// 程序头表的虚拟地址，未被装入时为0
+    pub phdr: VirtAddr,
+    pub phent: usize,
+    pub phnum: usize,
 }
 
 lazy_static! {
-    pub static ref KERNEL_SPACE: UPSafeCell<MemorySet> = 
-        unsafe{ UPSafeCell::new(MemorySet::new_kernel()) };
+    pub static ref KERNEL_SPACE: SpinLock<MemorySet> = 
+        SpinLock::new("KERNEL_SPACE", MemorySet::new_kernel());
 }
 
 //-----------------------impl structs------------------------//
@@ -100,6 +160,14 @@ impl MapArea {
             self.unmap_one(page_table, vpn);
         }
     }
+    /// 将区域起点向下扩展到`new_start`，并映射新增的页面
+    pub fn extend_down(&mut self, page_table: &mut PageTable, new_start: VirtPageNum) {
+        let old_start = self.vpn_range.get_start();
+        for vpn in VPNRange::new(new_start, old_start) {
+            self.map_one(page_table, vpn);
+        }
+        self.vpn_range = VPNRange::new(new_start, self.vpn_range.get_end());
+    }
     /// data: must be start-aligned
     /// assume that all frames were cleared before
     pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
@@ -127,6 +195,8 @@ impl MemorySet {
         MemorySet {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            stack_guard: VirtPageNum(0),
+            stack_top: VirtPageNum(0),
         }
     }
     fn map_trampoline(&mut self) {
@@ -191,7 +261,7 @@ impl MemorySet {
                 start.floor(),
                 VirtAddr(start.0 + len).ceil()
         );
-        if self.include(vr) {return -1}
+        if self.include(vr) || self.stack_include(vr) {return -1}
         self.push(
             MapArea::new(
                 start,
@@ -202,6 +272,37 @@ impl MemorySet {
         );
         0
     }
+    /// `vr`是否与预留的用户栈区（含保护页）重叠
+    fn stack_include(&self, vr: VPNRange) -> bool {
+        self.stack_top.0 != 0
+            && VPNRange::new(self.stack_guard, self.stack_top).include(vr)
+    }
+    /// 处理用户栈缺页：`va`落在预留栈区且尚未映射时，将栈向下扩展到`va`所在页
+    pub fn grow_stack(&mut self, va: VirtAddr) -> StackFault {
+        let vpn = va.floor();
+        if self.stack_top.0 == 0 {
+            return StackFault::NotStack;
+        }
+        if vpn == self.stack_guard {
+            return StackFault::Overflow;
+        }
+        if vpn < self.stack_guard || vpn >= self.stack_top {
+            return StackFault::NotStack;
+        }
+        let stack_top = self.stack_top;
+        let area = match self.areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_end() == stack_top) {
+            Some(area) => area,
+            None => return StackFault::NotStack,
+        };
+        if vpn >= area.vpn_range.get_start() {
+            // 已映射的栈页缺页，说明是权限问题
+            return StackFault::NotStack;
+        }
+        area.extend_down(&mut self.page_table, vpn);
+        StackFault::Grown
+    }
     pub fn munmap(&mut self, start: VirtAddr, len: usize) -> isize {
         let vr = VPNRange::new(
                 start.floor(),
@@ -222,6 +323,11 @@ impl MemorySet {
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
+    /// 地址空间占用的物理页帧数，包括页表节点
+    pub fn resident_frames(&self) -> usize {
+        self.areas.iter().map(|area| area.data_frames.len()).sum::<usize>()
+            + self.page_table.frame_count()
+    }
     pub fn new_kernel() -> Self {
         let mut memory_set = Self::new_bare();
         memory_set.map_trampoline();
@@ -275,60 +381,133 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ), None
         );
+        for &(start, len) in MMIO {
+            memory_set.push(
+                MapArea::new(
+                    VirtAddr(start),
+                    VirtAddr(start + len),
+                    MapType::Identical,
+                    MapPermission::R | MapPermission::W,
+                ), None
+            );
+        }
         memory_set
     }
+    /// 检查ELF并返回其中所有`Load`段，不分配任何内存
+    fn check_elf<'a>(elf: &xmas_elf::ElfFile<'a>) -> Result<Vec<ProgramHeader<'a>>, ElfError> {
+        let elf_header = &elf.header;
+        if elf_header.pt1.class() != header::Class::SixtyFour
+            || elf_header.pt2.type_().as_type() != header::Type::Executable
+        {
+            return Err(ElfError::Unsupported);
+        }
+        let mut segments: Vec<ProgramHeader<'a>> = Vec::new();
+        for i in 0..elf_header.pt2.ph_count() {
+            let ph = elf.program_header(i).map_err(ElfError::Malformed)?;
+            if ph.get_type().map_err(ElfError::Malformed)? != program::Type::Load
+                || ph.mem_size() == 0
+            {
+                continue;
+            }
+            let file_end = ph.offset().checked_add(ph.file_size());
+            if file_end.map_or(true, |end| end as usize > elf.input.len()) {
+                return Err(ElfError::SegmentOutOfFile);
+            }
+            if ph.file_size() > ph.mem_size() {
+                return Err(ElfError::BadSegmentSize);
+            }
+            let align = ph.align();
+            if align > 1
+                && (!align.is_power_of_two() || ph.virtual_addr() % align != ph.offset() % align)
+            {
+                return Err(ElfError::Misaligned);
+            }
+            let mem_end = ph.virtual_addr().checked_add(ph.mem_size());
+            if mem_end.map_or(true, |end| end as usize > TRAP_CONTEXT) {
+                return Err(ElfError::BadAddress);
+            }
+            // 以页为单位映射，两个段不能共用同一页
+            let range = Self::segment_range(&ph);
+            if segments.iter().any(|seg| Self::segment_range(seg).include(range)) {
+                return Err(ElfError::Overlap);
+            }
+            segments.push(ph);
+        }
+        if segments.is_empty() {
+            return Err(ElfError::NoLoadSegment);
+        }
+        let entry = elf_header.pt2.entry_point();
+        if !segments.iter().any(|seg| {
+            seg.flags().is_execute()
+                && seg.virtual_addr() <= entry
+                && entry < seg.virtual_addr() + seg.mem_size()
+        }) {
+            return Err(ElfError::BadEntry);
+        }
+        Ok(segments)
+    }
+    fn segment_range(ph: &ProgramHeader) -> VPNRange {
+        VPNRange::new(
+            VirtAddr(ph.virtual_addr() as usize).floor(),
+            VirtAddr((ph.virtual_addr() + ph.mem_size()) as usize).ceil(),
+        )
+    }
     /// Include sections in elf and trampoline and TrapContext and user stack,
-    /// alos returns user_sp and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, VirtAddr, VirtAddr) {
+    /// also returns user_sp, entry point and where the program headers live.
+    pub fn from_elf(elf_data: &[u8]) -> Result<(Self, ElfInfo), ElfError> {
+        let elf = xmas_elf::ElfFile::new(elf_data).map_err(ElfError::Malformed)?;
+        let segments = Self::check_elf(&elf)?;
         let mut memory_set = MemorySet::new_bare();
         memory_set.map_trampoline();
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        let elf_header = elf.header;
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf");
-        let ph_count = elf_header.pt2.ph_count();
+        let ph_offset = elf.header.pt2.ph_offset();
+        let mut phdr = 0;
         let mut max_end_vpn = VirtPageNum(0);
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va = VirtAddr(ph.virtual_addr() as usize);
-                let end_va = VirtAddr((ph.virtual_addr() + ph.mem_size()) as usize);
-                let mut map_perm = MapPermission::U;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() {
-                    map_perm |= MapPermission::R;
-                }
-                if ph_flags.is_write() {
-                    map_perm |= MapPermission::W;
-                }
-                if ph_flags.is_execute() {
-                    map_perm |= MapPermission::X;
-                }
-                let map_area = MapArea::new(
-                    start_va, end_va,
-                    MapType::Framed, map_perm);
-                max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize ..(ph.offset() + ph.file_size()) as usize]),
-                );
+        for ph in segments.iter() {
+            let start_va = VirtAddr(ph.virtual_addr() as usize);
+            let end_va = VirtAddr((ph.virtual_addr() + ph.mem_size()) as usize);
+            let mut map_perm = MapPermission::U;
+            let ph_flags = ph.flags();
+            if ph_flags.is_read() {
+                map_perm |= MapPermission::R;
+            }
+            if ph_flags.is_write() {
+                map_perm |= MapPermission::W;
+            }
+            if ph_flags.is_execute() {
+                map_perm |= MapPermission::X;
+            }
+            let map_area = MapArea::new(
+                start_va, end_va,
+                MapType::Framed, map_perm);
+            max_end_vpn = max_end_vpn.max(map_area.vpn_range.get_end());
+            memory_set.push(map_area, None);
+            // 段不一定页对齐，按实际地址拷贝，并显式清零.bss部分
+            let file_size = ph.file_size() as usize;
+            let data = &elf.input[ph.offset() as usize..ph.offset() as usize + file_size];
+            memory_set.write_user(start_va.0, data);
+            memory_set.zero_user(start_va.0 + file_size, ph.mem_size() as usize - file_size);
+            // 程序头表被某个段装入时，记录它的虚拟地址供AT_PHDR使用
+            if ph.offset() <= ph_offset && ph_offset < ph.offset() + ph.file_size() {
+                phdr = start_va.0 + (ph_offset - ph.offset()) as usize;
             }
         }
         // map user stack with U flags
+        // 布局：程序段 | 保护页 | 栈可增长区（USER_STACK_LIMIT）
+        // 初始只映射栈顶的USER_STACK_SIZE，其余在缺页时按需映射
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.0;
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
+        let stack_guard: usize = max_end_va.0;
+        let user_stack_top = stack_guard + PAGE_SIZE + USER_STACK_LIMIT;
         memory_set.push(
             MapArea::new(
-                VirtAddr(user_stack_bottom),
+                VirtAddr(user_stack_top - USER_STACK_SIZE),
                 VirtAddr(user_stack_top),
                 MapType::Framed,
                 MapPermission::R | MapPermission::W | MapPermission::U,
             ), None,
         );
+        memory_set.stack_guard = VirtAddr(stack_guard).floor();
+        memory_set.stack_top = VirtAddr(user_stack_top).floor();
         // map TrapContext
         memory_set.push(
             MapArea::new(
@@ -338,12 +517,115 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ), None,
         );
-        (
-            memory_set,
-            VirtAddr(user_stack_top),
-            VirtAddr(elf.header.pt2.entry_point() as usize),
-        )
+        let elf_info = ElfInfo {
+            user_sp: VirtAddr(user_stack_top),
+            entry: VirtAddr(elf.header.pt2.entry_point() as usize),
+            phdr: VirtAddr(phdr),
+            phent: elf.header.pt2.ph_entry_size() as usize,
+            phnum: elf.header.pt2.ph_count() as usize,
+        };
+        Ok((memory_set, elf_info))
+    }
+    /// 按System V ABI在用户栈上构造初始栈，自低向高为：
+    /// argc | argv[] | NULL | envp[] | NULL | auxv[] | AT_NULL | AT_RANDOM字节 | 字符串
+    /// 返回(sp, argv, envp)，其中sp指向argc且16字节对齐
+    pub fn init_user_stack(
+        &self,
+        elf_info: &ElfInfo,
+        argv: &[&str],
+        envp: &[&str],
+    ) -> Result<(usize, usize, usize), ElfError> {
+        let word = core::mem::size_of::<usize>();
+        let strings_len: usize = argv.iter().chain(envp.iter()).map(|s| s.len() + 1).sum();
+        let auxv_len = 7;
+        let words = 1 + argv.len() + 1 + envp.len() + 1 + auxv_len * 2;
+        // 两次16字节对齐最多各浪费15字节
+        if strings_len + 16 + words * word + 32 > USER_STACK_SIZE {
+            return Err(ElfError::ArgsTooLong);
+        }
+        let mut sp = elf_info.user_sp.0;
+        let mut envp_ptrs: Vec<usize> = Vec::new();
+        for s in envp.iter() {
+            sp -= s.len() + 1;
+            self.write_user(sp, s.as_bytes());
+            self.zero_user(sp + s.len(), 1);
+            envp_ptrs.push(sp);
+        }
+        let mut argv_ptrs: Vec<usize> = Vec::new();
+        for s in argv.iter() {
+            sp -= s.len() + 1;
+            self.write_user(sp, s.as_bytes());
+            self.zero_user(sp + s.len(), 1);
+            argv_ptrs.push(sp);
+        }
+        sp = (sp - 16) & !0xf;
+        let random = sp;
+        self.write_user(random, &random_bytes());
+        let auxv: [(usize, usize); 7] = [
+            (AT_PHDR, elf_info.phdr.0),
+            (AT_PHENT, elf_info.phent),
+            (AT_PHNUM, elf_info.phnum),
+            (AT_PAGESZ, PAGE_SIZE),
+            (AT_ENTRY, elf_info.entry.0),
+            (AT_RANDOM, random),
+            (AT_NULL, 0),
+        ];
+        let mut table: Vec<usize> = Vec::with_capacity(words);
+        table.push(argv.len());
+        table.extend(argv_ptrs.iter());
+        table.push(0);
+        table.extend(envp_ptrs.iter());
+        table.push(0);
+        for (key, value) in auxv.iter() {
+            table.push(*key);
+            table.push(*value);
+        }
+        sp = (sp - table.len() * word) & !0xf;
+        for (i, value) in table.iter().enumerate() {
+            self.write_user(sp + i * word, &value.to_ne_bytes());
+        }
+        let argv_base = sp + word;
+        let envp_base = argv_base + (argv.len() + 1) * word;
+        Ok((sp, argv_base, envp_base))
+    }
+    /// 把`data`写到用户地址`va`处，所涉及的页面必须已经映射
+    fn write_user(&self, va: usize, data: &[u8]) {
+        let mut done = 0;
+        while done < data.len() {
+            let cur = VirtAddr(va + done);
+            let ppn = self.page_table.translate(cur.floor()).unwrap().ppn();
+            let offset = cur.page_offset();
+            let len = (PAGE_SIZE - offset).min(data.len() - done);
+            ppn.get_bytes_array()[offset..offset + len]
+                .copy_from_slice(&data[done..done + len]);
+            done += len;
+        }
+    }
+    /// 把用户地址`[va, va + len)`清零，所涉及的页面必须已经映射
+    fn zero_user(&self, va: usize, len: usize) {
+        let mut done = 0;
+        while done < len {
+            let cur = VirtAddr(va + done);
+            let ppn = self.page_table.translate(cur.floor()).unwrap().ppn();
+            let offset = cur.page_offset();
+            let n = (PAGE_SIZE - offset).min(len - done);
+            ppn.get_bytes_array()[offset..offset + n].fill(0);
+            done += n;
+        }
+    }
+}
+
+/// 用时钟作种子的xorshift，为AT_RANDOM提供16字节
+fn random_bytes() -> [u8; 16] {
+    let mut x = get_time() as u64 | 1;
+    let mut bytes = [0u8; 16];
+    for chunk in bytes.chunks_mut(8) {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        chunk.copy_from_slice(&x.to_ne_bytes());
     }
+    bytes
 }
 
 extern "C" {
@@ -361,7 +643,7 @@ extern "C" {
 
 #[allow(unused)]
 pub fn remap_test() {
-    let mut kernel_space = KERNEL_SPACE.exclusive_access();
+    let mut kernel_space = KERNEL_SPACE.lock();
     let mid_text = VirtAddr((stext as usize + etext as usize)/2);
     let mid_rodata = VirtAddr((srodata as usize + erodata as usize)/2);
     let mid_data = VirtAddr((sdata as usize + edata as usize)/2);
@@ -11,12 +11,17 @@ pub use frame_allocator::{frame_alloc, FrameTracker};
 pub use page_table::{PageTable, PageTableEntry, PTEFlags, 
     translated_byte_buffer};
 pub use memory_set::{MapPermission, MapArea, MapType,
-    MemorySet, KERNEL_SPACE, remap_test};
+    MemorySet, StackFault, ElfError, ElfInfo, KERNEL_SPACE, remap_test};
 
 
 /// 初始化`heap_allocator`,`frame_allocator`,`kernel_space`
 pub fn init() {
     heap_allocator::init_heap();
     frame_allocator::init_frame_allocator();
-    KERNEL_SPACE.exclusive_access().active();
-}
\ No newline at end of file
+    KERNEL_SPACE.lock().active();
+}
+
+/// 其他hart上线时只需切换到已建好的内核地址空间
+pub fn init_hart() {
+    KERNEL_SPACE.lock().active();
+}
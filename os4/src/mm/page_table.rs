@@ -92,6 +92,11 @@ impl PageTable {
         }
     }
 
+    /// 页表节点占用的物理页帧数
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
     fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
         let mut idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
@@ -1,10 +1,42 @@
 #![allow(unused)]
 
+use core::sync::atomic::{AtomicU8, Ordering};
+use crate::config::MAX_HARTS;
+
+// legacy extensions (SBI v0.1): `a7` holds the function
 const SBI_SET_TIMER: usize = 0;
 const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
+const SBI_SEND_IPI: usize = 4;
 const SBI_SHUTDOWN: usize = 8;
 
+// SBI v0.2+ extensions: `a7` holds the extension id and `a6` the function id
+const SBI_EXT_BASE: usize = 0x10;
+const SBI_EXT_TIME: usize = 0x54494D45;
+const SBI_EXT_IPI: usize = 0x735049;
+const SBI_EXT_RFENCE: usize = 0x52464E43;
+const SBI_EXT_HSM: usize = 0x48534D;
+const SBI_EXT_SRST: usize = 0x53525354;
+const SBI_EXT_DBCN: usize = 0x4442434E;
+
+const BASE_GET_SPEC_VERSION: usize = 0;
+const BASE_PROBE_EXTENSION: usize = 3;
+const RFENCE_REMOTE_FENCE_I: usize = 0;
+const RFENCE_REMOTE_SFENCE_VMA: usize = 1;
+const HSM_HART_START: usize = 0;
+const HSM_HART_STOP: usize = 1;
+const HSM_HART_GET_STATUS: usize = 2;
+const SRST_SYSTEM_RESET: usize = 0;
+const DBCN_CONSOLE_READ: usize = 1;
+const DBCN_CONSOLE_WRITE_BYTE: usize = 2;
+
+const SRST_TYPE_SHUTDOWN: usize = 0;
+const SRST_REASON_NONE: usize = 0;
+const SRST_REASON_SYSTEM_FAILURE: usize = 1;
+
+pub const SBI_SUCCESS: isize = 0;
+pub const SBI_ERR_NOT_SUPPORTED: isize = -2;
+
 #[inline(always)]
 fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     let mut ret;
@@ -21,19 +53,185 @@ fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     ret
 }
 
+/// SBI v0.2 calling convention: `a7` holds the extension id and `a6` the
+/// function id; returns `(error, value)`.
+#[inline(always)]
+fn sbi_call_ext(eid: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize, arg3: usize) -> (isize, usize) {
+    let (error, value);
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("x10") arg0 => error,
+            inlateout("x11") arg1 => value,
+            in("x12") arg2,
+            in("x13") arg3,
+            in("x16") fid,
+            in("x17") eid,
+        );
+    }
+    (error as isize, value)
+}
+
+const UNPROBED: u8 = 0;
+const PRESENT: u8 = 1;
+const ABSENT: u8 = 2;
+
+/// An SBI extension whose presence is probed once and then cached.
+struct Extension {
+    eid: usize,
+    state: AtomicU8,
+}
+
+impl Extension {
+    const fn new(eid: usize) -> Self {
+        Extension {
+            eid,
+            state: AtomicU8::new(UNPROBED),
+        }
+    }
+    fn available(&self) -> bool {
+        match self.state.load(Ordering::Acquire) {
+            PRESENT => true,
+            ABSENT => false,
+            _ => {
+                let present = sbi_probe_extension(self.eid);
+                let state = if present { PRESENT } else { ABSENT };
+                self.state.store(state, Ordering::Release);
+                present
+            }
+        }
+    }
+}
+
+static TIME: Extension = Extension::new(SBI_EXT_TIME);
+static IPI: Extension = Extension::new(SBI_EXT_IPI);
+static RFENCE: Extension = Extension::new(SBI_EXT_RFENCE);
+static HSM: Extension = Extension::new(SBI_EXT_HSM);
+static SRST: Extension = Extension::new(SBI_EXT_SRST);
+static DBCN: Extension = Extension::new(SBI_EXT_DBCN);
+
+/// The SBI specification version as `(major, minor)`; v0.1 firmware has no
+/// base extension and reports `(0, 1)`.
+pub fn sbi_spec_version() -> (usize, usize) {
+    match sbi_call_ext(SBI_EXT_BASE, BASE_GET_SPEC_VERSION, 0, 0, 0, 0) {
+        (SBI_SUCCESS, version) => ((version >> 24) & 0x7f, version & 0xff_ffff),
+        _ => (0, 1),
+    }
+}
+
+pub fn sbi_probe_extension(eid: usize) -> bool {
+    matches!(
+        sbi_call_ext(SBI_EXT_BASE, BASE_PROBE_EXTENSION, eid, 0, 0, 0),
+        (SBI_SUCCESS, value) if value != 0
+    )
+}
+
 pub fn set_timer(timer: usize) {
-    sbi_call(SBI_SET_TIMER, timer, 0, 0);
+    if TIME.available() {
+        sbi_call_ext(SBI_EXT_TIME, 0, timer, 0, 0, 0);
+    } else {
+        sbi_call(SBI_SET_TIMER, timer, 0, 0);
+    }
 }
 
 pub fn console_putchar(c: usize) {
-    sbi_call(SBI_CONSOLE_PUTCHAR, c, 0, 0);
+    if DBCN.available() {
+        sbi_call_ext(SBI_EXT_DBCN, DBCN_CONSOLE_WRITE_BYTE, c & 0xff, 0, 0, 0);
+    } else {
+        sbi_call(SBI_CONSOLE_PUTCHAR, c, 0, 0);
+    }
 }
 
+/// DBCN reads into physical memory, so each hart gets a byte in the
+/// identically mapped kernel image instead of one on its (virtual) stack.
+static mut DBCN_READ_BUFFER: [u8; MAX_HARTS] = [0; MAX_HARTS];
+
+/// Returns `usize::MAX` when no input is available, like the legacy call.
 pub fn console_getchar() -> usize {
-    sbi_call(SBI_CONSOLE_GETCHAR, 0, 0, 0)
+    if DBCN.available() {
+        let buffer = unsafe { &mut DBCN_READ_BUFFER[crate::smp::hart_id()] as *mut u8 as usize };
+        match sbi_call_ext(SBI_EXT_DBCN, DBCN_CONSOLE_READ, 1, buffer, 0, 0) {
+            (SBI_SUCCESS, 1) => unsafe { (buffer as *const u8).read_volatile() as usize },
+            _ => usize::MAX,
+        }
+    } else {
+        sbi_call(SBI_CONSOLE_GETCHAR, 0, 0, 0)
+    }
+}
+
+/// The legacy IPI call takes a pointer to the mask, which must be a
+/// physical address; one slot per hart keeps concurrent senders apart.
+static mut LEGACY_HART_MASK: [usize; MAX_HARTS] = [0; MAX_HARTS];
+
+/// Send a supervisor software interrupt to every hart in `hart_mask`.
+pub fn send_ipi(hart_mask: usize) -> isize {
+    if IPI.available() {
+        sbi_call_ext(SBI_EXT_IPI, 0, hart_mask, 0, 0, 0).0
+    } else {
+        let mask = unsafe {
+            let slot = &mut LEGACY_HART_MASK[crate::smp::hart_id()];
+            *slot = hart_mask;
+            slot as *const usize as usize
+        };
+        sbi_call(SBI_SEND_IPI, mask, 0, 0) as isize
+    }
+}
+
+/// Execute `fence.i` on every hart in `hart_mask`.
+pub fn remote_fence_i(hart_mask: usize) -> isize {
+    if !RFENCE.available() {
+        return SBI_ERR_NOT_SUPPORTED;
+    }
+    sbi_call_ext(SBI_EXT_RFENCE, RFENCE_REMOTE_FENCE_I, hart_mask, 0, 0, 0).0
+}
+
+/// Execute `sfence.vma` for `[start, start + size)` on every hart in
+/// `hart_mask` and wait for them; `size == usize::MAX` flushes everything.
+pub fn remote_sfence_vma(hart_mask: usize, start: usize, size: usize) -> isize {
+    if !RFENCE.available() {
+        return SBI_ERR_NOT_SUPPORTED;
+    }
+    sbi_call_ext(SBI_EXT_RFENCE, RFENCE_REMOTE_SFENCE_VMA, hart_mask, 0, start, size).0
 }
 
-pub fn shutdown() -> ! {
+/// Start `hartid` at physical address `start_addr` with `a1 = opaque`.
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> isize {
+    if !HSM.available() {
+        return SBI_ERR_NOT_SUPPORTED;
+    }
+    sbi_call_ext(SBI_EXT_HSM, HSM_HART_START, hartid, start_addr, opaque, 0).0
+}
+
+/// Stop the calling hart; only returns on failure.
+pub fn hart_stop() -> isize {
+    if !HSM.available() {
+        return SBI_ERR_NOT_SUPPORTED;
+    }
+    sbi_call_ext(SBI_EXT_HSM, HSM_HART_STOP, 0, 0, 0, 0).0
+}
+
+/// HSM state of `hartid` (0 = started, 1 = stopped, ...), or the SBI error.
+pub fn hart_get_status(hartid: usize) -> Result<usize, isize> {
+    if !HSM.available() {
+        return Err(SBI_ERR_NOT_SUPPORTED);
+    }
+    match sbi_call_ext(SBI_EXT_HSM, HSM_HART_GET_STATUS, hartid, 0, 0, 0) {
+        (SBI_SUCCESS, status) => Ok(status),
+        (error, _) => Err(error),
+    }
+}
+
+/// Power off the machine. With SRST the reset reason tells QEMU whether
+/// to exit with a failure status; the legacy call always exits with 0.
+pub fn shutdown(failure: bool) -> ! {
+    if SRST.available() {
+        let reason = if failure {
+            SRST_REASON_SYSTEM_FAILURE
+        } else {
+            SRST_REASON_NONE
+        };
+        sbi_call_ext(SBI_EXT_SRST, SRST_SYSTEM_RESET, SRST_TYPE_SHUTDOWN, reason, 0, 0);
+    }
     sbi_call(SBI_SHUTDOWN, 0, 0, 0);
     panic!("It should shutdown!");
 }
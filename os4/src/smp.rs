@@ -0,0 +1,47 @@
+//! 多hart支持：启动其他hart与hart间中断
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::config::MAX_HARTS;
+use crate::sbi::hart_start;
+
+/// 已上线hart的位图
+static HARTS_ONLINE: AtomicUsize = AtomicUsize::new(0);
+
+/// 当前hart的id，入口处保存在`tp`中
+pub fn hart_id() -> usize {
+    let id;
+    unsafe {
+        core::arch::asm!("mv {}, tp", out(reg) id);
+    }
+    id
+}
+
+pub fn mark_online() {
+    HARTS_ONLINE.fetch_or(1 << hart_id(), Ordering::SeqCst);
+}
+
+/// 当前hart是否已完成初始化并上线
+pub fn is_online() -> bool {
+    HARTS_ONLINE.load(Ordering::SeqCst) & (1 << hart_id()) != 0
+}
+
+/// 通过SBI HSM扩展启动其余的hart，它们同样从`_start`进入内核
+pub fn start_other_harts() {
+    extern "C" {
+        fn _start();
+    }
+    let me = hart_id();
+    for hartid in (0..MAX_HARTS).filter(|id| *id != me) {
+        // 不存在或已在运行的hart会返回错误，忽略即可
+        if hart_start(hartid, _start as usize, 0) == 0 {
+            info!("[kernel] starting hart {}", hartid);
+        }
+    }
+}
+
+/// 处理hart间中断：清除`sip.SSIP`
+pub fn handle_ipi() {
+    unsafe {
+        core::arch::asm!("csrc sip, {}", in(reg) 1 << 1);
+    }
+}
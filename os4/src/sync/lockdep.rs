@@ -0,0 +1,150 @@
+//! 锁依赖检查（lockdep），只在`lock-debug`特性下编译。
+//!
+//! 同名的锁属于同一类，`UPSafeCell`的借用也当作加锁处理。每次在持有锁A时获取锁B，就在依赖图中记录
+//! A -> B 及其第一次出现的位置；若此前已能从B走到A，说明两条控制流
+//! 可能以相反的顺序加锁，即存在潜在的死锁，立即打印报告。
+//!
+//! 这里的状态用不受检查的`spin::Mutex`保护，也不分配堆内存，
+//! 因为堆初始化之前就会有锁被获取；报告通过`print_raw`输出，避免再次获取输出锁。
+
+use core::panic::Location;
+use spin::Mutex;
+use super::spinlock::At;
+use crate::config::MAX_HARTS;
+use crate::console::print_raw;
+use crate::smp::hart_id;
+
+pub const NO_CLASS: usize = usize::MAX;
+/// 自旋这么多次仍未拿到锁时报告持有者
+pub const SPIN_WARN: usize = 1 << 24;
+
+const MAX_CLASSES: usize = 64;
+/// 每个hart最多同时持有的锁数量，超出部分不再跟踪
+const MAX_DEPTH: usize = 16;
+
+struct Classes {
+    names: [&'static str; MAX_CLASSES],
+    count: usize,
+}
+
+/// `ORDER[a][b]`：第一次在持有a类锁时获取b类锁的位置
+type OrderGraph = [[Option<&'static Location<'static>>; MAX_CLASSES]; MAX_CLASSES];
+
+struct HeldLocks {
+    classes: [usize; MAX_DEPTH],
+    depth: usize,
+}
+
+static CLASSES: Mutex<Classes> = Mutex::new(Classes {
+    names: [""; MAX_CLASSES],
+    count: 0,
+});
+static ORDER: Mutex<OrderGraph> = Mutex::new([[None; MAX_CLASSES]; MAX_CLASSES]);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NOTHING_HELD: Mutex<HeldLocks> = Mutex::new(HeldLocks {
+    classes: [NO_CLASS; MAX_DEPTH],
+    depth: 0,
+});
+/// 每个hart当前持有的锁类别，按获取顺序排列
+static HELD: [Mutex<HeldLocks>; MAX_HARTS] = [NOTHING_HELD; MAX_HARTS];
+
+/// 按名字查找或分配类别，类别用完后返回`NO_CLASS`，不再跟踪
+pub fn class_of(name: &'static str) -> usize {
+    let mut classes = CLASSES.lock();
+    if let Some(class) = classes.names[..classes.count].iter().position(|n| *n == name) {
+        return class;
+    }
+    if classes.count == MAX_CLASSES {
+        return NO_CLASS;
+    }
+    let class = classes.count;
+    classes.names[class] = name;
+    classes.count += 1;
+    class
+}
+
+fn name_of(class: usize) -> &'static str {
+    CLASSES.lock().names[class]
+}
+
+/// 依赖图中是否存在从`from`到`to`的路径
+fn reaches(order: &OrderGraph, from: usize, to: usize) -> bool {
+    let mut visited: u64 = 1 << from;
+    let mut stack = [0usize; MAX_CLASSES];
+    let mut top = 1;
+    stack[0] = from;
+    while top > 0 {
+        top -= 1;
+        let class = stack[top];
+        if class == to {
+            return true;
+        }
+        for next in 0..MAX_CLASSES {
+            if order[class][next].is_some() && visited & (1 << next) == 0 {
+                visited |= 1 << next;
+                stack[top] = next;
+                top += 1;
+            }
+        }
+    }
+    false
+}
+
+/// 在真正加锁之前调用：记录新的依赖边并检查是否成环
+pub fn acquire(class: usize, location: &'static Location<'static>) {
+    if class == NO_CLASS {
+        return;
+    }
+    let mut held = HELD[hart_id()].lock();
+    let mut order = ORDER.lock();
+    for prev in held.classes[..held.depth].iter().copied() {
+        if prev == class || order[prev][class].is_some() {
+            continue;
+        }
+        if reaches(&order, class, prev) {
+            print_raw(format_args!(
+                "[lockdep] possible deadlock on hart {}: acquiring `{}` at {} while holding `{}`,\n\
+                 [lockdep] but `{}` -> `{}` has been seen before (directly at {})\n",
+                hart_id(),
+                name_of(class),
+                location,
+                name_of(prev),
+                name_of(class),
+                name_of(prev),
+                At(order[class][prev]),
+            ));
+        }
+        order[prev][class] = Some(location);
+    }
+    push_held(&mut held, class);
+}
+
+/// 记录不经等待就拿到的锁（`try_lock`），它不会参与死锁，因此不记录依赖边
+pub fn held(class: usize) {
+    if class == NO_CLASS {
+        return;
+    }
+    push_held(&mut HELD[hart_id()].lock(), class);
+}
+
+fn push_held(held: &mut HeldLocks, class: usize) {
+    if held.depth < MAX_DEPTH {
+        let depth = held.depth;
+        held.classes[depth] = class;
+        held.depth += 1;
+    }
+}
+
+/// 释放锁时调用，锁不一定按获取的逆序释放
+pub fn release(class: usize) {
+    if class == NO_CLASS {
+        return;
+    }
+    let mut held = HELD[hart_id()].lock();
+    let depth = held.depth;
+    if let Some(index) = held.classes[..depth].iter().rposition(|c| *c == class) {
+        held.classes.copy_within(index + 1..depth, index);
+        held.depth -= 1;
+    }
+}
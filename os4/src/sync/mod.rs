@@ -1,2 +1,7 @@
+mod spinlock;
 mod up;
-pub use up::UPSafeCell;
\ No newline at end of file
+#[cfg(feature = "lock-debug")]
+mod lockdep;
+
+pub use spinlock::{SpinLock, SpinLockGuard};
+pub use up::UPSafeCell;
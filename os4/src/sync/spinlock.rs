@@ -0,0 +1,151 @@
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use spin::{Mutex, MutexGuard};
+use crate::smp::hart_id;
+#[cfg(feature = "lock-debug")]
+use super::lockdep;
+
+const NO_HOLDER: usize = usize::MAX;
+
+/// 多hart之间互斥访问的自旋锁。
+/// 内核态始终关闭中断（`sstatus.SIE`为0），持锁期间不会被中断打断，
+/// 因此不需要额外的关中断处理。
+///
+/// 锁会记住持有者所在的hart和加锁位置：同一hart重复加锁必然死锁，
+/// 此时直接panic并给出两次加锁的位置。打开`lock-debug`特性后还会
+/// 检查加锁顺序（见`lockdep`），并在长时间等锁时报告持有者。
+pub struct SpinLock<T> {
+    name: &'static str,
+    /// 持有者所在的hart，未被持有时为`NO_HOLDER`
+    holder: AtomicUsize,
+    /// 持有者加锁的位置
+    location: AtomicPtr<Location<'static>>,
+    /// 在`lockdep`中的类别，首次加锁时按名字分配
+    #[cfg(feature = "lock-debug")]
+    class: AtomicUsize,
+    inner: Mutex<T>,
+}
+
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+    guard: MutexGuard<'a, T>,
+}
+
+impl<T> SpinLock<T> {
+    /// `name`用于诊断信息，同名的锁在`lockdep`中属于同一类
+    pub const fn new(name: &'static str, value: T) -> Self {
+        SpinLock {
+            name,
+            holder: AtomicUsize::new(NO_HOLDER),
+            location: AtomicPtr::new(null_mut()),
+            #[cfg(feature = "lock-debug")]
+            class: AtomicUsize::new(lockdep::NO_CLASS),
+            inner: Mutex::new(value),
+        }
+    }
+    #[track_caller]
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        let location = Location::caller();
+        let hart = hart_id();
+        if self.holder.load(Ordering::Acquire) == hart {
+            panic!(
+                "lock `{}` is already held by this hart (locked at {}), locked again at {}",
+                self.name,
+                self.holder_location(),
+                location,
+            );
+        }
+        #[cfg(feature = "lock-debug")]
+        lockdep::acquire(self.class(), location);
+        let guard = self.acquire();
+        self.holder.store(hart, Ordering::Release);
+        self.location.store(location as *const _ as *mut _, Ordering::Release);
+        SpinLockGuard { lock: self, guard }
+    }
+    /// 不等待地尝试加锁，锁已被持有（包括被本hart持有）时返回`None`。
+    /// 供panic处理等不能阻塞的场合使用
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        let guard = self.inner.try_lock()?;
+        #[cfg(feature = "lock-debug")]
+        lockdep::held(self.class());
+        self.holder.store(hart_id(), Ordering::Release);
+        self.location.store(Location::caller() as *const _ as *mut _, Ordering::Release);
+        Some(SpinLockGuard { lock: self, guard })
+    }
+    #[cfg(not(feature = "lock-debug"))]
+    fn acquire(&self) -> MutexGuard<'_, T> {
+        self.inner.lock()
+    }
+    #[cfg(feature = "lock-debug")]
+    fn acquire(&self) -> MutexGuard<'_, T> {
+        let mut spins: usize = 0;
+        loop {
+            if let Some(guard) = self.inner.try_lock() {
+                return guard;
+            }
+            spins += 1;
+            if spins == lockdep::SPIN_WARN {
+                crate::console::print_raw(format_args!(
+                    "[lockdep] hart {} is still waiting for lock `{}` held by hart {} (locked at {})\n",
+                    hart_id(),
+                    self.name,
+                    self.holder.load(Ordering::Acquire),
+                    self.holder_location(),
+                ));
+            }
+            core::hint::spin_loop();
+        }
+    }
+    #[cfg(feature = "lock-debug")]
+    fn class(&self) -> usize {
+        let class = self.class.load(Ordering::Acquire);
+        if class != lockdep::NO_CLASS {
+            return class;
+        }
+        let class = lockdep::class_of(self.name);
+        self.class.store(class, Ordering::Release);
+        class
+    }
+    fn holder_location(&self) -> At {
+        At(unsafe { self.location.load(Ordering::Acquire).as_ref() })
+    }
+}
+
+/// 打印可能缺失的加锁位置
+pub struct At(pub Option<&'static Location<'static>>);
+
+impl fmt::Display for At {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(location) => write!(f, "{}", location),
+            None => write!(f, "<unknown>"),
+        }
+    }
+}
+
+impl<'a, T> Deref for SpinLockGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T> DerefMut for SpinLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<'a, T> Drop for SpinLockGuard<'a, T> {
+    /// 先清除持有者信息，随后`guard`字段析构时才真正释放锁
+    fn drop(&mut self) {
+        self.lock.location.store(null_mut(), Ordering::Release);
+        self.lock.holder.store(NO_HOLDER, Ordering::Release);
+        #[cfg(feature = "lock-debug")]
+        lockdep::release(self.lock.class.load(Ordering::Acquire));
+    }
+}
@@ -1,17 +1,96 @@
-use core::cell::{RefCell, RefMut};
+use core::cell::{Cell, RefCell, RefMut};
+use core::ops::{Deref, DerefMut};
+use core::panic::Location;
+use super::spinlock::At;
+#[cfg(feature = "lock-debug")]
+use super::lockdep;
 
+/// 只会被一个hart访问的数据，例如每个hart各自的处理器状态。
+/// 内核态始终关闭中断，访问期间不会被打断，因此用`RefCell`检查借用即可。
+///
+/// 借用时会记住借用者的位置：重复借用时直接panic并给出两次借用的位置，
+/// 而不是匿名的"already borrowed"。打开`lock-debug`特性后，借用也和
+/// 自旋锁一样参与`lockdep`的加锁顺序检查。
 pub struct UPSafeCell<T> {
+    name: &'static str,
+    /// 当前借用者的位置
+    location: Cell<Option<&'static Location<'static>>>,
+    /// 在`lockdep`中的类别，首次借用时按名字分配
+    #[cfg(feature = "lock-debug")]
+    class: Cell<usize>,
     inner: RefCell<T>,
 }
+
 unsafe impl<T> Sync for UPSafeCell<T> {}
 
-impl <T> UPSafeCell<T> {
-    pub unsafe fn new(value: T) -> Self {
+pub struct UPSafeRefMut<'a, T> {
+    cell: &'a UPSafeCell<T>,
+    inner: RefMut<'a, T>,
+}
+
+impl<T> UPSafeCell<T> {
+    /// `name`用于诊断信息。调用者需保证只有一个hart会访问它
+    pub unsafe fn new(name: &'static str, value: T) -> Self {
         UPSafeCell {
+            name,
+            location: Cell::new(None),
+            #[cfg(feature = "lock-debug")]
+            class: Cell::new(lockdep::NO_CLASS),
             inner: RefCell::new(value),
         }
     }
-    pub fn exclusive_access(&self) -> RefMut<'_, T> {
-        self.inner.borrow_mut()
+    #[track_caller]
+    pub fn exclusive_access(&self) -> UPSafeRefMut<'_, T> {
+        let location = Location::caller();
+        let inner = match self.inner.try_borrow_mut() {
+            Ok(inner) => inner,
+            Err(_) => panic!(
+                "`{}` is already borrowed (borrowed at {}), borrowed again at {}",
+                self.name,
+                At(self.location.get()),
+                location,
+            ),
+        };
+        #[cfg(feature = "lock-debug")]
+        lockdep::acquire(self.class(), location);
+        self.location.set(Some(location));
+        UPSafeRefMut { cell: self, inner }
+    }
+    /// 已被借用时返回`None`，供panic处理等不能再次panic的场合使用
+    #[track_caller]
+    pub fn try_exclusive_access(&self) -> Option<UPSafeRefMut<'_, T>> {
+        let inner = self.inner.try_borrow_mut().ok()?;
+        #[cfg(feature = "lock-debug")]
+        lockdep::held(self.class());
+        self.location.set(Some(Location::caller()));
+        Some(UPSafeRefMut { cell: self, inner })
+    }
+    #[cfg(feature = "lock-debug")]
+    fn class(&self) -> usize {
+        if self.class.get() == lockdep::NO_CLASS {
+            self.class.set(lockdep::class_of(self.name));
+        }
+        self.class.get()
+    }
+}
+
+impl<'a, T> Deref for UPSafeRefMut<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<'a, T> DerefMut for UPSafeRefMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<'a, T> Drop for UPSafeRefMut<'a, T> {
+    fn drop(&mut self) {
+        self.cell.location.set(None);
+        #[cfg(feature = "lock-debug")]
+        lockdep::release(self.cell.class.get());
     }
-}
\ No newline at end of file
+}
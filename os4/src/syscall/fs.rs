@@ -1,9 +1,55 @@
+use alloc::vec;
+use crate::config::PAGE_SIZE;
+use crate::console::read_stdin;
+use crate::mm::{translated_byte_buffer, VirtAddr};
+use crate::task::{current_user_token, suspend_current_and_run_next, translate};
 
-use crate::mm::translated_byte_buffer;
-use crate::task::current_user_token;
-
+const FD_STDIN: usize = 0;
 const FD_STDOUT: usize = 1;
 
+/// `[buf, buf + len)`经过的每一页是否都已映射
+pub fn user_buffer_mapped(buf: usize, len: usize) -> bool {
+    match buf.checked_add(len - 1) {
+        Some(last) => (buf / PAGE_SIZE..=last / PAGE_SIZE)
+            .all(|page| translate(VirtAddr(page * PAGE_SIZE)).is_some()),
+        None => false,
+    }
+}
+
+/// 把`data`复制到用户地址`buf`处，调用者需先用`user_buffer_mapped`检查
+pub fn copy_to_user(buf: *const u8, data: &[u8]) {
+    let mut copied = 0;
+    for buffer in translated_byte_buffer(current_user_token(), buf, data.len()) {
+        buffer.copy_from_slice(&data[copied..copied + buffer.len()]);
+        copied += buffer.len();
+    }
+}
+
+/// 读标准输入：没有完整的一行时让出处理器，一次至多返回一行
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+    match fd {
+        FD_STDIN => {
+            if len == 0 {
+                return 0;
+            }
+            if !user_buffer_mapped(buf as usize, len) {
+                return -1;
+            }
+            let mut line = vec![0u8; len.min(PAGE_SIZE)];
+            let n = loop {
+                let n = read_stdin(&mut line);
+                if n > 0 {
+                    break n;
+                }
+                suspend_current_and_run_next();
+            };
+            copy_to_user(buf, &line[..n]);
+            n as isize
+        }
+        _ => -1,
+    }
+}
+
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     match fd {
         FD_STDOUT => {
@@ -17,4 +63,4 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
             panic!("Unsupported fd in sys_write!");
         }
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,5 @@
 
+const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_YIELD: usize = 124;
@@ -7,16 +8,36 @@ const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_MMAP: usize = 222;
 const SYSCALL_SET_PRIORITY: usize = 140;
 const SYSCALL_TASK_INFO: usize = 410;
+const SYSCALL_SYSLOG: usize = 116;
+const SYSCALL_LOG_FILTER: usize = 411;
+const SYSCALL_TRACE: usize = 412;
+const SYSCALL_PROFILE: usize = 413;
+const SYSCALL_GETRUSAGE: usize = 165;
 
 mod fs;
+mod syslog;
 mod process;
+mod trace;
 
 use fs::*;
+use syslog::*;
 use process::*;
+use trace::sys_trace;
+use crate::task::{current_trace, TraceMode};
 
 /// handle syscall exception with `syscall_id` and other arguments
 pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+    match current_trace() {
+        Some((task, name, mode)) if mode != TraceMode::Off => {
+            trace::traced_syscall(task, name, mode, syscall_id, args)
+        }
+        _ => dispatch(syscall_id, args),
+    }
+}
+
+fn dispatch(syscall_id: usize, args: [usize; 3]) -> isize {
     match syscall_id {
+        SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
         SYSCALL_YIELD => sys_yield(),
@@ -24,7 +45,12 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         SYSCALL_MMAP => sys_mmap(args[0],args[1], args[2]),
         SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
         SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
-        SYSCALL_TASK_INFO => sys_task_info(args[0] as *mut TaskInfo),
+        SYSCALL_TASK_INFO => sys_task_info(args[0] as *mut TaskInfo, args[1]),
+        SYSCALL_GETRUSAGE => sys_getrusage(args[0] as isize, args[1] as *mut RUsage),
+        SYSCALL_SYSLOG => sys_syslog(args[0], args[1] as *mut u8, args[2]),
+        SYSCALL_LOG_FILTER => sys_log_filter(args[0] as *const u8, args[1], args[2]),
+        SYSCALL_TRACE => sys_trace(args[0] as isize, args[1]),
+        SYSCALL_PROFILE => sys_profile(args[0] as isize, args[1] as *mut ProfileSample, args[2]),
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
     }
 }
\ No newline at end of file
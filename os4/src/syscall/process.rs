@@ -1,26 +1,86 @@
-use crate::{config::MAX_SYSCALL_NUM, task::{TaskStatus, exit_current_and_run_next, 
+use crate::{config::{MAX_SYSCALL_NUM, PAGE_SIZE}, task::{TaskStatus, exit_current_and_run_next, record_exit_code,
     suspend_current_and_run_next, translate, current_syscall_info, current_start_time,
-    current_map_crate, current_munmap}, timer::get_time_us, mm::MapPermission};
+    current_map_crate, current_munmap, current_usage, TaskUsage}, timer::get_time_us, mm::MapPermission};
 use crate::mm::{VirtAddr};
+#[cfg(feature = "profiler")]
+use alloc::vec::Vec;
+use super::fs::{user_buffer_mapped, copy_to_user};
 
 
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct TimeVal {
     pub sec: usize,
     pub usec: usize,
 }
 
+/// 字段按rustc默认布局的顺序排列，不带`#[repr(C)]`的旧程序看到的布局不变
+#[repr(C)]
 #[derive(Clone, Copy)]
 pub struct TaskInfo {
-    pub status: TaskStatus,
-    pub syscall_times: [u32; MAX_SYSCALL_NUM],
     pub time: usize,
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    pub status: TaskStatus,
+}
+
+/// `sys_task_info`的扩展版本，调用者在第二个参数中传入它的大小时才会填写`usage`。
+/// `info`在最前面，因此只认识`TaskInfo`的程序不受影响
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TaskInfoEx {
+    pub info: TaskInfo,
+    pub usage: TaskUsage,
+}
+
+/// 与Linux `struct rusage`布局相同，未统计的字段为0
+#[repr(C)]
+#[derive(Default)]
+pub struct RUsage {
+    pub utime: TimeVal,
+    pub stime: TimeVal,
+    /// 峰值驻留内存，单位KiB
+    pub maxrss: isize,
+    pub ixrss: isize,
+    pub idrss: isize,
+    pub isrss: isize,
+    pub minflt: isize,
+    pub majflt: isize,
+    pub nswap: isize,
+    pub inblock: isize,
+    pub oublock: isize,
+    pub msgsnd: isize,
+    pub msgrcv: isize,
+    pub nsignals: isize,
+    pub nvcsw: isize,
+    pub nivcsw: isize,
+}
+
+const RUSAGE_SELF: isize = 0;
+
+impl TimeVal {
+    fn from_us(us: usize) -> Self {
+        TimeVal {
+            sec: us / 1_000_000,
+            usec: us % 1_000_000,
+        }
+    }
+}
+
+/// 把`value`按字节复制到用户地址`ptr`处，地址无效时返回false
+fn write_to_user<T>(ptr: *mut T, value: &T) -> bool {
+    let size = core::mem::size_of::<T>();
+    if !user_buffer_mapped(ptr as usize, size) {
+        return false;
+    }
+    let bytes = unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size) };
+    copy_to_user(ptr as *const u8, bytes);
+    true
 }
 
 pub fn sys_exit(exit_code: i32) -> ! {
     info!("[kernel] Application exited with code {}", exit_code);
+    record_exit_code(exit_code);
     exit_current_and_run_next();
     panic!("Unreachable in sys_exit!");
 }
@@ -65,7 +125,18 @@ pub fn sys_munmap(start: usize, len: usize) -> isize {
 }
 
 //your job: 引入虚地址后重写 sys_task_info 
-pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
+pub fn sys_task_info(ti: *mut TaskInfo, size: usize) -> isize {
+    if size >= core::mem::size_of::<TaskInfoEx>() {
+        let info = TaskInfoEx {
+            info: TaskInfo {
+                status: TaskStatus::Running,
+                syscall_times: *current_syscall_info(),
+                time: (get_time_us() - current_start_time()) / 1_000,
+            },
+            usage: current_usage(),
+        };
+        return if write_to_user(ti as *mut TaskInfoEx, &info) { 0 } else { -1 };
+    }
     match translate(VirtAddr(ti as usize)) {
         Some(pa) => {
             unsafe { 
@@ -82,6 +153,65 @@ pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
     }
 }
 
+/// 只支持`RUSAGE_SELF`。内核态的时间包括为任务处理系统调用和中断的时间
+pub fn sys_getrusage(who: isize, usage: *mut RUsage) -> isize {
+    if who != RUSAGE_SELF {
+        return -1;
+    }
+    let task_usage = current_usage();
+    let rusage = RUsage {
+        utime: TimeVal::from_us(task_usage.user_time_us),
+        stime: TimeVal::from_us(task_usage.kernel_time_us),
+        maxrss: (task_usage.peak_frames * PAGE_SIZE / 1024) as isize,
+        minflt: task_usage.page_faults as isize,
+        nvcsw: task_usage.voluntary_switches as isize,
+        nivcsw: task_usage.involuntary_switches as isize,
+        ..RUsage::default()
+    };
+    if write_to_user(usage, &rusage) { 0 } else { -1 }
+}
+
 pub fn sys_set_priority(_prio: isize) -> isize {
     -1
-}
\ No newline at end of file
+}
+
+/// `sys_profile`返回的一个采样点
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ProfileSample {
+    pub pc: usize,
+    pub count: usize,
+}
+
+/// 把任务`task`（-1为当前任务）的采样结果按pc升序写入`buf`，至多`len`项，
+/// 返回采样点的总数。任务不存在时返回-1
+#[cfg(feature = "profiler")]
+pub fn sys_profile(task: isize, buf: *mut ProfileSample, len: usize) -> isize {
+    let task = match task {
+        -1 => None,
+        task if task >= 0 => Some(task as usize),
+        _ => return -1,
+    };
+    let samples = match crate::task::task_profile(task) {
+        Some(samples) => samples,
+        None => return -1,
+    };
+    let n = samples.len().min(len);
+    let size = n * core::mem::size_of::<ProfileSample>();
+    if n > 0 && !user_buffer_mapped(buf as usize, size) {
+        return -1;
+    }
+    let out: Vec<ProfileSample> = samples[..n]
+        .iter()
+        .map(|&(pc, count)| ProfileSample { pc, count })
+        .collect();
+    let bytes = unsafe { core::slice::from_raw_parts(out.as_ptr() as *const u8, size) };
+    copy_to_user(buf as *const u8, bytes);
+    samples.len() as isize
+}
+
+/// 内核未启用`profiler`特性
+#[cfg(not(feature = "profiler"))]
+pub fn sys_profile(_task: isize, _buf: *mut ProfileSample, _len: usize) -> isize {
+    -1
+}
@@ -0,0 +1,68 @@
+use alloc::vec;
+use log::LevelFilter;
+use crate::logging::{read_log, clear_log, log_len, set_level, LOG_BUFFER_SIZE, MAX_MODULE_LEN};
+use crate::mm::translated_byte_buffer;
+use crate::task::current_user_token;
+use super::fs::{user_buffer_mapped, copy_to_user};
+
+/// 与Linux `syslog(2)`相同的操作编号
+const SYSLOG_ACTION_READ_ALL: usize = 3;
+const SYSLOG_ACTION_READ_CLEAR: usize = 4;
+const SYSLOG_ACTION_CLEAR: usize = 5;
+const SYSLOG_ACTION_SIZE_UNREAD: usize = 9;
+const SYSLOG_ACTION_SIZE_BUFFER: usize = 10;
+
+/// 读取或清空内核日志缓冲区，读取时返回缓冲区中最新的至多`len`字节
+pub fn sys_syslog(action: usize, buf: *mut u8, len: usize) -> isize {
+    match action {
+        SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_READ_CLEAR => {
+            if len == 0 {
+                return 0;
+            }
+            if !user_buffer_mapped(buf as usize, len) {
+                return -1;
+            }
+            let mut log = vec![0u8; len.min(LOG_BUFFER_SIZE)];
+            let n = read_log(&mut log);
+            if action == SYSLOG_ACTION_READ_CLEAR {
+                clear_log();
+            }
+            copy_to_user(buf, &log[..n]);
+            n as isize
+        }
+        SYSLOG_ACTION_CLEAR => {
+            clear_log();
+            0
+        }
+        SYSLOG_ACTION_SIZE_UNREAD => log_len() as isize,
+        SYSLOG_ACTION_SIZE_BUFFER => LOG_BUFFER_SIZE as isize,
+        _ => -1,
+    }
+}
+
+/// 设置模块`module[..len]`的日志级别（0为关闭，1到5依次为ERROR到TRACE），
+/// `len`为0时设置默认级别
+pub fn sys_log_filter(module: *const u8, len: usize, level: usize) -> isize {
+    let level = match level {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        5 => LevelFilter::Trace,
+        _ => return -1,
+    };
+    if len > MAX_MODULE_LEN || (len > 0 && !user_buffer_mapped(module as usize, len)) {
+        return -1;
+    }
+    let mut name = [0u8; MAX_MODULE_LEN];
+    let mut copied = 0;
+    for buffer in translated_byte_buffer(current_user_token(), module, len) {
+        name[copied..copied + buffer.len()].copy_from_slice(buffer);
+        copied += buffer.len();
+    }
+    match core::str::from_utf8(&name[..len]) {
+        Ok(module) if set_level(module, level) => 0,
+        _ => -1,
+    }
+}
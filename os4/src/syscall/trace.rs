@@ -0,0 +1,102 @@
+//! 系统调用跟踪：为打开跟踪的任务记录每次系统调用的参数、返回值和耗时
+
+use core::fmt;
+use super::*;
+use crate::logging::record;
+use crate::task::{set_trace, TraceMode};
+use crate::timer::get_time_us;
+
+/// 系统调用及其参数，按调用号解码
+struct Call {
+    id: usize,
+    args: [usize; 3],
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a0, a1, a2] = self.args;
+        match self.id {
+            SYSCALL_READ => write!(f, "read(fd={}, buf={:#x}, len={})", a0, a1, a2),
+            SYSCALL_WRITE => write!(f, "write(fd={}, buf={:#x}, len={})", a0, a1, a2),
+            SYSCALL_EXIT => write!(f, "exit(code={})", a0 as i32),
+            SYSCALL_YIELD => write!(f, "yield()"),
+            SYSCALL_GET_TIME => write!(f, "get_time(ts={:#x}, tz={})", a0, a1),
+            SYSCALL_MUNMAP => write!(f, "munmap(start={:#x}, len={:#x})", a0, a1),
+            SYSCALL_MMAP => write!(f, "mmap(start={:#x}, len={:#x}, prot={})", a0, a1, Prot(a2)),
+            SYSCALL_SET_PRIORITY => write!(f, "set_priority(prio={})", a0 as isize),
+            SYSCALL_TASK_INFO => write!(f, "task_info(ti={:#x}, size={})", a0, a1),
+            SYSCALL_GETRUSAGE => write!(f, "getrusage(who={}, usage={:#x})", a0 as isize, a1),
+            SYSCALL_SYSLOG => write!(f, "syslog(action={}, buf={:#x}, len={})", a0, a1, a2),
+            SYSCALL_LOG_FILTER => write!(f, "log_filter(module={:#x}, len={}, level={})", a0, a1, a2),
+            SYSCALL_TRACE => write!(f, "trace(task={}, mode={})", a0 as isize, a1),
+            SYSCALL_PROFILE => write!(f, "profile(task={}, buf={:#x}, len={})", a0 as isize, a1, a2),
+            id => write!(f, "syscall_{}({:#x}, {:#x}, {:#x})", id, a0, a1, a2),
+        }
+    }
+}
+
+/// `mmap`的权限位：1可读，2可写，4可执行
+struct Prot(usize);
+
+impl fmt::Display for Prot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [(1, "R"), (2, "W"), (4, "X")];
+        let mut first = true;
+        for (bit, name) in names {
+            if self.0 & bit != 0 {
+                write!(f, "{}{}", if first { "" } else { "|" }, name)?;
+                first = false;
+            }
+        }
+        if self.0 & !7 != 0 || first {
+            write!(f, "{}{:#x}", if first { "" } else { "|" }, self.0 & !7)?;
+        }
+        Ok(())
+    }
+}
+
+fn emit(mode: TraceMode, args: fmt::Arguments) {
+    match mode {
+        TraceMode::Off => {}
+        TraceMode::Console => println!("[strace] {}", args),
+        TraceMode::Buffer => record(format_args!("strace: {}", args)),
+    }
+}
+
+/// 执行系统调用并按`mode`记录；`exit`不会返回，因此在调用前记录
+pub fn traced_syscall(task: usize, name: &str, mode: TraceMode, syscall_id: usize, args: [usize; 3]) -> isize {
+    let call = Call { id: syscall_id, args };
+    if syscall_id == SYSCALL_EXIT {
+        emit(mode, format_args!("task {} ({}) {} = ?", task, name, call));
+        return dispatch(syscall_id, args);
+    }
+    let start = get_time_us();
+    let ret = dispatch(syscall_id, args);
+    let duration = get_time_us() - start;
+    emit(mode, format_args!("task {} ({}) {} = {} <{}us>", task, name, call, ret, duration));
+    ret
+}
+
+/// 设置任务的系统调用跟踪：`mode`为0关闭，1输出到控制台，2写入内核日志缓冲区。
+/// `task`为-1时设置当前任务。返回原来的方式，任务不存在时返回-1
+pub fn sys_trace(task: isize, mode: usize) -> isize {
+    let mode = match mode {
+        0 => TraceMode::Off,
+        1 => TraceMode::Console,
+        2 => TraceMode::Buffer,
+        _ => return -1,
+    };
+    let task = if task == -1 {
+        None
+    } else if task >= 0 {
+        Some(task as usize)
+    } else {
+        return -1;
+    };
+    match set_trace(task, mode) {
+        Some(TraceMode::Off) => 0,
+        Some(TraceMode::Console) => 1,
+        Some(TraceMode::Buffer) => 2,
+        None => -1,
+    }
+}
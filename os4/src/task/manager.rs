@@ -1,165 +1,300 @@
-
-
-
 use alloc::boxed::Box;
+use alloc::collections::VecDeque;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use lazy_static::lazy_static;
 
-use super::{TaskControlBlock, TaskStatus, TaskContext,
-    __switch};
-use crate::config::MAX_SYSCALL_NUM;
-use crate::mm::{VirtAddr, PhysAddr, MapPermission};
-use crate::sync::UPSafeCell;
+use super::{TaskControlBlock, TaskStatus, TaskContext, TraceMode, TaskUsage};
+use super::processor::{current_task_id, try_current_task_id, schedule};
+use crate::config::{MAX_SYSCALL_NUM, MAX_HARTS};
+use crate::mm::{VirtAddr, PhysAddr, MapPermission, StackFault};
+use crate::smp::hart_id;
+use crate::sync::SpinLock;
 use crate::timer::get_time_us;
 use crate::trap::TrapContext;
-use crate::loader::{get_num_app, get_app_data};
+use crate::loader::{get_num_app, get_app_data, get_app_argv, get_app_name};
 
 pub struct TaskManagerInner {
     tasks: Vec<TaskControlBlock>,
-    current_task: usize,
+    /// 每个hart一个就绪队列，存放任务在`tasks`中的下标
+    ready_queues: Vec<VecDeque<usize>>,
 }
 
 pub struct TaskManager {
-    num_app: usize,
-    inner: UPSafeCell<TaskManagerInner>,
+    inner: SpinLock<TaskManagerInner>,
+    /// 有应用以非零退出码退出；被内核杀死的应用不算，
+    /// 因为部分测试本来就会触发异常
+    failed: AtomicBool,
+}
+
+/// 跟踪方式不是`Off`的任务数，为0时系统调用不必加锁查询当前任务
+static TRACED_TASKS: AtomicUsize = AtomicUsize::new(0);
+
+/// 任务的跟踪方式由`old`改为`new`时更新`TRACED_TASKS`
+fn count_traced(old: TraceMode, new: TraceMode) {
+    match (old == TraceMode::Off, new == TraceMode::Off) {
+        (true, false) => { TRACED_TASKS.fetch_add(1, Ordering::Relaxed); }
+        (false, true) => { TRACED_TASKS.fetch_sub(1, Ordering::Relaxed); }
+        _ => {}
+    }
 }
 
 lazy_static! {
     pub static ref TASK_MANAGER: TaskManager = {
         info!("init TASK_MANAGER");
-        let num_app = get_num_app();
-        info!("num_app = {}", num_app);
+        info!("num_app = {}", get_num_app());
         let mut tasks: Vec<TaskControlBlock> = Vec::new();
-        for i in 0..num_app {
-            tasks.push(TaskControlBlock::new(get_app_data(i), i));
+        for i in 0..get_num_app() {
+            match TaskControlBlock::new(get_app_data(i), i, &get_app_argv(i)) {
+                Ok(task) => {
+                    count_traced(TraceMode::Off, task.trace);
+                    tasks.push(task);
+                }
+                Err(err) => println!("[kernel] Failed to load app {}: {:?}", get_app_name(i), err),
+            }
         }
+        // 装载失败的应用不参与调度；其余先全部放进启动hart的队列，
+        // 其他hart上线后再从中窃取
+        let mut ready_queues: Vec<VecDeque<usize>> = (0..MAX_HARTS)
+            .map(|_| VecDeque::new())
+            .collect();
+        ready_queues[hart_id()].extend(0..tasks.len());
         TaskManager {
-            num_app,
-            inner: unsafe {
-                UPSafeCell::new(TaskManagerInner {
-                    tasks,
-                    current_task: 0,
-                })
-            },
+            inner: SpinLock::new("TASK_MANAGER", TaskManagerInner {
+                tasks,
+                ready_queues,
+            }),
+            failed: AtomicBool::new(false),
         }
     };
 }
 
 impl TaskManager {
-    fn run_first_task(&self) -> ! {
-        let mut inner = self.inner.exclusive_access();
-        let next_task = &mut inner.tasks[0];
-        next_task.task_status = TaskStatus::Running;
-        next_task.time = get_time_us();
-        let next_task_cx_ptr = &next_task.task_cx as *const TaskContext;
-        drop(inner);
-        let mut _unused = TaskContext::zero_init();
-        // before this, we should drop local variables that dropped manaully
-        unsafe {
-            __switch(&mut _unused as *mut _, next_task_cx_ptr);
+    /// 从本hart的就绪队列取任务；队列为空时从最长的队列末尾窃取一个
+    pub fn fetch_task(&self) -> Option<usize> {
+        let mut inner = self.inner.lock();
+        let hart = hart_id();
+        if let Some(id) = inner.ready_queues[hart].pop_front() {
+            return Some(id);
+        }
+        let victim = (0..MAX_HARTS).max_by_key(|h| inner.ready_queues[*h].len())?;
+        inner.ready_queues[victim].pop_back()
+    }
+    /// 将任务标记为运行中，返回它的上下文
+    pub fn prepare_run(&self, id: usize) -> *const TaskContext {
+        let mut inner = self.inner.lock();
+        let task = &mut inner.tasks[id];
+        task.task_status = TaskStatus::Running;
+        let now = get_time_us();
+        if task.time == 0 {
+            task.time = now;
+        }
+        task.accounted_at = now;
+        &task.task_cx as *const TaskContext
+    }
+    /// 任务让出hart后由空闲控制流调用，仍就绪的任务放回本hart的队列
+    pub fn put_back(&self, id: usize) {
+        let mut inner = self.inner.lock();
+        if inner.tasks[id].task_status == TaskStatus::Ready {
+            inner.ready_queues[hart_id()].push_back(id);
         }
-        panic!("unreachable in run_first_tasks!");
+    }
+    pub fn all_exited(&self) -> bool {
+        let inner = self.inner.lock();
+        inner.tasks
+            .iter()
+            .all(|task| task.task_status == TaskStatus::Exited)
+    }
+    pub fn any_failed(&self) -> bool {
+        self.failed.load(Ordering::Acquire)
     }
 
-    fn mark_current_suspended(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].task_status = TaskStatus::Ready;
-    }
-    fn mark_current_exited(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].task_status = TaskStatus::Exited;
-    }
-    fn find_next_task(&self) -> Option<usize> {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        (current + 1..=current + self.num_app)
-            .map(|id| id % self.num_app)
-            .find(|id| inner.tasks[*id].task_status == TaskStatus::Ready)
+    /// 当前任务让出hart：更新状态，把这段内核态时间记账，并按原因计数
+    fn mark_current(&self, status: TaskStatus, reason: SwitchReason) -> *mut TaskContext {
+        let current = current_task_id();
+        let mut inner = self.inner.lock();
+        let task = &mut inner.tasks[current];
+        task.task_status = status;
+        let now = get_time_us();
+        task.usage.kernel_time_us += now - task.accounted_at;
+        task.accounted_at = now;
+        match reason {
+            SwitchReason::Voluntary => task.usage.voluntary_switches += 1,
+            SwitchReason::Preempted => task.usage.involuntary_switches += 1,
+            SwitchReason::Exited => {}
+        }
+        &mut task.task_cx as *mut TaskContext
+    }
+    /// 从用户态陷入内核：上一个记账点以来的时间属于用户态
+    fn account_trap_enter(&self) {
+        let current = current_task_id();
+        let mut inner = self.inner.lock();
+        let task = &mut inner.tasks[current];
+        let now = get_time_us();
+        task.usage.user_time_us += now - task.accounted_at;
+        task.accounted_at = now;
+    }
+    /// 即将返回用户态：上一个记账点以来的时间属于内核态
+    fn account_trap_return(&self) {
+        let current = current_task_id();
+        let mut inner = self.inner.lock();
+        let task = &mut inner.tasks[current];
+        let now = get_time_us();
+        task.usage.kernel_time_us += now - task.accounted_at;
+        task.accounted_at = now;
+    }
+    fn current_usage(&self) -> TaskUsage {
+        let current = current_task_id();
+        let inner = self.inner.lock();
+        inner.tasks[current].usage
     }
     fn get_current_token(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
+        let current = current_task_id();
+        let inner = self.inner.lock();
         inner.tasks[current].get_user_token()
     }
-    fn get_current_trap_cx(&self) -> &mut TrapContext {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
+    fn get_current_trap_cx(&self) -> &'static mut TrapContext {
+        let current = current_task_id();
+        let inner = self.inner.lock();
         inner.tasks[current].get_trap_cx()
     }
     fn current_start_time(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
+        let current = current_task_id();
+        let inner = self.inner.lock();
         inner.tasks[current].time
     }
     fn current_syscall_plus(&self, syscall: usize) {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
+        let current = current_task_id();
+        let mut inner = self.inner.lock();
         inner.tasks[current].syscall_times[syscall] += 1;
     }
     fn current_syscall_info(&self) -> Box<[u32; MAX_SYSCALL_NUM]>{
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
+        let current = current_task_id();
+        let inner = self.inner.lock();
         inner.tasks[current].syscall_times.clone()
     }
+    /// 当前任务的编号、名字和跟踪方式
+    fn current_trace(&self) -> (usize, &'static str, TraceMode) {
+        let current = current_task_id();
+        let inner = self.inner.lock();
+        let task = &inner.tasks[current];
+        (current, task.name, task.trace)
+    }
+    /// 设置任务`id`的跟踪方式并返回原来的方式，`id`为`None`时设置当前任务
+    fn set_trace(&self, id: Option<usize>, mode: TraceMode) -> Option<TraceMode> {
+        let id = id.unwrap_or_else(current_task_id);
+        let mut inner = self.inner.lock();
+        let task = inner.tasks.get_mut(id)?;
+        let old = core::mem::replace(&mut task.trace, mode);
+        count_traced(old, mode);
+        Some(old)
+    }
+    #[cfg(feature = "profiler")]
+    fn current_profile_sample(&self, pc: usize) {
+        let current = current_task_id();
+        let mut inner = self.inner.lock();
+        *inner.tasks[current].profile.entry(pc).or_insert(0) += 1;
+    }
+    /// 任务`id`（`None`为当前任务）的采样结果，按pc升序排列
+    #[cfg(feature = "profiler")]
+    fn profile(&self, id: Option<usize>) -> Option<(&'static str, Vec<(usize, usize)>)> {
+        let id = id.unwrap_or_else(current_task_id);
+        let inner = self.inner.lock();
+        let task = inner.tasks.get(id)?;
+        Some((task.name, task.profile.iter().map(|(pc, count)| (*pc, *count)).collect()))
+    }
     fn translate_addr_current_unchecked(&self, va: VirtAddr) -> Option<PhysAddr> {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
+        let current = current_task_id();
+        let inner = self.inner.lock();
         inner.tasks[current].memory_set.translate_addr_unchecked(va)
     }
     fn current_map_crate(&self, start: VirtAddr, len: usize, port: MapPermission) -> isize {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        inner.tasks[current].memory_set.map_create(start, len, port)
+        let current = current_task_id();
+        let mut inner = self.inner.lock();
+        let task = &mut inner.tasks[current];
+        let ret = task.memory_set.map_create(start, len, port);
+        task.update_peak_frames();
+        ret
     }
     fn current_munmap(&self, start: VirtAddr, len: usize) -> isize {
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
+        let current = current_task_id();
+        let mut inner = self.inner.lock();
         inner.tasks[current].memory_set.munmap(start, len)
     }
-    fn run_next_task(&self) {
-        if let Some(next) = self.find_next_task() {
-            let mut inner = self.inner.exclusive_access();
-            let current = inner.current_task;
-            inner.tasks[next].task_status = TaskStatus::Running;
-            if inner.tasks[next].time == 0 {
-                inner.tasks[next].time = get_time_us();
-            } 
-            inner.current_task = next;
-            let current_task_cx_ptr = &mut inner.tasks[current].task_cx as *mut TaskContext;
-            let next_task_cx_ptr = &inner.tasks[next].task_cx as *const TaskContext;
-            drop(inner);
-            
-            unsafe {
-                __switch(current_task_cx_ptr, next_task_cx_ptr);
-            }
-        } else {
-            panic!("All application completed!");
-        }
+    fn current_stack_fault(&self, va: VirtAddr) -> StackFault {
+        let current = current_task_id();
+        let mut inner = self.inner.lock();
+        let task = &mut inner.tasks[current];
+        task.usage.page_faults += 1;
+        let fault = task.memory_set.grow_stack(va);
+        task.update_peak_frames();
+        fault
     }
 }
 
 
-fn run_next_task() {
-    TASK_MANAGER.run_next_task();
-}
-fn mark_current_suspended() {
-    TASK_MANAGER.mark_current_suspended();
+/// 装载所有应用，须在其他hart上线前调用，以免它们看到尚未映射的内核栈
+pub fn init() {
+    lazy_static::initialize(&TASK_MANAGER);
 }
-fn mark_current_exited() {
-    TASK_MANAGER.mark_current_exited();
+/// 记录当前应用的退出码，用于关机时报告测试是否通过
+pub fn record_exit_code(exit_code: i32) {
+    if exit_code != 0 {
+        TASK_MANAGER.failed.store(true, Ordering::Release);
+    }
 }
-pub fn run_first_task() {
-    TASK_MANAGER.run_first_task();
+/// 任务让出hart的原因，用于统计上下文切换
+enum SwitchReason {
+    Voluntary,
+    Preempted,
+    Exited,
 }
+
+/// 当前任务主动让出hart
 pub fn suspend_current_and_run_next() {
-    mark_current_suspended();
-    run_next_task();
+    let task_cx_ptr = TASK_MANAGER.mark_current(TaskStatus::Ready, SwitchReason::Voluntary);
+    schedule(task_cx_ptr);
+}
+/// 当前任务的时间片用完，被抢占
+pub fn preempt_current_and_run_next() {
+    let task_cx_ptr = TASK_MANAGER.mark_current(TaskStatus::Ready, SwitchReason::Preempted);
+    schedule(task_cx_ptr);
 }
 pub fn exit_current_and_run_next() {
-    mark_current_exited();
-    run_next_task();
+    #[cfg(feature = "profiler")]
+    {
+        crate::trap::kernel_sampling_end();
+        dump_current_profile();
+    }
+    let task_cx_ptr = TASK_MANAGER.mark_current(TaskStatus::Exited, SwitchReason::Exited);
+    schedule(task_cx_ptr);
+}
+/// 采样一次当前任务被打断的pc
+#[cfg(feature = "profiler")]
+pub fn profile_sample(pc: usize) {
+    TASK_MANAGER.current_profile_sample(pc);
+}
+#[cfg(feature = "profiler")]
+pub fn task_profile(id: Option<usize>) -> Option<Vec<(usize, usize)>> {
+    TASK_MANAGER.profile(id).map(|(_, samples)| samples)
+}
+/// 任务退出时把采样结果打印出来，供`profile.py`符号化
+#[cfg(feature = "profiler")]
+fn dump_current_profile() {
+    if let Some((name, samples)) = TASK_MANAGER.profile(None) {
+        for (pc, count) in samples {
+            println!("[profile] {} {:#x} {}", name, pc, count);
+        }
+    }
+}
+pub fn account_trap_enter() {
+    TASK_MANAGER.account_trap_enter();
+}
+pub fn account_trap_return() {
+    TASK_MANAGER.account_trap_return();
+}
+pub fn current_usage() -> TaskUsage {
+    TASK_MANAGER.current_usage()
 }
 pub fn current_user_token() -> usize {
     TASK_MANAGER.get_current_token()
@@ -167,6 +302,12 @@ pub fn current_user_token() -> usize {
 pub fn current_trap_cx() -> &'static mut TrapContext {
     TASK_MANAGER.get_current_trap_cx()
 }
+/// 供panic处理使用：不等待任何锁，拿不到锁或当前没有任务时返回`None`
+pub fn try_current_trap_cx() -> Option<(usize, &'static TrapContext)> {
+    let current = try_current_task_id()?;
+    let inner = TASK_MANAGER.inner.try_lock()?;
+    Some((current, inner.tasks[current].get_trap_cx()))
+}
 pub fn translate(va: VirtAddr) -> Option<PhysAddr> {
     TASK_MANAGER.translate_addr_current_unchecked(va)
 }
@@ -176,6 +317,16 @@ pub fn current_syscall_plus(syscall: usize) {
 pub fn current_syscall_info() -> Box<[u32; MAX_SYSCALL_NUM]> {
     TASK_MANAGER.current_syscall_info()
 }
+/// 当前任务的编号、名字和跟踪方式；没有任务被跟踪时不加锁，直接返回`None`
+pub fn current_trace() -> Option<(usize, &'static str, TraceMode)> {
+    if TRACED_TASKS.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    Some(TASK_MANAGER.current_trace())
+}
+pub fn set_trace(id: Option<usize>, mode: TraceMode) -> Option<TraceMode> {
+    TASK_MANAGER.set_trace(id, mode)
+}
 pub fn current_start_time() -> usize {
     TASK_MANAGER.current_start_time()
 }
@@ -184,4 +335,7 @@ pub fn current_map_crate(start: VirtAddr, len: usize, port: MapPermission) -> is
 }
 pub fn current_munmap(start: VirtAddr, len: usize) -> isize {
     TASK_MANAGER.current_munmap(start, len)
-}
\ No newline at end of file
+}
+pub fn current_stack_fault(va: VirtAddr) -> StackFault {
+    TASK_MANAGER.current_stack_fault(va)
+}
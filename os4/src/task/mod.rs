@@ -1,21 +1,19 @@
-
 mod context;
 mod switch;
 mod task;
 mod manager;
+mod processor;
 
 pub use context::TaskContext;
 pub use switch::__switch;
-pub use task::{TaskControlBlock, TaskStatus};
-pub use manager::{TaskManager, TASK_MANAGER,
-    run_first_task, suspend_current_and_run_next,
-    exit_current_and_run_next, current_trap_cx,
+pub use task::{TaskControlBlock, TaskStatus, TraceMode, TaskUsage};
+pub use manager::{TaskManager, TASK_MANAGER, init, record_exit_code,
+    suspend_current_and_run_next, preempt_current_and_run_next,
+    account_trap_enter, account_trap_return, current_usage,
+    exit_current_and_run_next, current_trap_cx, try_current_trap_cx,
     current_user_token, translate, current_syscall_plus,
     current_syscall_info, current_start_time, current_map_crate,
-    current_munmap};
-
-
-
-
-
-
+    current_munmap, current_stack_fault, current_trace, set_trace};
+#[cfg(feature = "profiler")]
+pub use manager::{profile_sample, task_profile};
+pub use processor::{run_tasks, try_current_task_id};
@@ -0,0 +1,96 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::lazy_static;
+
+use super::{TaskContext, __switch, TASK_MANAGER};
+use crate::config::MAX_HARTS;
+use crate::drivers::uart;
+use crate::smp::hart_id;
+use crate::sbi::shutdown;
+use crate::sync::UPSafeCell;
+
+/// 每个hart各自的处理器状态
+pub struct Processor {
+    /// 正在该hart上运行的任务
+    current: Option<usize>,
+    /// 空闲控制流的上下文，任务让出hart时切换回这里
+    idle_task_cx: TaskContext,
+}
+
+lazy_static! {
+    /// 每个hart只访问自己的那一项
+    static ref PROCESSORS: Vec<UPSafeCell<Processor>> = (0..MAX_HARTS)
+        .map(|_| unsafe { UPSafeCell::new("PROCESSOR", Processor::new()) })
+        .collect();
+}
+
+/// 所有任务结束后只让一个hart关机
+static ALL_DONE: AtomicBool = AtomicBool::new(false);
+
+impl Processor {
+    fn new() -> Self {
+        Processor {
+            current: None,
+            idle_task_cx: TaskContext::zero_init(),
+        }
+    }
+    fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
+        &mut self.idle_task_cx as *mut TaskContext
+    }
+}
+
+fn current_processor() -> &'static UPSafeCell<Processor> {
+    &PROCESSORS[hart_id()]
+}
+
+/// 当前hart上正在运行的任务
+pub fn current_task_id() -> usize {
+    current_processor().exclusive_access().current.expect("no task is running on this hart")
+}
+
+/// 不等待任何锁地查询当前任务，供panic处理使用
+pub fn try_current_task_id() -> Option<usize> {
+    current_processor().try_exclusive_access()?.current
+}
+
+/// 每个hart的空闲控制流：取一个任务运行，任务让出hart后再把它放回就绪队列
+pub fn run_tasks() -> ! {
+    loop {
+        if let Some(next) = TASK_MANAGER.fetch_task() {
+            let next_task_cx_ptr = TASK_MANAGER.prepare_run(next);
+            let idle_task_cx_ptr = {
+                let mut processor = current_processor().exclusive_access();
+                processor.current = Some(next);
+                processor.get_idle_task_cx_ptr()
+            };
+            unsafe {
+                __switch(idle_task_cx_ptr, next_task_cx_ptr);
+            }
+            // 已经离开了该任务的内核栈，此时才能让其他hart接手它
+            let prev = current_processor().exclusive_access().current.take().unwrap();
+            TASK_MANAGER.put_back(prev);
+        } else if TASK_MANAGER.all_exited() {
+            if ALL_DONE.compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+                println!("[kernel] All applications completed!");
+                uart::flush();
+                shutdown(TASK_MANAGER.any_failed());
+            }
+            loop {
+                core::hint::spin_loop();
+            }
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// 保存当前任务的上下文，切换回本hart的空闲控制流
+pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
+    // 空闲控制流和其他任务不在当前任务的系统调用里，不采样
+    #[cfg(feature = "profiler")]
+    crate::trap::kernel_sampling_end();
+    let idle_task_cx_ptr = current_processor().exclusive_access().get_idle_task_cx_ptr();
+    unsafe {
+        __switch(switched_task_cx_ptr, idle_task_cx_ptr);
+    }
+}
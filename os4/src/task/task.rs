@@ -1,11 +1,14 @@
 
 use alloc::boxed::Box;
+#[cfg(feature = "profiler")]
+use alloc::collections::BTreeMap;
 
 use super::TaskContext;
 use crate::trap::{trap_handler, TrapContext};
 use crate::config::{kernel_stack_position, TRAP_CONTEXT, MAX_SYSCALL_NUM};
 use crate::mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr, 
-    KERNEL_SPACE};
+    KERNEL_SPACE, ElfError};
+use crate::loader::get_app_name;
 
     #[derive(Copy, Clone, PartialEq)]
 pub enum TaskStatus {
@@ -15,6 +18,43 @@ pub enum TaskStatus {
     Exited,
 }
 
+/// 系统调用跟踪的输出位置
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TraceMode {
+    Off,
+    Console,
+    /// 内核日志缓冲区，可用`dmesg`读出
+    Buffer,
+}
+
+impl TraceMode {
+    /// 编译时`STRACE`中列出的应用（逗号分隔）从一开始就跟踪到控制台
+    fn initial(name: &str) -> Self {
+        match option_env!("STRACE") {
+            Some(apps) if apps.split(',').any(|app| app.trim() == name) => TraceMode::Console,
+            _ => TraceMode::Off,
+        }
+    }
+}
+
+/// 任务的资源使用统计
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct TaskUsage {
+    /// 在用户态运行的时间
+    pub user_time_us: usize,
+    /// 在内核态为它运行的时间，不含它被换下的时间
+    pub kernel_time_us: usize,
+    /// 主动让出处理器（yield、等待输入等）的次数
+    pub voluntary_switches: usize,
+    /// 时间片用完被抢占的次数
+    pub involuntary_switches: usize,
+    /// 处理过的缺页异常次数
+    pub page_faults: usize,
+    /// 地址空间占用物理页帧数（含页表）的峰值
+    pub peak_frames: usize,
+}
+
 /// this block controls current task.
 /// `base_size` -> this task stack's top, that means
 /// the base_size indicates the size of the app space
@@ -22,11 +62,19 @@ pub enum TaskStatus {
 pub struct TaskControlBlock {
     pub task_status: TaskStatus,
     pub task_cx: TaskContext,
+    pub name: &'static str,
+    pub trace: TraceMode,
+    pub usage: TaskUsage,
+    /// 上一次记账的时刻，此后的时间计入用户态还是内核态取决于下一个记账点
+    pub accounted_at: usize,
     pub time: usize,
     pub syscall_times: Box<[u32; MAX_SYSCALL_NUM]>,
     pub memory_set: MemorySet,
     pub trap_cx_ppn: PhysPageNum,
     pub base_size: usize,
+    /// 时钟中断时被打断的pc及其采样次数
+    #[cfg(feature = "profiler")]
+    pub profile: BTreeMap<usize, usize>,
 }
 
 //------------------impl struct--------------------//
@@ -37,9 +85,14 @@ impl TaskControlBlock {
     pub fn get_user_token(&self) -> usize {
         self.memory_set.token()
     }
-    pub fn new(elf_data: &[u8], app_id: usize) -> Self {
+    pub fn update_peak_frames(&mut self) {
+        self.usage.peak_frames = self.usage.peak_frames.max(self.memory_set.resident_frames());
+    }
+    pub fn new(elf_data: &[u8], app_id: usize, argv: &[&str]) -> Result<Self, ElfError> {
         // memory_set with elf program headers/trampoline/trap_context/user_stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, elf_info) = MemorySet::from_elf(elf_data)?;
+        // argc/argv/envp/auxv on the initial user stack
+        let (user_sp, argv_base, envp_base) = memory_set.init_user_stack(&elf_info, argv, &[])?;
         let trap_cx_ppn = memory_set
             .translate(VirtAddr(TRAP_CONTEXT).into())
             .unwrap()
@@ -47,29 +100,43 @@ impl TaskControlBlock {
         let task_status = TaskStatus::Ready;
         // map a kernel-stack in kernel space
         let (kernel_stack_botton, kernel_stack_top) = kernel_stack_position(app_id);
-        KERNEL_SPACE.exclusive_access().insert_framed_area(
+        KERNEL_SPACE.lock().insert_framed_area(
             VirtAddr(kernel_stack_botton),
             VirtAddr(kernel_stack_top),
             MapPermission::R | MapPermission::W,
         );
+        let name = get_app_name(app_id);
         let task_control_block = TaskControlBlock {
             task_status,
             task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+            name,
+            trace: TraceMode::initial(name),
+            usage: TaskUsage {
+                peak_frames: memory_set.resident_frames(),
+                ..TaskUsage::default()
+            },
+            accounted_at: 0,
             time: 0,
             syscall_times: Box::new([0; MAX_SYSCALL_NUM]),
             memory_set,
             trap_cx_ppn,
-            base_size: user_sp.0,
+            base_size: elf_info.user_sp.0,
+            #[cfg(feature = "profiler")]
+            profile: BTreeMap::new(),
         };
         // preapare TrapContext in user space
         let trap_cx = task_control_block.get_trap_cx();
         *trap_cx = TrapContext::app_init_context(
-            entry_point.0,
-            user_sp.0,
-            KERNEL_SPACE.exclusive_access().token(),
+            elf_info.entry.0,
+            user_sp,
+            KERNEL_SPACE.lock().token(),
             kernel_stack_top,
             trap_handler as usize,
         );
-        task_control_block
+        // user_lib::_start(argc, argv) takes them in a0/a1
+        trap_cx.x[10] = argv.len();
+        trap_cx.x[11] = argv_base;
+        trap_cx.x[12] = envp_base;
+        Ok(task_control_block)
     }
 }
\ No newline at end of file
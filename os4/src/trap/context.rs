@@ -1,4 +1,5 @@
 
+use core::fmt;
 use riscv::register::sstatus::{self, Sstatus, SPP};
 
 #[repr(C)]
@@ -34,4 +35,31 @@ impl TrapContext {
         cx.set_sp(sp);
         cx
     }
-}
\ No newline at end of file
+}
+
+/// ABI names of `x0`..`x31`
+const REG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+impl fmt::Debug for TrapContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "sepc: {:#018x}  sstatus: {:#018x}",
+            self.sepc,
+            self.sstatus.bits()
+        )?;
+        for (i, reg) in self.x.iter().enumerate() {
+            write!(f, "{:>4}: {:#018x}", REG_NAMES[i], reg)?;
+            if i % 4 == 3 {
+                writeln!(f)?;
+            } else {
+                write!(f, "  ")?;
+            }
+        }
+        Ok(())
+    }
+}
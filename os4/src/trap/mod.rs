@@ -3,14 +3,39 @@ mod context;
 
 use riscv::register::{utvec::TrapMode, stvec, sie, stval,
     scause::{self, Trap, Exception, Interrupt}};
+#[cfg(feature = "profiler")]
+use riscv::register::{sepc, sstatus};
+#[cfg(feature = "profiler")]
+use core::sync::atomic::{AtomicUsize, Ordering};
 pub use context::TrapContext;
-use crate::{task::{current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next,
-    current_syscall_plus}, syscall::syscall, timer::set_next_trigger};
+use crate::{task::{current_trap_cx, current_user_token, exit_current_and_run_next, preempt_current_and_run_next,
+    current_syscall_plus, current_stack_fault, account_trap_enter, account_trap_return}, syscall::syscall, timer::set_next_trigger};
+use crate::mm::{VirtAddr, StackFault};
+use crate::smp::handle_ipi;
+use crate::drivers::handle_external_interrupt;
 
 
 use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
+#[cfg(feature = "profiler")]
+use crate::config::MAX_HARTS;
+#[cfg(feature = "profiler")]
+use crate::smp::hart_id;
 
 core::arch::global_asm!(include_str!("trap.S"));
+#[cfg(feature = "profiler")]
+core::arch::global_asm!(include_str!("kernel_trap.S"));
+
+/// `sstatus.SPIE`，清掉后`sret`回到内核时仍关着中断
+#[cfg(feature = "profiler")]
+const SSTATUS_SPIE: usize = 1 << 5;
+
+#[cfg(feature = "profiler")]
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_SAMPLE: AtomicUsize = AtomicUsize::new(0);
+/// 每个hart在内核态被时钟中断打断时的pc，0表示没有。
+/// 中断处理不能加锁，先记在这里，回到能加锁的地方再记到当前任务上
+#[cfg(feature = "profiler")]
+static KERNEL_SAMPLES: [AtomicUsize; MAX_HARTS] = [NO_SAMPLE; MAX_HARTS];
 
 pub fn init() {
     set_kernel_trap_entry();
@@ -20,15 +45,79 @@ pub fn enable_timer_interrupt() {
         sie::set_stimer();
     }
 }
+/// 接收hart间中断（S态软件中断）
+pub fn enable_soft_interrupt() {
+    unsafe {
+        sie::set_ssoft();
+    }
+}
+/// 外部中断来自PLIC，目前只有串口
+pub fn enable_external_interrupt() {
+    unsafe {
+        sie::set_sext();
+    }
+}
 #[no_mangle]
 pub fn trap_from_kernel() -> ! {
-    panic!("a trap from kernel!");
+    panic!(
+        "a trap from kernel! scause = {:?}, stval = {:#x}",
+        scause::read().cause(),
+        stval::read()
+    );
 }
+/// 开启`profiler`时内核态的陷入，只有处理系统调用期间才开中断。
+/// 时钟中断采下内核的pc，抢占留到系统调用返回时；其余中断的处理要加锁，
+/// 这次陷入内核期间不再开中断，留到回用户态时处理
+#[cfg(feature = "profiler")]
+#[no_mangle]
+pub fn kernel_trap_handler() {
+    match scause::read().cause() {
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            KERNEL_SAMPLES[hart_id()].store(sepc::read(), Ordering::Relaxed);
+            set_next_trigger();
+        }
+        Trap::Interrupt(_) => unsafe {
+            core::arch::asm!("csrc sstatus, {}", in(reg) SSTATUS_SPIE);
+        },
+        _ => trap_from_kernel(),
+    }
+}
+/// 处理系统调用期间打开内核态中断，让时钟中断也能采到内核里的pc
+#[cfg(feature = "profiler")]
+fn kernel_sampling_begin() {
+    unsafe {
+        sstatus::set_sie();
+    }
+}
+/// 关闭内核态中断，把本hart在内核态的采样记到当前任务上。
+/// 返回期间是否来过时钟中断，来过则当前任务的时间片已经用完
+#[cfg(feature = "profiler")]
+pub fn kernel_sampling_end() -> bool {
+    unsafe {
+        sstatus::clear_sie();
+    }
+    let pc = KERNEL_SAMPLES[hart_id()].swap(0, Ordering::Relaxed);
+    if pc != 0 {
+        crate::task::profile_sample(pc);
+    }
+    pc != 0
+}
+#[cfg(not(feature = "profiler"))]
 fn set_kernel_trap_entry() {
     unsafe {
         stvec::write(trap_from_kernel as usize, TrapMode::Direct);
     }
 }
+/// 内核态会开中断，陷入先经过`__kerneltraps`保存寄存器
+#[cfg(feature = "profiler")]
+fn set_kernel_trap_entry() {
+    extern "C" {
+        fn __kerneltraps();
+    }
+    unsafe {
+        stvec::write(__kerneltraps as usize, TrapMode::Direct);
+    }
+}
 fn set_user_trap_entry() {
     unsafe {
         stvec::write(TRAMPOLINE as usize, TrapMode::Direct);
@@ -37,6 +126,7 @@ fn set_user_trap_entry() {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    account_trap_enter();
     let cx = current_trap_cx();
     let scause = scause::read();
     let stval = stval::read();
@@ -44,14 +134,32 @@ pub fn trap_handler() -> ! {
         Trap::Exception(Exception::UserEnvCall) => {
             cx.sepc += 4;
             current_syscall_plus(cx.x[17]);
+            #[cfg(feature = "profiler")]
+            kernel_sampling_begin();
             cx.x[10] = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]) as usize;
+            #[cfg(feature = "profiler")]
+            if kernel_sampling_end() {
+                preempt_current_and_run_next();
+            }
         },
         Trap::Exception(
-            Exception::StoreFault | 
             Exception::StorePageFault |
             Exception::LoadPageFault
         ) => {
-            error!("[kernel] IllegalInstruction in application, core dumped,");
+            match current_stack_fault(VirtAddr(stval)) {
+                StackFault::Grown => {}
+                StackFault::Overflow => {
+                    error!("[kernel] Stack overflow in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.", stval, cx.sepc);
+                    exit_current_and_run_next();
+                }
+                StackFault::NotStack => {
+                    error!("[kernel] PageFault in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.", stval, cx.sepc);
+                    exit_current_and_run_next();
+                }
+            }
+        }
+        Trap::Exception(Exception::StoreFault) => {
+            error!("[kernel] StoreFault in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.", stval, cx.sepc);
             exit_current_and_run_next();
         }
         Trap::Exception(Exception::IllegalInstruction) => {
@@ -59,8 +167,17 @@ pub fn trap_handler() -> ! {
             exit_current_and_run_next();
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            // 内核里的pc在`kernel_trap_handler`中采样
+            #[cfg(feature = "profiler")]
+            crate::task::profile_sample(cx.sepc);
             set_next_trigger();
-            suspend_current_and_run_next();
+            preempt_current_and_run_next();
+        }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            handle_ipi();
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            handle_external_interrupt();
         }
         _ => panic!(
             "Unsupported trap {:?}, stval = {:#x}!",
@@ -72,6 +189,7 @@ pub fn trap_handler() -> ! {
 }
 #[no_mangle]
 pub fn trap_return() -> ! {
+    account_trap_return();
     set_user_trap_entry();
     let trap_cx_ptr = TRAP_CONTEXT;
     let user_satp = current_user_token();
@@ -61,7 +61,8 @@ impl OSInode {
 lazy_static! {
     /// The root of all inodes, or '/' in short
     pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
+        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone())
+            .unwrap_or_else(|err| panic!("Cannot open the file system: {}", err));
         Arc::new(EasyFileSystem::root_inode(&efs))
     };
 }
@@ -7,8 +7,12 @@ pub const MEMORY_END: usize = 0x88000000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 pub const MAX_SYSCALL_NUM: usize = 500;
+/// Number of disk blocks the filesystem keeps in memory
+pub const BLOCK_CACHE_SIZE: usize = 64;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
 pub const CLOCK_FREQ: usize = 12500000;
-pub const MMIO: &[(usize, usize)] = &[(0x10001000, 0x1000)];
+pub const MMIO: &[(usize, usize)] = &[(0x101000, 0x1000), (0x10001000, 0x1000)];
+/// Base address of the goldfish RTC on the QEMU virt machine
+pub const RTC_BASE: usize = 0x101000;
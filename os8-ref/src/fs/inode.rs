@@ -1,7 +1,11 @@
 use easy_fs::{
     EasyFileSystem,
     Inode,
+    Metadata,
+    TimeSpec,
+    set_block_cache_capacity,
 };
+use crate::config::BLOCK_CACHE_SIZE;
 use crate::drivers::BLOCK_DEVICE;
 use crate::sync::UPSafeCell;
 use alloc::sync::Arc;
@@ -9,6 +13,7 @@ use lazy_static::*;
 use bitflags::*;
 use alloc::vec::Vec;
 use super::File;
+use crate::timer::get_unix_time_ns;
 use crate::mm::UserBuffer;
 
 /// A wrapper around a filesystem inode
@@ -61,11 +66,23 @@ impl OSInode {
 lazy_static! {
     /// The root of all inodes, or '/' in short
     pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
+        set_block_cache_capacity(BLOCK_CACHE_SIZE);
+        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone())
+            .unwrap_or_else(|err| panic!("Cannot open the file system: {}", err));
+        efs.lock().set_clock(wall_clock);
         Arc::new(EasyFileSystem::root_inode(&efs))
     };
 }
 
+/// The current time for inode timestamps
+pub fn wall_clock() -> TimeSpec {
+    let ns = get_unix_time_ns();
+    TimeSpec {
+        sec: ns / 1_000_000_000,
+        nsec: ns % 1_000_000_000,
+    }
+}
+
 /// List all files in the filesystems
 pub fn list_apps() {
     println!("/**** APPS ****");
@@ -101,11 +118,15 @@ impl OpenFlags {
     }
 }
 
-/// Open a file by path
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+/// Open a file by path. Directories can only be opened for reading.
+pub fn open_file(path: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
     let (readable, writable) = flags.read_write();
+    let modifies = writable || flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC);
     if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
+        if let Some(inode) = ROOT_INODE.find(path) {
+            if inode.is_dir() {
+                return None;
+            }
             // clear size
             inode.clear();
             Some(Arc::new(OSInode::new(
@@ -115,7 +136,7 @@ pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
             )))
         } else {
             // create file
-            ROOT_INODE.create(name)
+            ROOT_INODE.create(path)
                 .map(|inode| {
                     Arc::new(OSInode::new(
                         readable,
@@ -125,7 +146,8 @@ pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
                 })
         }
     } else {
-        ROOT_INODE.find(name)
+        ROOT_INODE.find(path)
+            .filter(|inode| !(modifies && inode.is_dir()))
             .map(|inode| {
                 if flags.contains(OpenFlags::TRUNC) {
                     inode.clear();
@@ -139,6 +161,38 @@ pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
     }
 }
 
+/// Create a directory by path
+pub fn make_dir(path: &str) -> bool {
+    ROOT_INODE.mkdir(path).is_some()
+}
+
+/// Remove an empty directory by path
+pub fn remove_dir(path: &str) -> bool {
+    ROOT_INODE.rmdir(path)
+}
+
+/// Create a hard link `new_path` to the file `old_path`
+pub fn link_file(old_path: &str, new_path: &str) -> bool {
+    ROOT_INODE.link(old_path, new_path)
+}
+
+/// Remove a link to a file, which stays readable through open files
+pub fn unlink_file(path: &str) -> bool {
+    ROOT_INODE.unlink(path)
+}
+
+/// Set the access and modification times of a file by path, `None`
+/// leaving one unchanged
+pub fn set_file_times(path: &str, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> bool {
+    match ROOT_INODE.find(path) {
+        Some(inode) => {
+            inode.set_times(atime, mtime);
+            true
+        }
+        None => false,
+    }
+}
+
 impl File for OSInode {
     fn readable(&self) -> bool { self.readable }
     fn writable(&self) -> bool { self.writable }
@@ -166,4 +220,11 @@ impl File for OSInode {
         }
         total_write_size
     }
+    fn metadata(&self) -> Option<Metadata> {
+        Some(self.inner.exclusive_access().inode.metadata())
+    }
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> bool {
+        self.inner.exclusive_access().inode.set_times(atime, mtime);
+        true
+    }
 }
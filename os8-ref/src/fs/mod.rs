@@ -3,6 +3,7 @@ mod inode;
 mod pipe;
 
 use crate::mm::UserBuffer;
+use easy_fs::BLOCK_SZ;
 
 /// The common abstraction of all IO resources
 pub trait File : Send + Sync {
@@ -10,11 +11,24 @@ pub trait File : Send + Sync {
     fn writable(&self) -> bool;
     fn read(&self, buf: UserBuffer) -> usize;
     fn write(&self, buf: UserBuffer) -> usize;
+    /// Whether this is the console terminal
+    fn is_tty(&self) -> bool {
+        false
+    }
+    /// Metadata of the inode, if this is a file on the filesystem
+    fn metadata(&self) -> Option<Metadata> {
+        None
+    }
+    /// Set the access and modification times, leaving those given as
+    /// `None` unchanged; false if not a file on the filesystem
+    fn set_times(&self, _atime: Option<TimeSpec>, _mtime: Option<TimeSpec>) -> bool {
+        false
+    }
 }
 
 /// The stat of a inode
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Stat {
     /// ID of device containing file
     pub dev: u64,
@@ -28,6 +42,45 @@ pub struct Stat {
     pad: [u64; 7],
 }
 
+/// The stat of a inode as returned by `sys_fstat_ex`, with permission
+/// bits in `mode`, the owner, the size and the timestamps
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct StatEx {
+    /// ID of device containing file
+    pub dev: u64,
+    /// inode number
+    pub ino: u64,
+    /// file type and permission bits
+    pub mode: StatMode,
+    /// number of hard links
+    pub nlink: u32,
+    /// user ID of owner
+    pub uid: u32,
+    /// group ID of owner
+    pub gid: u32,
+    /// device ID, if a special file
+    pub rdev: u64,
+    pad1: u64,
+    /// total size in bytes
+    pub size: i64,
+    /// block size for filesystem I/O
+    pub blksize: i32,
+    pad2: i32,
+    /// number of 512-byte blocks allocated
+    pub blocks: i64,
+    /// time of last access
+    pub atime_sec: i64,
+    pub atime_nsec: i64,
+    /// time of last modification
+    pub mtime_sec: i64,
+    pub mtime_nsec: i64,
+    /// time of last status change
+    pub ctime_sec: i64,
+    pub ctime_nsec: i64,
+    unused: [u32; 2],
+}
+
 bitflags! {
     /// The mode of a inode
     /// whether a directory or a file
@@ -37,9 +90,63 @@ bitflags! {
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// permission bits, only reported by `StatEx`
+        const PERM  = 0o7777;
+    }
+}
+
+impl StatMode {
+    fn file_type(metadata: &Metadata) -> Self {
+        if metadata.is_dir {
+            StatMode::DIR
+        } else {
+            StatMode::FILE
+        }
     }
-}    
+}
+
+impl Stat {
+    /// The stat of an inode with the given metadata
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        Self {
+            dev: 0,
+            ino: metadata.inode_id as u64,
+            mode: StatMode::file_type(metadata),
+            nlink: metadata.nlink,
+            pad: [0; 7],
+        }
+    }
+}
+
+impl StatEx {
+    /// The extended stat of an inode with the given metadata
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        Self {
+            dev: 0,
+            ino: metadata.inode_id as u64,
+            mode: StatMode::file_type(metadata)
+                | StatMode::from_bits_truncate(metadata.mode) & StatMode::PERM,
+            nlink: metadata.nlink,
+            uid: metadata.uid,
+            gid: metadata.gid,
+            rdev: 0,
+            pad1: 0,
+            size: metadata.size as i64,
+            blksize: BLOCK_SZ as i32,
+            pad2: 0,
+            blocks: (metadata.blocks as usize * BLOCK_SZ / 512) as i64,
+            atime_sec: metadata.atime.sec as i64,
+            atime_nsec: metadata.atime.nsec as i64,
+            mtime_sec: metadata.mtime.sec as i64,
+            mtime_nsec: metadata.mtime.nsec as i64,
+            ctime_sec: metadata.ctime.sec as i64,
+            ctime_nsec: metadata.ctime.nsec as i64,
+            unused: [0; 2],
+        }
+    }
+}
 
-pub use stdio::{Stdin, Stdout};
-pub use inode::{OSInode, open_file, OpenFlags, list_apps};
+pub use stdio::{poll_console, set_tty_foreground, tty_foreground, Stdin, Stdout};
+pub use inode::{OSInode, open_file, make_dir, remove_dir, link_file, unlink_file, set_file_times, wall_clock, OpenFlags, list_apps};
+pub use easy_fs::{Metadata, TimeSpec};
 pub use pipe::{Pipe, make_pipe};
@@ -1,36 +1,95 @@
 use super::File;
 use crate::mm::{UserBuffer};
 use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
+use crate::sync::UPSafeCell;
+use crate::task::{current_interrupted, send_signal_to_group, suspend_current_and_run_next, SignalFlags};
+use alloc::collections::VecDeque;
+use lazy_static::*;
 
 /// The standard input
 pub struct Stdin;
 /// The standard output
 pub struct Stdout;
 
+const CTRL_C: u8 = 0x03;
+const CTRL_Z: u8 = 0x1a;
+
+/// The console as a terminal: input typed so far and the foreground
+/// process group, which gets the signals of Ctrl-C and Ctrl-Z
+struct Tty {
+    input: VecDeque<u8>,
+    /// 0 before any process group takes the terminal
+    foreground: usize,
+}
+
+lazy_static! {
+    static ref TTY: UPSafeCell<Tty> = unsafe {
+        UPSafeCell::new(Tty {
+            input: VecDeque::new(),
+            foreground: 0,
+        })
+    };
+}
+
+/// Move console input into the terminal buffer, turning control characters
+/// into signals. Called on every timer interrupt so that Ctrl-C reaches a
+/// program that never reads its input.
+pub fn poll_console() {
+    loop {
+        let c = console_getchar();
+        // no input is 0 on some SBI implementations and -1 on others
+        if c == 0 || c == usize::MAX {
+            break;
+        }
+        let c = c as u8;
+        let signal = match c {
+            CTRL_C => SignalFlags::SIGINT,
+            CTRL_Z => SignalFlags::SIGTSTP,
+            _ => {
+                TTY.exclusive_access().input.push_back(c);
+                continue;
+            }
+        };
+        print!("^{}", (c + b'@') as char);
+        let foreground = TTY.exclusive_access().foreground;
+        if foreground != 0 {
+            send_signal_to_group(foreground, signal);
+        }
+    }
+}
+
+pub fn tty_foreground() -> usize {
+    TTY.exclusive_access().foreground
+}
+
+pub fn set_tty_foreground(pgid: usize) {
+    TTY.exclusive_access().foreground = pgid;
+}
+
 impl File for Stdin {
     fn readable(&self) -> bool { true }
     fn writable(&self) -> bool { false }
     fn read(&self, mut user_buf: UserBuffer) -> usize {
         assert_eq!(user_buf.len(), 1);
         // busy loop
-        let mut c: usize;
-        loop {
-            c = console_getchar();
-            if c == 0 {
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                break;
+        let ch = loop {
+            poll_console();
+            if let Some(c) = TTY.exclusive_access().input.pop_front() {
+                break c;
             }
-        }
-        let ch = c as u8;
+            // let a signal from the terminal take effect, e.g. kill the reader
+            if current_interrupted() {
+                return 0;
+            }
+            suspend_current_and_run_next();
+        };
         unsafe { user_buf.buffers[0].as_mut_ptr().write_volatile(ch); }
         1
     }
     fn write(&self, _user_buf: UserBuffer) -> usize {
         panic!("Cannot write to stdin!");
     }
+    fn is_tty(&self) -> bool { true }
 }
 
 impl File for Stdout {
@@ -45,4 +104,5 @@ impl File for Stdout {
         }
         user_buf.len()
     }
+    fn is_tty(&self) -> bool { true }
 }
@@ -215,26 +215,77 @@ impl MemorySet {
             elf.header.pt2.entry_point() as usize,
         )
     }
-    /// Copy an identical user_space
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
+    /// Copy an identical user_space. Pages the user can access are shared
+    /// copy-on-write: both sides map them without `W` and the first store
+    /// copies the frame in [`MemorySet::handle_cow_fault`]. The trap contexts
+    /// are written by the kernel through physical addresses, so they are
+    /// still copied eagerly.
+    pub fn from_existed_user(user_space: &mut MemorySet) -> MemorySet {
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
         // copy data sections/trap_context/user_stack
         for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
-            // copy data from another space
-            for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
-                let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
-                dst_ppn
-                    .get_bytes_array()
-                    .copy_from_slice(src_ppn.get_bytes_array());
+            let mut new_area = MapArea::from_another(area);
+            if !area.map_perm.contains(MapPermission::U) {
+                memory_set.push(new_area, None);
+                // copy data from another space
+                for vpn in area.vpn_range {
+                    let src_ppn = user_space.translate(vpn).unwrap().ppn();
+                    let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
+                    dst_ppn
+                        .get_bytes_array()
+                        .copy_from_slice(src_ppn.get_bytes_array());
+                }
+                continue;
+            }
+            let mut pte_flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+            pte_flags.remove(PTEFlags::W);
+            for (vpn, frame) in area.data_frames.iter() {
+                memory_set.page_table.map(*vpn, frame.ppn, pte_flags);
+                user_space.page_table.unmap(*vpn);
+                user_space.page_table.map(*vpn, frame.ppn, pte_flags);
+                new_area.data_frames.insert(*vpn, Arc::clone(frame));
             }
+            memory_set.areas.push(new_area);
         }
         memory_set
     }
+    /// Resolve a store to a copy-on-write page: copy the frame if it is still
+    /// shared, then map it writable again. Returns false if `vpn` is not a
+    /// copy-on-write page, i.e. the store is a real fault.
+    pub fn handle_cow_fault(&mut self, vpn: VirtPageNum) -> bool {
+        let area = match self.areas.iter_mut().find(|area| {
+            area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end()
+        }) {
+            Some(area) => area,
+            None => return false,
+        };
+        if area.map_type != MapType::Framed || !area.map_perm.contains(MapPermission::W) {
+            return false;
+        }
+        let frame = match area.data_frames.get(&vpn) {
+            Some(frame) => frame,
+            None => return false,
+        };
+        let ppn = if Arc::strong_count(frame) == 1 {
+            // the other side has already copied or gone away
+            frame.ppn
+        } else {
+            let new_frame = frame_alloc().unwrap();
+            new_frame
+                .ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            let ppn = new_frame.ppn;
+            area.data_frames.insert(vpn, Arc::new(new_frame));
+            ppn
+        };
+        let pte_flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+        self.page_table.unmap(vpn);
+        self.page_table.map(vpn, ppn, pte_flags);
+        true
+    }
     pub fn activate(&self) {
         let satp = self.page_table.token();
         unsafe {
@@ -262,7 +313,8 @@ impl MemorySet {
 #[derive(Clone)]
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// frames shared with other memory sets after a fork are copy-on-write
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
 }
@@ -300,7 +352,7 @@ impl MapArea {
             MapType::Framed => {
                 let frame = frame_alloc().unwrap();
                 ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                self.data_frames.insert(vpn, Arc::new(frame));
             }
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
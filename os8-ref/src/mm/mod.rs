@@ -18,7 +18,8 @@ pub use address::{StepByOne, VPNRange};
 pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
 pub use memory_set::{remap_test, kernel_token};
 pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
-pub use page_table::{translated_byte_buffer, translated_refmut, translated_ref, translated_str, PageTableEntry};
+pub use page_table::{translated_byte_buffer, translated_byte_buffer_mut, translated_refmut, translated_ref, translated_str, PageTableEntry};
+pub use page_table::{copy_from_user, copy_to_user, read_user, write_user};
 pub use page_table::{PTEFlags, PageTable, UserBuffer};
 
 /// initiate heap allocator, frame allocator and kernel space
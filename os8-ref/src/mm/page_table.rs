@@ -1,6 +1,7 @@
 //! Implementation of [`PageTableEntry`] and [`PageTable`].
 
 use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use crate::task::current_process;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -144,8 +145,35 @@ impl PageTable {
     }
 }
 
-/// translate a pointer to a mutable u8 Vec through page table
+/// The kernel writes to user memory through physical addresses, which would
+/// bypass the read-only mapping of a copy-on-write page and modify every
+/// process sharing it, so break the sharing first. Only the current process
+/// can have such pages in `page_table`; its PCB must not be borrowed.
+fn prepare_user_write(page_table: &PageTable, vpn: VirtPageNum) -> Option<PageTableEntry> {
+    let pte = page_table.translate(vpn)?;
+    if pte.is_valid() && !pte.writable() && pte.flags().contains(PTEFlags::U) {
+        let process = current_process();
+        let mut inner = process.inner_exclusive_access();
+        if inner.memory_set.token() == page_table.token() && inner.memory_set.handle_cow_fault(vpn) {
+            return page_table.translate(vpn);
+        }
+    }
+    Some(pte)
+}
+
+/// translate a pointer to a mutable u8 Vec through page table, for buffers
+/// the kernel only reads from
 pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+    translate_user_buffer(token, ptr, len, false)
+}
+
+/// Like `translated_byte_buffer`, but for buffers the kernel writes into, so
+/// copy-on-write pages in the range are unshared first
+pub fn translated_byte_buffer_mut(token: usize, ptr: *mut u8, len: usize) -> Vec<&'static mut [u8]> {
+    translate_user_buffer(token, ptr, len, true)
+}
+
+fn translate_user_buffer(token: usize, ptr: *const u8, len: usize, write: bool) -> Vec<&'static mut [u8]> {
     let page_table = PageTable::from_token(token);
     let mut start = ptr as usize;
     let end = start + len;
@@ -153,7 +181,11 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
+        let ppn = if write {
+            prepare_user_write(&page_table, vpn).unwrap().ppn()
+        } else {
+            page_table.translate(vpn).unwrap().ppn()
+        };
         vpn.step();
         let mut end_va: VirtAddr = vpn.into();
         end_va = end_va.min(VirtAddr::from(end));
@@ -195,6 +227,7 @@ pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
     //println!("into translated_refmut!");
     let page_table = PageTable::from_token(token);
     let va = ptr as usize;
+    prepare_user_write(&page_table, VirtAddr::from(va).floor());
     //println!("translated_refmut: before translate_va");
     page_table
         .translate_va(VirtAddr::from(va))
@@ -202,6 +235,93 @@ pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
         .get_mut()
 }
 
+/// Split the user range `[va, va + len)` into slices of physical memory, or
+/// `None` if any page is not mapped for user access (and writable, if asked)
+fn checked_user_buffer(token: usize, va: usize, len: usize, write: bool) -> Option<Vec<&'static mut [u8]>> {
+    let page_table = PageTable::from_token(token);
+    let mut start = va;
+    let end = va.checked_add(len)?;
+    // user space is the lower half of SV39; higher addresses would alias it
+    if end > 1 << 38 {
+        return None;
+    }
+    let mut v = Vec::new();
+    while start < end {
+        let start_va = VirtAddr::from(start);
+        let mut vpn = start_va.floor();
+        let pte = if write {
+            prepare_user_write(&page_table, vpn)?
+        } else {
+            page_table.translate(vpn)?
+        };
+        if !pte.is_valid() || !pte.flags().contains(PTEFlags::U) || (write && !pte.writable()) {
+            return None;
+        }
+        vpn.step();
+        let mut end_va: VirtAddr = vpn.into();
+        end_va = end_va.min(VirtAddr::from(end));
+        if end_va.page_offset() == 0 {
+            v.push(&mut pte.ppn().get_bytes_array()[start_va.page_offset()..]);
+        } else {
+            v.push(&mut pte.ppn().get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+        }
+        start = end_va.into();
+    }
+    Some(v)
+}
+
+/// Copy `data` to user address `va`; unlike the `translated_*` helpers this
+/// fails instead of panicking when the range is not mapped
+pub fn copy_to_user(token: usize, va: usize, data: &[u8]) -> bool {
+    match checked_user_buffer(token, va, data.len(), true) {
+        Some(buffers) => {
+            let mut offset = 0;
+            for buffer in buffers {
+                buffer.copy_from_slice(&data[offset..offset + buffer.len()]);
+                offset += buffer.len();
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// Copy from user address `va` into `data`, failing if the range is not mapped
+pub fn copy_from_user(token: usize, va: usize, data: &mut [u8]) -> bool {
+    match checked_user_buffer(token, va, data.len(), false) {
+        Some(buffers) => {
+            let mut offset = 0;
+            for buffer in buffers {
+                data[offset..offset + buffer.len()].copy_from_slice(buffer);
+                offset += buffer.len();
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// Read a `T` from user space, `None` if it is not mapped
+pub fn read_user<T: Copy>(token: usize, ptr: *const T) -> Option<T> {
+    let mut value = core::mem::MaybeUninit::<T>::uninit();
+    let bytes = unsafe {
+        core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, core::mem::size_of::<T>())
+    };
+    if copy_from_user(token, ptr as usize, bytes) {
+        Some(unsafe { value.assume_init() })
+    } else {
+        None
+    }
+}
+
+/// Write `value` to user space, false if it is not mapped writable
+pub fn write_user<T: Copy>(token: usize, ptr: *mut T, value: &T) -> bool {
+    let bytes = unsafe {
+        core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>())
+    };
+    copy_to_user(token, ptr as usize, bytes)
+}
+
 /// An abstraction over a buffer passed from user space to kernel space
 pub struct UserBuffer {
     pub buffers: Vec<&'static mut [u8]>,
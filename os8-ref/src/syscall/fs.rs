@@ -2,14 +2,22 @@
 
 use crate::fs::make_pipe;
 use crate::fs::open_file;
+use crate::fs::{link_file, make_dir, remove_dir, unlink_file};
+use crate::fs::{set_file_times, wall_clock, TimeSpec};
+use crate::fs::Metadata;
+use crate::fs::{set_tty_foreground, tty_foreground};
 use crate::fs::OpenFlags;
-use crate::fs::Stat;
+use crate::fs::{Stat, StatEx};
 use crate::mm::translated_byte_buffer;
+use crate::mm::translated_byte_buffer_mut;
+use crate::mm::read_user;
+use crate::mm::write_user;
 use crate::mm::translated_refmut;
 use crate::mm::translated_str;
 use crate::mm::UserBuffer;
 use crate::task::current_process;
 use crate::task::current_user_token;
+use crate::task::processes_in_group;
 use alloc::sync::Arc;
 
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
@@ -40,7 +48,7 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
         let file = file.clone();
         // release current process TCB manually to avoid multi-borrow
         drop(inner);
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        file.read(UserBuffer::new(translated_byte_buffer_mut(token, buf as *mut u8, len))) as isize
     } else {
         -1
     }
@@ -82,6 +90,8 @@ pub fn sys_pipe(pipe: *mut usize) -> isize {
     inner.fd_table[read_fd] = Some(pipe_read);
     let write_fd = inner.alloc_fd();
     inner.fd_table[write_fd] = Some(pipe_write);
+    // writing to user memory may copy a copy-on-write page of this process
+    drop(inner);
     *translated_refmut(token, pipe) = read_fd;
     *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
     0
@@ -101,14 +111,149 @@ pub fn sys_dup(fd: usize) -> isize {
     new_fd as isize
 }
 
-pub fn sys_fstat(_fd: usize, _st: *mut Stat) -> isize {
-    -1
+pub fn sys_fstat(fd: usize, st: *mut Stat) -> isize {
+    match fd_metadata(fd) {
+        Some(metadata) => write_status(st, &Stat::from_metadata(&metadata)),
+        None => -1,
+    }
+}
+
+/// Like `sys_fstat`, but also reports the permission bits, owner, size and
+/// timestamps
+pub fn sys_fstat_ex(fd: usize, st: *mut StatEx) -> isize {
+    match fd_metadata(fd) {
+        Some(metadata) => write_status(st, &StatEx::from_metadata(&metadata)),
+        None => -1,
+    }
+}
+
+fn fd_metadata(fd: usize) -> Option<Metadata> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.metadata(),
+        _ => None,
+    }
+}
+
+fn write_status<T: Copy>(st: *mut T, stat: &T) -> isize {
+    if write_user(current_user_token(), st, stat) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// `sys_utimensat` nanoseconds meaning the current time
+pub const UTIME_NOW: u64 = (1 << 30) - 1;
+/// `sys_utimensat` nanoseconds leaving a time unchanged
+pub const UTIME_OMIT: u64 = (1 << 30) - 2;
+
+/// Set the access and modification times of the file at `path`, or of the
+/// open file `fd` if `path` is null. Null `times` sets both to now.
+pub fn sys_utimensat(fd: usize, path: *const u8, times: *const [TimeSpec; 2]) -> isize {
+    let token = current_user_token();
+    let now = wall_clock();
+    let [atime, mtime] = if times.is_null() {
+        [Some(now); 2]
+    } else {
+        match read_user(token, times) {
+            Some(times) if times.iter().all(|time| {
+                time.nsec < 1_000_000_000 || time.nsec == UTIME_NOW || time.nsec == UTIME_OMIT
+            }) => times.map(|time| match time.nsec {
+                UTIME_NOW => Some(now),
+                UTIME_OMIT => None,
+                _ => Some(time),
+            }),
+            _ => return -1,
+        }
+    };
+    let done = if path.is_null() {
+        let process = current_process();
+        let inner = process.inner_exclusive_access();
+        match inner.fd_table.get(fd) {
+            Some(Some(file)) => file.set_times(atime, mtime),
+            _ => false,
+        }
+    } else {
+        let path = translated_str(token, path);
+        set_file_times(path.as_str(), atime, mtime)
+    };
+    if done {
+        0
+    } else {
+        -1
+    }
+}
+
+pub fn sys_linkat(old_name: *const u8, new_name: *const u8) -> isize {
+    let token = current_user_token();
+    let old_name = translated_str(token, old_name);
+    let new_name = translated_str(token, new_name);
+    if link_file(old_name.as_str(), new_name.as_str()) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// `sys_unlinkat` flag: remove a directory instead of a file
+pub const AT_REMOVEDIR: usize = 0x200;
+
+pub fn sys_unlinkat(path: *const u8, flags: usize) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let removed = if flags & AT_REMOVEDIR != 0 {
+        remove_dir(path.as_str())
+    } else {
+        unlink_file(path.as_str())
+    };
+    if removed {
+        0
+    } else {
+        -1
+    }
 }
 
-pub fn sys_linkat(_old_name: *const u8, _new_name: *const u8) -> isize {
-    -1
+pub fn sys_mkdir(path: *const u8) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    if make_dir(path.as_str()) {
+        0
+    } else {
+        -1
+    }
 }
 
-pub fn sys_unlinkat(_name: *const u8) -> isize {
-    -1
+/// `sys_ioctl` request: get the foreground process group of the terminal
+pub const TIOCGPGRP: usize = 0x540f;
+/// `sys_ioctl` request: set the foreground process group of the terminal
+pub const TIOCSPGRP: usize = 0x5410;
+
+/// Terminal control on `fd`; only the foreground process group requests
+/// are supported, and only on the console
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    match inner.fd_table.get(fd) {
+        Some(Some(file)) if file.is_tty() => {}
+        _ => return -1,
+    }
+    let sid = inner.sid;
+    drop(inner);
+    match request {
+        TIOCGPGRP => tty_foreground() as isize,
+        TIOCSPGRP => {
+            // the new foreground group must exist in the caller's session
+            if !processes_in_group(arg)
+                .iter()
+                .any(|process| process.inner_exclusive_access().sid == sid)
+            {
+                return -1;
+            }
+            set_tty_foreground(arg);
+            0
+        }
+        _ => -1,
+    }
 }
@@ -1,11 +1,14 @@
 //! Process management syscalls
 
 use crate::config::MAX_SYSCALL_NUM;
-use crate::fs::{open_file, OpenFlags};
-use crate::mm::{translated_ref, translated_refmut, translated_str, PageTable, VirtAddr};
+use crate::fs::{open_file, File, OpenFlags};
+use crate::mm::{
+    read_user, translated_ref, translated_refmut, translated_str, write_user, PageTable, VirtAddr,
+};
 use crate::task::{
-    current_process, current_task, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next, TaskStatus,
+    current_process, current_task, current_user_token, exit_current_and_run_next, pid2process,
+    pop_signal_frame, processes_in_group, send_signal, suspend_current_and_run_next, SignalAction,
+    SignalFlags, TaskStatus,
 };
 use crate::timer::get_time_us;
 use alloc::string::String;
@@ -57,35 +60,52 @@ pub fn sys_fork() -> isize {
     new_pid as isize
 }
 
-/// Syscall Exec which accepts the elf path
-pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
-    let token = current_user_token();
-    let path = translated_str(token, path);
-    let mut args_vec: Vec<String> = Vec::new();
+/// Read a null-terminated array of string pointers, as `argv` and `envp`
+fn translated_str_array(token: usize, mut ptr: *const usize) -> Vec<String> {
+    let mut strings = Vec::new();
+    if ptr.is_null() {
+        return strings;
+    }
     loop {
-        let arg_str_ptr = *translated_ref(token, args);
-        if arg_str_ptr == 0 {
+        let str_ptr = *translated_ref(token, ptr);
+        if str_ptr == 0 {
             break;
         }
-        args_vec.push(translated_str(token, arg_str_ptr as *const u8));
+        strings.push(translated_str(token, str_ptr as *const u8));
         unsafe {
-            args = args.add(1);
+            ptr = ptr.add(1);
         }
     }
+    strings
+}
+
+/// Syscall Exec which accepts the elf path, arguments and environment
+pub fn sys_exec(path: *const u8, args: *const usize, envs: *const usize) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let args_vec = translated_str_array(token, args);
+    let envs_vec = translated_str_array(token, envs);
     if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
         let all_data = app_inode.read_all();
         let process = current_process();
         let argc = args_vec.len();
-        process.exec(all_data.as_slice(), args_vec);
+        process.exec(all_data.as_slice(), args_vec, envs_vec);
         argc as isize
     } else {
         -1
     }
 }
 
+/// `sys_waitpid` option: also report children that have stopped
+pub const WUNTRACED: usize = 2;
+/// Exit code reported for a stopped child, or-ed with the stop signal
+pub const WAIT_STOPPED: i32 = 0x7f00_0000;
+
 /// If there is not a child process whose pid is same as given, return -1.
 /// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// With `WUNTRACED` a child that stopped since the last report counts too,
+/// with exit code `WAIT_STOPPED | signum`.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> isize {
     let process = current_process();
     // find a child process
 
@@ -112,8 +132,34 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         // ++++ temporarily access child TCB exclusively
         let exit_code = child.inner_exclusive_access().exit_code;
         // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
+        let token = inner.memory_set.token();
+        // writing to user memory may copy a copy-on-write page of this process
+        drop(inner);
+        *translated_refmut(token, exit_code_ptr) = exit_code;
         found_pid as isize
+    } else if options & WUNTRACED != 0 {
+        let stopped = inner.children.iter().find_map(|p| {
+            if pid != -1 && pid as usize != p.getpid() {
+                return None;
+            }
+            let mut child_inner = p.inner_exclusive_access();
+            match child_inner.stopped {
+                Some(signum) if !child_inner.stop_reported => {
+                    child_inner.stop_reported = true;
+                    Some((p.getpid(), signum))
+                }
+                _ => None,
+            }
+        });
+        let token = inner.memory_set.token();
+        drop(inner);
+        match stopped {
+            Some((found_pid, signum)) => {
+                *translated_refmut(token, exit_code_ptr) = WAIT_STOPPED | signum as i32;
+                found_pid as isize
+            }
+            None => -2,
+        }
     } else {
         -2
     }
@@ -151,8 +197,286 @@ pub fn sys_munmap(_start: usize, _len: usize) -> isize {
     -1
 }
 
-//
-// ALERT: 注意在实现 SPAWN 时不需要复制父进程地址空间，SPAWN != FORK + EXEC
-pub fn sys_spawn(_path: *const u8) -> isize {
-    -1
+pub const SPAWN_CLOSE: usize = 0;
+pub const SPAWN_DUP2: usize = 1;
+pub const SPAWN_OPEN: usize = 2;
+/// Largest fd a spawn file action may create
+const SPAWN_MAX_FD: usize = 1024;
+
+/// One change to the child's fd table, applied in order by `sys_spawn`:
+/// close `fd`, dup2 `fd` onto `arg`, or open the string at `path` with
+/// flags `arg` as `fd`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SpawnFileAction {
+    pub op: usize,
+    pub fd: usize,
+    pub arg: usize,
+    pub path: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SpawnFileActions {
+    pub actions: *const SpawnFileAction,
+    pub len: usize,
+}
+
+type FdTable = Vec<Option<Arc<dyn File + Send + Sync>>>;
+
+fn set_fd(fd_table: &mut FdTable, fd: usize, file: Option<Arc<dyn File + Send + Sync>>) {
+    if fd >= fd_table.len() {
+        fd_table.resize(fd + 1, None);
+    }
+    fd_table[fd] = file;
+}
+
+/// Apply one file action, false if it fails
+fn apply_spawn_action(token: usize, fd_table: &mut FdTable, action: &SpawnFileAction) -> bool {
+    match action.op {
+        SPAWN_CLOSE => match fd_table.get_mut(action.fd) {
+            Some(file) if file.is_some() => {
+                *file = None;
+                true
+            }
+            _ => false,
+        },
+        SPAWN_DUP2 => {
+            let file = match fd_table.get(action.fd) {
+                Some(Some(file)) => file.clone(),
+                _ => return false,
+            };
+            if action.arg >= SPAWN_MAX_FD {
+                return false;
+            }
+            set_fd(fd_table, action.arg, Some(file));
+            true
+        }
+        SPAWN_OPEN => {
+            if action.fd >= SPAWN_MAX_FD {
+                return false;
+            }
+            let path = translated_str(token, action.path as *const u8);
+            let flags = match OpenFlags::from_bits(action.arg as u32) {
+                Some(flags) => flags,
+                None => return false,
+            };
+            match open_file(path.as_str(), flags) {
+                Some(inode) => {
+                    set_fd(fd_table, action.fd, Some(inode));
+                    true
+                }
+                None => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Create a child running the elf at `path` without copying this address
+/// space, like `posix_spawn`. `args` defaults to just the path, `envs` to
+/// empty, and `file_actions` (may be null) adjusts a copy of our fd table
+/// before the child runs. Returns the child pid, or -1 without creating
+/// anything if the elf cannot be opened or an action fails.
+pub fn sys_spawn(
+    path: *const u8,
+    args: *const usize,
+    envs: *const usize,
+    file_actions: *const SpawnFileActions,
+) -> isize {
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    let mut args_vec = translated_str_array(token, args);
+    if args.is_null() {
+        args_vec.push(path.clone());
+    }
+    let envs_vec = translated_str_array(token, envs);
+    let process = current_process();
+    let mut fd_table = process.inner_exclusive_access().fd_table.clone();
+    if !file_actions.is_null() {
+        let file_actions = match read_user(token, file_actions) {
+            Some(file_actions) => file_actions,
+            None => return -1,
+        };
+        for i in 0..file_actions.len {
+            let action = match read_user(token, file_actions.actions.wrapping_add(i)) {
+                Some(action) => action,
+                None => return -1,
+            };
+            if !apply_spawn_action(token, &mut fd_table, &action) {
+                return -1;
+            }
+        }
+    }
+    match open_file(path.as_str(), OpenFlags::RDONLY) {
+        Some(app_inode) => {
+            let all_data = app_inode.read_all();
+            let child = process.spawn(all_data.as_slice(), args_vec, envs_vec, fd_table);
+            child.getpid() as isize
+        }
+        None => -1,
+    }
+}
+
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// Send signal `signum` to process `pid`, to our own process group if `pid`
+/// is 0, or to process group `-pid` if it is negative; `signum` 0 only
+/// checks that the target exists
+pub fn sys_kill(pid: isize, signum: usize) -> isize {
+    let signal = match SignalFlags::from_signum(signum) {
+        Some(signal) => Some(signal),
+        None if signum == 0 => None,
+        None => return -1,
+    };
+    // read our pgid first: the group walk borrows every member, us included
+    let pgid = current_process().inner_exclusive_access().pgid;
+    let targets = match pid {
+        0 => processes_in_group(pgid),
+        -1 => return -1,
+        pid if pid < 0 => processes_in_group((-pid) as usize),
+        pid => pid2process(pid as usize).into_iter().collect(),
+    };
+    if targets.is_empty() {
+        return -1;
+    }
+    if let Some(signal) = signal {
+        for process in targets.iter() {
+            send_signal(process, signal);
+        }
+    }
+    0
+}
+
+/// Move process `pid` (0 for ourselves) into process group `pgid` (0 for a
+/// new group named after `pid`). Only ourselves or a child may be moved, not
+/// a session leader, and only to a group of the same session.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    let current = current_process();
+    let target = if pid == 0 || pid == current.getpid() {
+        current.clone()
+    } else {
+        match current
+            .inner_exclusive_access()
+            .children
+            .iter()
+            .find(|child| child.getpid() == pid)
+        {
+            Some(child) => child.clone(),
+            None => return -1,
+        }
+    };
+    let pgid = if pgid == 0 { target.getpid() } else { pgid };
+    let sid = target.inner_exclusive_access().sid;
+    if sid == target.getpid() {
+        return -1;
+    }
+    if pgid != target.getpid()
+        && !processes_in_group(pgid)
+            .iter()
+            .any(|process| process.inner_exclusive_access().sid == sid)
+    {
+        return -1;
+    }
+    target.inner_exclusive_access().pgid = pgid;
+    0
+}
+
+/// Process group of process `pid`, 0 for ourselves
+pub fn sys_getpgid(pid: usize) -> isize {
+    let process = if pid == 0 {
+        current_process()
+    } else {
+        match pid2process(pid) {
+            Some(process) => process,
+            None => return -1,
+        }
+    };
+    let pgid = process.inner_exclusive_access().pgid;
+    pgid as isize
+}
+
+/// Start a new session and process group led by ourselves
+pub fn sys_setsid() -> isize {
+    let process = current_process();
+    let pid = process.getpid();
+    if !processes_in_group(pid).is_empty() {
+        // already a group leader
+        return -1;
+    }
+    let mut inner = process.inner_exclusive_access();
+    inner.pgid = pid;
+    inner.sid = pid;
+    pid as isize
+}
+
+/// Session of process `pid`, 0 for ourselves
+pub fn sys_getsid(pid: usize) -> isize {
+    let process = if pid == 0 {
+        current_process()
+    } else {
+        match pid2process(pid) {
+            Some(process) => process,
+            None => return -1,
+        }
+    };
+    let sid = process.inner_exclusive_access().sid;
+    sid as isize
+}
+
+/// Set the action for `signum` if `action` is not null, after storing the
+/// previous one to `old_action` if that is not null
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    let signal = match SignalFlags::from_signum(signum) {
+        Some(signal) => signal,
+        None => return -1,
+    };
+    let token = current_user_token();
+    let process = current_process();
+    let old = process.inner_exclusive_access().signal_actions.table[signum];
+    if !old_action.is_null() && !write_user(token, old_action, &old) {
+        return -1;
+    }
+    if !action.is_null() {
+        if SignalFlags::uncatchable().contains(signal) {
+            return -1;
+        }
+        let mut action = match read_user(token, action) {
+            Some(action) => action,
+            None => return -1,
+        };
+        action.mask &= !SignalFlags::uncatchable();
+        process.inner_exclusive_access().signal_actions.table[signum] = action;
+    }
+    0
+}
+
+/// Change the signal mask as `how` says and return the previous mask
+pub fn sys_sigprocmask(how: usize, set: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let old_mask = inner.signal_mask;
+    let set = SignalFlags::from_bits_truncate(set as u32) & !SignalFlags::uncatchable();
+    inner.signal_mask = match how {
+        SIG_BLOCK => old_mask | set,
+        SIG_UNBLOCK => old_mask & !set,
+        SIG_SETMASK => set,
+        _ => return -1,
+    };
+    old_mask.bits() as isize
+}
+
+/// Return from a signal handler to the code it interrupted
+pub fn sys_sigreturn() -> isize {
+    match pop_signal_frame() {
+        // the syscall return value is written to a0, so hand back its saved value
+        Some(a0) => a0 as isize,
+        None => -1,
+    }
 }
@@ -4,10 +4,11 @@
 //! Other CPU process monitoring functions are in Processor.
 
 
-use super::TaskControlBlock;
+use super::{ProcessControlBlock, TaskControlBlock};
 use crate::sync::UPSafeCell;
-use alloc::collections::VecDeque;
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 
 pub struct TaskManager {
@@ -25,9 +26,15 @@ impl TaskManager {
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
         self.ready_queue.push_back(task);
     }
-    /// Take a process out of the ready queue
+    /// Take a process out of the ready queue, dropping threads whose process
+    /// exited while they were waiting: their user resources are gone
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.ready_queue.pop_front()
+        while let Some(task) = self.ready_queue.pop_front() {
+            if task.inner_exclusive_access().res.is_some() {
+                return Some(task);
+            }
+        }
+        None
     }
 }
 
@@ -35,6 +42,9 @@ lazy_static! {
     /// TASK_MANAGER instance through lazy_static!
     pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
         unsafe { UPSafeCell::new(TaskManager::new()) };
+    /// Live user processes by pid, so that signals can find their target
+    pub static ref PID2PCB: UPSafeCell<BTreeMap<usize, Arc<ProcessControlBlock>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
 }
 
 pub fn add_task(task: Arc<TaskControlBlock>) {
@@ -44,3 +54,25 @@ pub fn add_task(task: Arc<TaskControlBlock>) {
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     TASK_MANAGER.exclusive_access().fetch()
 }
+
+pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    PID2PCB.exclusive_access().get(&pid).map(Arc::clone)
+}
+
+pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
+    PID2PCB.exclusive_access().insert(pid, process);
+}
+
+/// Live processes whose process group is `pgid`
+pub fn processes_in_group(pgid: usize) -> Vec<Arc<ProcessControlBlock>> {
+    PID2PCB
+        .exclusive_access()
+        .values()
+        .filter(|process| process.inner_exclusive_access().pgid == pgid)
+        .cloned()
+        .collect()
+}
+
+pub fn remove_from_pid2process(pid: usize) {
+    PID2PCB.exclusive_access().remove(&pid);
+}
@@ -15,6 +15,7 @@ pub mod kthread;
 mod manager;
 mod process;
 mod processor;
+mod signal;
 pub mod stackless_coroutine;
 mod switch;
 #[allow(clippy::module_inception)]
@@ -30,13 +31,18 @@ pub use context::TaskContext;
 pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle};
 pub use kthread::kernel_stackful_coroutine_test;
 use lazy_static::*;
-pub use manager::add_task;
+pub use manager::{add_task, pid2process, remove_from_pid2process};
 use manager::fetch_task;
 use process::ProcessControlBlock;
 pub use processor::{
     current_process, current_task, current_trap_cx, current_trap_cx_user_va, current_user_token,
     run_tasks, schedule, take_current_task,
 };
+pub use manager::processes_in_group;
+pub use signal::{
+    current_interrupted, handle_signals, notify_parent, pop_signal_frame, send_fault_signal,
+    send_signal, send_signal_to_group, SignalAction, SignalFlags, MAX_SIG,
+};
 pub use stackless_coroutine::kernel_stackless_coroutine_test;
 use switch::__switch;
 pub use task::{TaskControlBlock, TaskStatus};
@@ -72,6 +78,18 @@ pub fn suspend_current_and_run_next() {
 
 /// Exit current task, recycle process resources and switch to the next task
 pub fn exit_current_and_run_next(exit_code: i32) {
+    exit_current(exit_code, false);
+}
+
+/// Exit the whole process from any of its threads, e.g. when a signal kills it
+pub fn exit_current_process_and_run_next(exit_code: i32) {
+    exit_current(exit_code, true);
+}
+
+/// The process exits with the thread if it is the main thread or `whole_process`
+/// is set. Its other threads then have no user resources left; they are dropped
+/// when they come out of the ready queue (see [`manager::fetch_task`]).
+fn exit_current(exit_code: i32, whole_process: bool) {
     // take from Processor
     let task = take_current_task().unwrap();
     // **** access current TCB exclusively
@@ -88,12 +106,13 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     drop(task);
     // debug!("task {} dropped", tid);
 
-    if tid == 0 {
+    if tid == 0 || whole_process {
         let mut process_inner = process.inner_exclusive_access();
         // mark this process as a zombie process
         process_inner.is_zombie = true;
-        // record exit code of main process
+        // record exit code of the process
         process_inner.exit_code = exit_code;
+        remove_from_pid2process(process.getpid());
 
         // do not move to its parent but under initproc
         // debug!("reparent");
@@ -128,6 +147,8 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         process_inner.memory_set.recycle_data_pages();
         // drop file descriptors
         process_inner.fd_table.clear();
+        drop(process_inner);
+        notify_parent(&process);
     }
     // debug!("pcb dropped");
 
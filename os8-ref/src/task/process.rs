@@ -1,4 +1,6 @@
 use super::id::RecycleAllocator;
+use super::manager::insert_into_pid2process;
+use super::signal::{SignalActions, SignalFlags};
 use super::{add_task, pid_alloc, PidHandle, TaskControlBlock};
 use crate::fs::{File, Stdin, Stdout};
 use crate::mm::{translated_refmut, MemorySet, KERNEL_SPACE};
@@ -30,6 +32,19 @@ pub struct ProcessControlBlockInner {
     pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
     pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
     pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    /// Signals sent but not delivered yet
+    pub signals: SignalFlags,
+    /// Signals blocked from delivery
+    pub signal_mask: SignalFlags,
+    pub signal_actions: SignalActions,
+    /// Process group, for job control
+    pub pgid: usize,
+    /// Session, the process group of its leader
+    pub sid: usize,
+    /// The signal that stopped the process, if stopped
+    pub stopped: Option<usize>,
+    /// Whether `waitpid` has already told the parent about the stop
+    pub stop_reported: bool,
 }
 
 impl ProcessControlBlockInner {
@@ -75,6 +90,7 @@ impl ProcessControlBlock {
         let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
         // allocate a pid
         let pid_handle = pid_alloc();
+        let pid = pid_handle.0;
         let process = Arc::new(Self {
             pid: pid_handle,
             inner: unsafe {
@@ -97,6 +113,13 @@ impl ProcessControlBlock {
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
+                    signals: SignalFlags::empty(),
+                    signal_mask: SignalFlags::empty(),
+                    signal_actions: SignalActions::default(),
+                    pgid: pid,
+                    sid: pid,
+                    stopped: None,
+                    stop_reported: false,
                 })
             },
         });
@@ -123,6 +146,7 @@ impl ProcessControlBlock {
         let mut process_inner = process.inner_exclusive_access();
         process_inner.tasks.push(Some(Arc::clone(&task)));
         drop(process_inner);
+        insert_into_pid2process(process.getpid(), Arc::clone(&process));
         // add main thread to scheduler
         add_task(task);
         process
@@ -131,13 +155,15 @@ impl ProcessControlBlock {
     // LAB5 HINT: How to initialize deadlock data structures?
     /// Load a new elf to replace the original application address space and start execution
     /// Only support processes with a single thread.
-    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: Vec<String>) {
+    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: Vec<String>, envs: Vec<String>) {
         assert_eq!(self.inner_exclusive_access().thread_count(), 1);
         // memory_set with elf program headers/trampoline/trap context/user stack
         let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
         let new_token = memory_set.token();
         // substitute memory_set
         self.inner_exclusive_access().memory_set = memory_set;
+        // handlers point into the old image
+        self.inner_exclusive_access().signal_actions.reset_handlers();
         // then we alloc user resource for main thread again
         // since memory_set has been changed
         let task = self.inner_exclusive_access().get_task(0);
@@ -145,31 +171,10 @@ impl ProcessControlBlock {
         task_inner.res.as_mut().unwrap().ustack_base = ustack_base;
         task_inner.res.as_mut().unwrap().alloc_user_res();
         task_inner.trap_cx_ppn = task_inner.res.as_mut().unwrap().trap_cx_ppn();
+        task_inner.signal_frame = 0;
         // push arguments on user stack
-        let mut user_sp = task_inner.res.as_mut().unwrap().ustack_top();
-        user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
-        let argv_base = user_sp;
-        let mut argv: Vec<_> = (0..=args.len())
-            .map(|arg| {
-                translated_refmut(
-                    new_token,
-                    (argv_base + arg * core::mem::size_of::<usize>()) as *mut usize,
-                )
-            })
-            .collect();
-        *argv[args.len()] = 0;
-        for i in 0..args.len() {
-            user_sp -= args[i].len() + 1;
-            *argv[i] = user_sp;
-            let mut p = user_sp;
-            for c in args[i].as_bytes() {
-                *translated_refmut(new_token, p as *mut u8) = *c;
-                p += 1;
-            }
-            *translated_refmut(new_token, p as *mut u8) = 0;
-        }
-        // make the user_sp aligned to 8B for k210 platform
-        user_sp -= user_sp % core::mem::size_of::<usize>();
+        let user_sp = task_inner.res.as_mut().unwrap().ustack_top();
+        let (user_sp, argv_base, envp_base) = push_args(new_token, user_sp, &args, &envs);
         // initialize trap_cx
         let mut trap_cx = TrapContext::app_init_context(
             entry_point,
@@ -180,6 +185,7 @@ impl ProcessControlBlock {
         );
         trap_cx.x[10] = args.len();
         trap_cx.x[11] = argv_base;
+        trap_cx.x[12] = envp_base;
         *task_inner.get_trap_cx() = trap_cx;
     }
 
@@ -189,8 +195,8 @@ impl ProcessControlBlock {
     pub fn fork(self: &Arc<Self>) -> Arc<Self> {
         let mut parent = self.inner_exclusive_access();
         assert_eq!(parent.thread_count(), 1);
-        // clone parent's memory_set completely including trampoline/ustacks/trap_cxs
-        let memory_set = MemorySet::from_existed_user(&parent.memory_set);
+        // share parent's user pages copy-on-write, copy trap_cxs
+        let memory_set = MemorySet::from_existed_user(&mut parent.memory_set);
         // alloc a pid
         let pid = pid_alloc();
         // copy fd table
@@ -218,6 +224,13 @@ impl ProcessControlBlock {
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
+                    signals: SignalFlags::empty(),
+                    signal_mask: parent.signal_mask,
+                    signal_actions: parent.signal_actions.clone(),
+                    pgid: parent.pgid,
+                    sid: parent.sid,
+                    stopped: None,
+                    stop_reported: false,
                 })
             },
         });
@@ -242,15 +255,88 @@ impl ProcessControlBlock {
         child_inner.tasks.push(Some(Arc::clone(&task)));
         drop(child_inner);
         // modify kernel_stack_top in trap_cx of this thread
-        let task_inner = task.inner_exclusive_access();
+        let mut task_inner = task.inner_exclusive_access();
         let trap_cx = task_inner.get_trap_cx();
         trap_cx.kernel_sp = task.kernel_stack.get_top();
+        // the copied user stack holds the same signal frames
+        task_inner.signal_frame = parent.get_task(0).inner_exclusive_access().signal_frame;
         drop(task_inner);
+        insert_into_pid2process(child.getpid(), Arc::clone(&child));
         // add this thread to scheduler
         add_task(task);
         child
     }
 
+    /// Create a child process running `elf_data` directly, without copying
+    /// this address space first. `fd_table` is the child's, already adjusted
+    /// by the spawn file actions; signal handlers are reset as in exec.
+    pub fn spawn(
+        self: &Arc<Self>,
+        elf_data: &[u8],
+        args: Vec<String>,
+        envs: Vec<String>,
+        fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    ) -> Arc<Self> {
+        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let token = memory_set.token();
+        let pid_handle = pid_alloc();
+        let mut parent = self.inner_exclusive_access();
+        let mut signal_actions = parent.signal_actions.clone();
+        signal_actions.reset_handlers();
+        let child = Arc::new(Self {
+            pid: pid_handle,
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    is_zombie: false,
+                    memory_set,
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    fd_table,
+                    tasks: Vec::new(),
+                    task_res_allocator: RecycleAllocator::new(),
+                    mutex_list: Vec::new(),
+                    semaphore_list: Vec::new(),
+                    condvar_list: Vec::new(),
+                    signals: SignalFlags::empty(),
+                    signal_mask: parent.signal_mask,
+                    signal_actions,
+                    pgid: parent.pgid,
+                    sid: parent.sid,
+                    stopped: None,
+                    stop_reported: false,
+                })
+            },
+        });
+        parent.children.push(Arc::clone(&child));
+        drop(parent);
+        // create the main thread with its ustack and trap_cx
+        let task = Arc::new(TaskControlBlock::new(
+            Arc::clone(&child),
+            ustack_base,
+            true,
+        ));
+        let task_inner = task.inner_exclusive_access();
+        let trap_cx = task_inner.get_trap_cx();
+        let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
+        drop(task_inner);
+        let (user_sp, argv_base, envp_base) = push_args(token, ustack_top, &args, &envs);
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            task.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = argv_base;
+        trap_cx.x[12] = envp_base;
+        child.inner_exclusive_access().tasks.push(Some(Arc::clone(&task)));
+        insert_into_pid2process(child.getpid(), Arc::clone(&child));
+        add_task(task);
+        child
+    }
+
     pub fn getpid(&self) -> usize {
         self.pid.0
     }
@@ -272,9 +358,42 @@ impl ProcessControlBlock {
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
+                    signals: SignalFlags::empty(),
+                    signal_mask: SignalFlags::empty(),
+                    signal_actions: SignalActions::default(),
+                    pgid: 0,
+                    sid: 0,
+                    stopped: None,
+                    stop_reported: false,
                 })
             },
         });
         process
     }
 }
+
+/// Push `args` and `envs` below `user_sp` in the address space `token`, as
+/// the null-terminated arrays `argv` and `envp` pointing to strings further
+/// down. Returns the new stack pointer and the addresses of both arrays.
+fn push_args(token: usize, mut user_sp: usize, args: &[String], envs: &[String]) -> (usize, usize, usize) {
+    user_sp -= (envs.len() + 1) * core::mem::size_of::<usize>();
+    let envp_base = user_sp;
+    user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
+    let argv_base = user_sp;
+    for (base, strings) in [(argv_base, args), (envp_base, envs)] {
+        for (i, string) in strings.iter().enumerate() {
+            user_sp -= string.len() + 1;
+            *translated_refmut(token, (base + i * core::mem::size_of::<usize>()) as *mut usize) = user_sp;
+            let mut p = user_sp;
+            for c in string.as_bytes() {
+                *translated_refmut(token, p as *mut u8) = *c;
+                p += 1;
+            }
+            *translated_refmut(token, p as *mut u8) = 0;
+        }
+        *translated_refmut(token, (base + strings.len() * core::mem::size_of::<usize>()) as *mut usize) = 0;
+    }
+    // make the user_sp aligned to 8B for k210 platform
+    user_sp -= user_sp % core::mem::size_of::<usize>();
+    (user_sp, argv_base, envp_base)
+}
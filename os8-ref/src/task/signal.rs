@@ -0,0 +1,356 @@
+//! Signals
+//!
+//! A process keeps a set of pending signals, a mask of blocked ones and an
+//! action for each signal. Pending signals that are not blocked are checked
+//! every time a thread of the process goes back to user mode (see
+//! [`crate::trap::trap_return`]). A user handler runs on the thread's user
+//! stack, below a [`SignalFrame`] holding the interrupted registers, and
+//! `sys_sigreturn` resumes from that frame.
+//!
+//! The stop signals stop the whole process: its threads wait in
+//! [`handle_signals`] until `SIGCONT` (or `SIGKILL`) is sent.
+
+use super::manager::processes_in_group;
+use super::{
+    current_process, current_task, exit_current_process_and_run_next,
+    suspend_current_and_run_next, ProcessControlBlock,
+};
+use crate::mm::{read_user, write_user};
+use bitflags::*;
+
+/// The largest signal number
+pub const MAX_SIG: usize = 31;
+/// `handler` of the default action
+pub const SIG_DFL: usize = 0;
+/// `handler` that ignores the signal
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// A set of signals, bit `n` standing for signal number `n`
+    pub struct SignalFlags: u32 {
+        const SIGHUP = 1 << 1;
+        const SIGINT = 1 << 2;
+        const SIGQUIT = 1 << 3;
+        const SIGILL = 1 << 4;
+        const SIGTRAP = 1 << 5;
+        const SIGABRT = 1 << 6;
+        const SIGBUS = 1 << 7;
+        const SIGFPE = 1 << 8;
+        const SIGKILL = 1 << 9;
+        const SIGUSR1 = 1 << 10;
+        const SIGSEGV = 1 << 11;
+        const SIGUSR2 = 1 << 12;
+        const SIGPIPE = 1 << 13;
+        const SIGALRM = 1 << 14;
+        const SIGTERM = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD = 1 << 17;
+        const SIGCONT = 1 << 18;
+        const SIGSTOP = 1 << 19;
+        const SIGTSTP = 1 << 20;
+        const SIGTTIN = 1 << 21;
+        const SIGTTOU = 1 << 22;
+        const SIGURG = 1 << 23;
+        const SIGXCPU = 1 << 24;
+        const SIGXFSZ = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF = 1 << 27;
+        const SIGWINCH = 1 << 28;
+        const SIGIO = 1 << 29;
+        const SIGPWR = 1 << 30;
+        const SIGSYS = 1 << 31;
+    }
+}
+
+impl SignalFlags {
+    /// The set containing only `signum`, `None` if it is not a valid signal
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if signum == 0 || signum > MAX_SIG {
+            None
+        } else {
+            Self::from_bits(1 << signum)
+        }
+    }
+    /// The lowest signal number in the set
+    pub fn first(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.bits().trailing_zeros() as usize)
+        }
+    }
+    /// Signals that can be neither caught, ignored nor blocked
+    pub fn uncatchable() -> Self {
+        Self::SIGKILL | Self::SIGSTOP
+    }
+}
+
+/// What to do when a signal is delivered, same layout as the user library
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalAction {
+    /// [`SIG_DFL`], [`SIG_IGN`] or the address of `fn(signum)`
+    pub handler: usize,
+    /// Signals blocked while the handler runs, besides the signal itself
+    pub mask: SignalFlags,
+    /// Where the handler returns to; it should call `sys_sigreturn`
+    pub restorer: usize,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+            restorer: 0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SignalActions {
+    pub table: [SignalAction; MAX_SIG + 1],
+}
+
+impl Default for SignalActions {
+    fn default() -> Self {
+        Self {
+            table: [SignalAction::default(); MAX_SIG + 1],
+        }
+    }
+}
+
+impl SignalActions {
+    /// Handlers do not survive exec since the code is gone, ignored signals stay ignored
+    pub fn reset_handlers(&mut self) {
+        for action in self.table.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
+        }
+    }
+}
+
+fn ignored_by_default(signum: usize) -> bool {
+    let flag = SignalFlags::from_signum(signum).unwrap();
+    // SIGCONT continues the process when it is sent, see `send_signal`
+    (SignalFlags::SIGCHLD | SignalFlags::SIGCONT | SignalFlags::SIGURG | SignalFlags::SIGWINCH)
+        .contains(flag)
+}
+
+fn stop_signals() -> SignalFlags {
+    SignalFlags::SIGSTOP | SignalFlags::SIGTSTP | SignalFlags::SIGTTIN | SignalFlags::SIGTTOU
+}
+
+fn description(signum: usize) -> &'static str {
+    match SignalFlags::from_signum(signum).unwrap() {
+        SignalFlags::SIGINT => "Interrupted",
+        SignalFlags::SIGILL => "Illegal Instruction",
+        SignalFlags::SIGABRT => "Aborted",
+        SignalFlags::SIGFPE => "Floating Point Exception",
+        SignalFlags::SIGKILL => "Killed",
+        SignalFlags::SIGSEGV => "Segmentation Fault",
+        SignalFlags::SIGTERM => "Terminated",
+        _ => "Terminated by signal",
+    }
+}
+
+/// Exit code of a process terminated by `signum`. Faults keep the codes they
+/// had before signals: -2 for a memory fault and -3 for an illegal instruction.
+fn exit_code(signum: usize) -> i32 {
+    match SignalFlags::from_signum(signum).unwrap() {
+        SignalFlags::SIGSEGV => -2,
+        SignalFlags::SIGILL => -3,
+        _ => -(signum as i32),
+    }
+}
+
+/// Saved on the user stack while a handler runs
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SignalFrame {
+    /// General-purpose registers of the interrupted code
+    x: [usize; 32],
+    sepc: usize,
+    /// Signal mask to restore
+    mask: usize,
+    /// Frame of the handler this one interrupted, 0 if none
+    prev: usize,
+}
+
+/// Mark `signal` pending for `process`. Continuing a stopped process
+/// happens here rather than on delivery, whatever the action of `SIGCONT`.
+pub fn send_signal(process: &ProcessControlBlock, signal: SignalFlags) {
+    let mut inner = process.inner_exclusive_access();
+    if signal.contains(SignalFlags::SIGCONT) {
+        inner.stopped = None;
+        inner.signals.remove(stop_signals());
+    }
+    if signal.intersects(stop_signals()) {
+        inner.signals.remove(SignalFlags::SIGCONT);
+    }
+    inner.signals |= signal;
+}
+
+/// Send `signal` to every process in group `pgid`, false if there is none
+pub fn send_signal_to_group(pgid: usize, signal: SignalFlags) -> bool {
+    let processes = processes_in_group(pgid);
+    for process in processes.iter() {
+        send_signal(process, signal);
+    }
+    !processes.is_empty()
+}
+
+/// Tell the parent of `process` that it has stopped or exited
+pub fn notify_parent(process: &ProcessControlBlock) {
+    let parent = process
+        .inner_exclusive_access()
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade());
+    if let Some(parent) = parent {
+        send_signal(&parent, SignalFlags::SIGCHLD);
+    }
+}
+
+/// Whether a signal is waiting that will interrupt the current process,
+/// so that a syscall blocked in the kernel should give up
+pub fn current_interrupted() -> bool {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let pending = inner.signals & !inner.signal_mask;
+    (0..=MAX_SIG).any(|signum| match SignalFlags::from_signum(signum) {
+        Some(signal) if pending.contains(signal) => {
+            match inner.signal_actions.table[signum].handler {
+                SIG_IGN => false,
+                SIG_DFL => !ignored_by_default(signum),
+                _ => true,
+            }
+        }
+        _ => false,
+    })
+}
+
+/// Raise a signal for a fault of the current thread. Returning to the
+/// faulting instruction would fault again, so if the signal is blocked or
+/// ignored its default action is restored, like Linux does.
+pub fn send_fault_signal(signal: SignalFlags) {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let signum = signal.first().unwrap();
+    if inner.signal_mask.contains(signal) || inner.signal_actions.table[signum].handler == SIG_IGN {
+        inner.signal_mask.remove(signal);
+        inner.signal_actions.table[signum] = SignalAction::default();
+    }
+    inner.signals |= signal;
+}
+
+/// Deliver the pending signals of the current process before going back to
+/// user mode: ignore them, terminate, or set up the current thread to run a
+/// handler. Does not return if the thread exits.
+pub fn handle_signals() {
+    loop {
+        let process = current_process();
+        let mut inner = process.inner_exclusive_access();
+        if inner.stopped.is_some() && !inner.signals.contains(SignalFlags::SIGKILL) {
+            drop(inner);
+            drop(process);
+            suspend_current_and_run_next();
+            continue;
+        }
+        let signum = match (inner.signals & !inner.signal_mask).first() {
+            Some(signum) => signum,
+            None => return,
+        };
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        let action = inner.signal_actions.table[signum];
+        match action.handler {
+            SIG_IGN => {
+                inner.signals.remove(signal);
+            }
+            SIG_DFL if ignored_by_default(signum) => {
+                inner.signals.remove(signal);
+            }
+            SIG_DFL if stop_signals().contains(signal) => {
+                inner.signals.remove(signal);
+                inner.stopped = Some(signum);
+                inner.stop_reported = false;
+                drop(inner);
+                notify_parent(&process);
+            }
+            SIG_DFL => {
+                // whichever thread takes the signal brings the whole process down
+                inner.signals.remove(signal);
+                println!("[kernel] {}, core dumped.", description(signum));
+                drop(inner);
+                drop(process);
+                exit_current_process_and_run_next(exit_code(signum));
+                return;
+            }
+            handler => {
+                inner.signals.remove(signal);
+                let old_mask = inner.signal_mask;
+                inner.signal_mask |= (action.mask | signal) & !SignalFlags::uncatchable();
+                drop(inner);
+                drop(process);
+                if !push_signal_frame(signum, handler, action.restorer, old_mask) {
+                    // nowhere to run the handler, e.g. the fault was a stack overflow
+                    let segv = SignalFlags::SIGSEGV.first().unwrap();
+                    println!("[kernel] {}, core dumped.", description(segv));
+                    exit_current_process_and_run_next(exit_code(segv));
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Save the current trap context below the user stack pointer and redirect
+/// the thread to `handler(signum)`, returning to `restorer`
+fn push_signal_frame(signum: usize, handler: usize, restorer: usize, old_mask: SignalFlags) -> bool {
+    let task = current_task().unwrap();
+    let token = task.get_user_token();
+    let mut task_inner = task.inner_exclusive_access();
+    let trap_cx = task_inner.get_trap_cx();
+    let frame = SignalFrame {
+        x: trap_cx.x,
+        sepc: trap_cx.sepc,
+        mask: old_mask.bits() as usize,
+        prev: task_inner.signal_frame,
+    };
+    let frame_va = match trap_cx.x[2].checked_sub(core::mem::size_of::<SignalFrame>()) {
+        // keep the stack 16-byte aligned for the handler
+        Some(va) => va & !0xf,
+        None => return false,
+    };
+    if !write_user(token, frame_va as *mut SignalFrame, &frame) {
+        return false;
+    }
+    task_inner.signal_frame = frame_va;
+    trap_cx.x[1] = restorer;
+    trap_cx.x[2] = frame_va;
+    trap_cx.x[10] = signum;
+    trap_cx.sepc = handler;
+    true
+}
+
+/// Resume from the innermost signal frame of the current thread, returning
+/// the restored `a0` so that the syscall return does not clobber it
+pub fn pop_signal_frame() -> Option<usize> {
+    let task = current_task().unwrap();
+    let token = task.get_user_token();
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.signal_frame == 0 {
+        return None;
+    }
+    let frame = read_user(token, task_inner.signal_frame as *const SignalFrame)?;
+    task_inner.signal_frame = frame.prev;
+    let trap_cx = task_inner.get_trap_cx();
+    trap_cx.x = frame.x;
+    trap_cx.sepc = frame.sepc;
+    drop(task_inner);
+    let process = current_process();
+    process.inner_exclusive_access().signal_mask =
+        SignalFlags::from_bits_truncate(frame.mask as u32) & !SignalFlags::uncatchable();
+    Some(frame.x[10])
+}
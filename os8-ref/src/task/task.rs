@@ -34,6 +34,8 @@ pub struct TaskControlBlockInner {
     pub exit_code: Option<i32>,
     /// Tid and ustack will be deallocated when this goes None
     pub res: Option<TaskUserRes>,
+    /// User address of the innermost signal frame, 0 outside signal handlers
+    pub signal_frame: usize,
 }
 
 /// Simple access to its internal fields
@@ -73,6 +75,7 @@ impl TaskControlBlock {
                     task_cx: TaskContext::goto_trap_return(kstack_top),
                     task_status: TaskStatus::Ready,
                     exit_code: None,
+                    signal_frame: 0,
                 })
             },
         }
@@ -124,6 +127,7 @@ impl TaskControlBlock {
                     task_cx: context,
                     task_status: TaskStatus::Ready,
                     exit_code: None,
+                    signal_frame: 0,
                 })
             },
         }
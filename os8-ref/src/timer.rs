@@ -1,6 +1,6 @@
 //! RISC-V timer-related functionality
 
-use crate::config::CLOCK_FREQ;
+use crate::config::{CLOCK_FREQ, RTC_BASE};
 use crate::sbi::set_timer;
 use crate::sync::UPSafeCell;
 use crate::task::{add_task, TaskControlBlock};
@@ -29,6 +29,16 @@ pub fn get_time_ms() -> usize {
     time::read() / (CLOCK_FREQ / MILLI_PER_SEC)
 }
 
+/// get wall clock time in nanoseconds since the Unix epoch from the RTC
+pub fn get_unix_time_ns() -> u64 {
+    // reading the low word latches the high word
+    unsafe {
+        let low = (RTC_BASE as *const u32).read_volatile() as u64;
+        let high = ((RTC_BASE + 4) as *const u32).read_volatile() as u64;
+        (high << 32) | low
+    }
+}
+
 /// set the next timer interrupt
 pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
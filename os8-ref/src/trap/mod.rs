@@ -15,10 +15,12 @@
 mod context;
 
 use crate::config::TRAMPOLINE;
+use crate::fs::poll_console;
 use crate::syscall::syscall;
+use crate::mm::VirtAddr;
 use crate::task::{
-    current_trap_cx, current_trap_cx_user_va, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next,
+    current_process, current_trap_cx, current_trap_cx_user_va, current_user_token, handle_signals,
+    send_fault_signal, suspend_current_and_run_next, SignalFlags,
 };
 use crate::timer::{check_timer, set_next_trigger};
 use riscv::register::{
@@ -67,29 +69,29 @@ pub fn trap_handler() -> ! {
             cx = current_trap_cx();
             cx.x[10] = result as usize;
         }
+        Trap::Exception(Exception::StorePageFault) if handle_cow_fault(stval) => {}
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionFault)
         | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
-            println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
+            debug!(
+                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}.",
                 scause.cause(),
                 stval,
                 current_trap_cx().sepc,
             );
-            // page fault exit code
-            exit_current_and_run_next(-2);
+            send_fault_signal(SignalFlags::SIGSEGV);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            println!("[kernel] IllegalInstruction in application, core dumped.");
-            // illegal instruction exit code
-            exit_current_and_run_next(-3);
+            debug!("[kernel] IllegalInstruction in application.");
+            send_fault_signal(SignalFlags::SIGILL);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
             check_timer();
+            poll_console();
             suspend_current_and_run_next();
         }
         _ => {
@@ -103,8 +105,18 @@ pub fn trap_handler() -> ! {
     trap_return();
 }
 
+/// A store to a page shared copy-on-write after fork
+fn handle_cow_fault(va: usize) -> bool {
+    current_process()
+        .inner_exclusive_access()
+        .memory_set
+        .handle_cow_fault(VirtAddr::from(va).floor())
+}
+
 #[no_mangle]
 pub fn trap_return() -> ! {
+    // may redirect the thread to a signal handler, or not return at all
+    handle_signals();
     set_user_trap_entry();
     let trap_cx_ptr = current_trap_cx_user_va();
     let user_satp = current_user_token();
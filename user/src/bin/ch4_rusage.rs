@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::{
+    get_time, getrusage, println, task_info_ex, yield_, RUsage, TaskInfoEx, TaskStatus,
+    RUSAGE_SELF, SYSCALL_TASK_INFO, SYSCALL_YIELD,
+};
+
+/*
+理想结果：输出 Test rusage OK!
+*/
+
+/// 在用户态忙等`ms`毫秒
+fn busy(ms: isize) {
+    let start = get_time();
+    while get_time() - start < ms {}
+}
+
+/// 逐页写一块远大于初始用户栈的栈上数组，让栈在缺页时增长
+#[inline(never)]
+fn grow_stack() {
+    let mut buf = [0u8; 16 * 4096];
+    for i in (0..buf.len()).step_by(4096).rev() {
+        unsafe { core::ptr::write_volatile(&mut buf[i], 1) };
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    busy(50);
+    for _ in 0..3 {
+        yield_();
+    }
+    let mut before = RUsage::default();
+    assert_eq!(0, getrusage(RUSAGE_SELF, &mut before));
+    assert!(before.utime.sec > 0 || before.utime.usec > 0);
+    assert!(before.nvcsw >= 3);
+    assert!(before.maxrss > 0);
+
+    grow_stack();
+    let mut after = RUsage::default();
+    assert_eq!(0, getrusage(RUSAGE_SELF, &mut after));
+    assert!(after.minflt > before.minflt);
+    assert!(after.maxrss > before.maxrss);
+    // 只支持统计当前任务
+    assert_eq!(-1, getrusage(1, &mut after));
+
+    let info = TaskInfoEx::new();
+    assert_eq!(0, task_info_ex(&info));
+    assert!(info.info.status == TaskStatus::Running);
+    assert_eq!(1, info.info.syscall_times[SYSCALL_TASK_INFO]);
+    assert!(info.info.syscall_times[SYSCALL_YIELD] >= 3);
+    // 计数只增不减，与之前getrusage的结果一致
+    assert!(info.usage.voluntary_switches as isize >= after.nvcsw);
+    assert!(info.usage.page_faults as isize >= after.minflt);
+    assert!(info.usage.peak_frames * 4096 / 1024 >= after.maxrss as usize);
+    assert!(info.usage.user_time_us >= after.utime.sec * 1_000_000 + after.utime.usec);
+    println!("Test rusage OK!");
+    0
+}
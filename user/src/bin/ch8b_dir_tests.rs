@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mkdir, open, read, rmdir, write, OpenFlags};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdir("dir_a\0"), 0);
+    assert_eq!(mkdir("dir_a\0"), -1);
+    assert_eq!(mkdir("dir_a/dir_b\0"), 0);
+    // the parent must exist
+    assert_eq!(mkdir("no_such_dir/dir_c\0"), -1);
+
+    let fd = open("/dir_a/dir_b/file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    write(fd as usize, b"nested");
+    close(fd as usize);
+    // `.` and `..` lead to the same file
+    let fd = open("dir_a/./dir_b/../dir_b/file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buffer = [0u8; 16];
+    let len = read(fd as usize, &mut buffer) as usize;
+    close(fd as usize);
+    assert_eq!(&buffer[..len], b"nested");
+    // a file is not a directory
+    assert_eq!(mkdir("dir_a/dir_b/file/dir\0"), -1);
+    assert!(open("dir_a/dir_b/file/x\0", OpenFlags::RDONLY) < 0);
+    // directories cannot be written
+    assert!(open("dir_a\0", OpenFlags::WRONLY) < 0);
+
+    // only empty directories can be removed
+    assert_eq!(rmdir("dir_a\0"), -1);
+    assert_eq!(rmdir("dir_a/dir_b/..\0"), -1);
+    assert_eq!(mkdir("dir_a/dir_c\0"), 0);
+    assert_eq!(rmdir("dir_a/dir_c\0"), 0);
+    assert_eq!(rmdir("dir_a/dir_c\0"), -1);
+    assert_eq!(mkdir("dir_a/dir_c\0"), 0);
+    println!("directory tests passed!");
+    0
+}
@@ -0,0 +1,877 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate user_lib;
+
+const LF: u8 = 0x0au8;
+const CR: u8 = 0x0du8;
+const DL: u8 = 0x7fu8;
+const BS: u8 = 0x08u8;
+const ESC: u8 = 0x1bu8;
+const LINE_START: &str = ">> ";
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::iter::Peekable;
+use core::str::Chars;
+use user_lib::console::getchar;
+use user_lib::{
+    close, dup, environ, execve, exit, flush, fork, getpid, kill, killpg, open, read, setpgid,
+    sigaction, tcsetpgrp, wait_stopped, waitpid_options, yield_, OpenFlags, SignalAction,
+    SIGCONT, SIGINT, SIGTSTP, SIGTTOU, WUNTRACED,
+};
+
+/// Signals from the terminal that the shell itself ignores but its jobs
+/// should not
+const JOB_CONTROL_SIGNALS: [i32; 3] = [SIGINT, SIGTSTP, SIGTTOU];
+
+const BUILTINS: [&str; 12] = [
+    "echo", "export", "unset", "exit", "source", ".", "history", "jobs", "fg", "bg", "true",
+    "false",
+];
+
+/// A command started by the shell. With job control it runs in a process
+/// group of its own named after its pid.
+struct Job {
+    id: usize,
+    pid: usize,
+    command: String,
+    stopped: bool,
+}
+
+struct Jobs {
+    jobs: Vec<Job>,
+    /// Whether jobs get their own process group and the terminal
+    job_control: bool,
+}
+
+impl Jobs {
+    fn add(&mut self, pid: usize, command: String) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            pid,
+            command,
+            stopped: false,
+        });
+        id
+    }
+
+    /// `%n` selects job `n`, nothing selects the latest job
+    fn find(&self, spec: Option<&str>) -> Option<usize> {
+        match spec {
+            None => self.jobs.len().checked_sub(1),
+            Some(spec) => {
+                let id: usize = spec.trim_start_matches('%').parse().ok()?;
+                self.jobs.iter().position(|job| job.id == id)
+            }
+        }
+    }
+
+    /// Record that `pid` stopped or exited with `exit_code`, as reported by
+    /// `waitpid`. Returns a description of what happened.
+    fn update(&mut self, pid: usize, exit_code: i32) -> Option<String> {
+        let idx = self.jobs.iter().position(|job| job.pid == pid)?;
+        if wait_stopped(exit_code).is_some() {
+            let job = &mut self.jobs[idx];
+            job.stopped = true;
+            Some(format!("[{}] Stopped  {}", job.id, job.command))
+        } else {
+            let job = self.jobs.remove(idx);
+            Some(format!("[{}] Done({})  {}", job.id, exit_code, job.command))
+        }
+    }
+
+    /// Report background jobs that stopped or finished since the last prompt
+    fn reap(&mut self) {
+        let mut exit_code: i32 = 0;
+        loop {
+            let pid = waitpid_options(-1, &mut exit_code, WUNTRACED);
+            if pid < 0 {
+                break;
+            }
+            if let Some(report) = self.update(pid as usize, exit_code) {
+                println!("{}", report);
+            }
+        }
+    }
+
+    fn continue_job(&mut self, idx: usize) {
+        let job = &mut self.jobs[idx];
+        if job.stopped {
+            job.stopped = false;
+            if self.job_control {
+                killpg(job.pid, SIGCONT);
+            } else {
+                kill(job.pid, SIGCONT);
+            }
+        }
+    }
+
+    /// Give the terminal to job `idx` and wait until it exits or stops.
+    /// Returns its exit status, or 128 plus the signal that stopped it.
+    fn wait_foreground(&mut self, idx: usize) -> i32 {
+        let pid = self.jobs[idx].pid;
+        if self.job_control {
+            tcsetpgrp(0, pid);
+        }
+        let mut exit_code: i32 = 0;
+        let status = loop {
+            match waitpid_options(pid as isize, &mut exit_code, WUNTRACED) {
+                -2 => {
+                    yield_();
+                }
+                -1 => break 1,
+                _ => {
+                    if let Some(signum) = wait_stopped(exit_code) {
+                        println!("");
+                        println!("{}", self.update(pid, exit_code).unwrap());
+                        break 128 + signum;
+                    }
+                    self.jobs.remove(idx);
+                    if self.job_control {
+                        println!("Shell: Process {} exited with code {}", pid, exit_code);
+                    }
+                    break exit_code;
+                }
+            }
+        };
+        if self.job_control {
+            tcsetpgrp(0, getpid() as usize);
+        }
+        status
+    }
+
+    fn jobs(&self) {
+        for job in self.jobs.iter() {
+            let state = if job.stopped { "Stopped" } else { "Running" };
+            println!("[{}] {}  {}", job.id, state, job.command);
+        }
+    }
+
+    fn fg(&mut self, spec: Option<&str>) -> i32 {
+        let idx = match self.find(spec) {
+            Some(idx) => idx,
+            None => {
+                println!("fg: no such job");
+                return 1;
+            }
+        };
+        println!("{}", self.jobs[idx].command);
+        if self.job_control {
+            tcsetpgrp(0, self.jobs[idx].pid);
+        }
+        self.continue_job(idx);
+        self.wait_foreground(idx)
+    }
+
+    fn bg(&mut self, spec: Option<&str>) -> i32 {
+        let idx = match self.find(spec) {
+            Some(idx) => idx,
+            None => {
+                println!("bg: no such job");
+                return 1;
+            }
+        };
+        self.continue_job(idx);
+        println!("[{}] {} &", self.jobs[idx].id, self.jobs[idx].command);
+        0
+    }
+}
+
+/// A piece of a word: text, or a variable expanded when the command runs
+enum Piece {
+    Text(String),
+    Var(String),
+}
+
+#[derive(Default)]
+struct Word {
+    pieces: Vec<Piece>,
+    /// A quoted word is kept even if it expands to nothing
+    quoted: bool,
+}
+
+impl Word {
+    fn push(&mut self, c: char) {
+        match self.pieces.last_mut() {
+            Some(Piece::Text(text)) => text.push(c),
+            _ => self.pieces.push(Piece::Text(c.to_string())),
+        }
+    }
+}
+
+enum Token {
+    Word(Word),
+    /// One of `;`, `&`, `&&`, `||`, `<` and `>`
+    Op(&'static str),
+}
+
+/// Read the variable name after a `$`: `?`, a digit, `NAME` or `{NAME}`
+fn variable(chars: &mut Peekable<Chars>, word: &mut Word) {
+    let braced = chars.peek() == Some(&'{');
+    if braced {
+        chars.next();
+    }
+    let mut name = String::new();
+    match chars.peek() {
+        Some(&c) if c == '?' || c.is_ascii_digit() => {
+            name.push(c);
+            chars.next();
+        }
+        _ => {
+            while let Some(&c) = chars.peek() {
+                if !c.is_ascii_alphanumeric() && c != '_' {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+        }
+    }
+    if braced && chars.peek() == Some(&'}') {
+        chars.next();
+    }
+    if name.is_empty() {
+        word.push('$');
+    } else {
+        word.pieces.push(Piece::Var(name));
+    }
+}
+
+/// Split a line into words and operators. Single quotes keep everything,
+/// double quotes still expand variables, and `#` starts a comment.
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let op = match c {
+            ' ' | '\t' => None,
+            '#' if word.is_none() => break,
+            ';' => Some(";"),
+            '&' if chars.peek() == Some(&'&') => {
+                chars.next();
+                Some("&&")
+            }
+            '&' => Some("&"),
+            '|' if chars.peek() == Some(&'|') => {
+                chars.next();
+                Some("||")
+            }
+            '|' => return Err(String::from("pipes are not supported")),
+            '<' => Some("<"),
+            '>' => Some(">"),
+            _ => {
+                let current = word.get_or_insert_with(Word::default);
+                match c {
+                    '\'' => {
+                        current.quoted = true;
+                        loop {
+                            match chars.next() {
+                                Some('\'') => break,
+                                Some(c) => current.push(c),
+                                None => return Err(String::from("unterminated quote")),
+                            }
+                        }
+                    }
+                    '"' => {
+                        current.quoted = true;
+                        loop {
+                            match chars.next() {
+                                Some('"') => break,
+                                Some('$') => variable(&mut chars, current),
+                                Some('\\') => {
+                                    if let Some(c) = chars.next() {
+                                        current.push(c);
+                                    }
+                                }
+                                Some(c) => current.push(c),
+                                None => return Err(String::from("unterminated quote")),
+                            }
+                        }
+                    }
+                    '\\' => {
+                        if let Some(c) = chars.next() {
+                            current.push(c);
+                        }
+                    }
+                    '$' => variable(&mut chars, current),
+                    c => current.push(c),
+                }
+                continue;
+            }
+        };
+        if let Some(word) = word.take() {
+            tokens.push(Token::Word(word));
+        }
+        if let Some(op) = op {
+            tokens.push(Token::Op(op));
+        }
+    }
+    if let Some(word) = word {
+        tokens.push(Token::Word(word));
+    }
+    Ok(tokens)
+}
+
+/// When a command runs, depending on the status of the one before it
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Connector {
+    Always,
+    And,
+    Or,
+}
+
+#[derive(Default)]
+struct Command {
+    words: Vec<Word>,
+    input: Option<Word>,
+    output: Option<Word>,
+    background: bool,
+}
+
+/// Group tokens into commands joined by `;`, `&`, `&&` and `||`
+fn parse(tokens: Vec<Token>) -> Result<Vec<(Connector, Command)>, String> {
+    let mut commands = Vec::new();
+    let mut connector = Connector::Always;
+    let mut command = Command::default();
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        let op = match token {
+            Token::Word(word) => {
+                command.words.push(word);
+                continue;
+            }
+            Token::Op(op) => op,
+        };
+        if op == "<" || op == ">" {
+            let target = match tokens.next() {
+                Some(Token::Word(word)) => word,
+                _ => return Err(format!("syntax error: missing file after `{}`", op)),
+            };
+            if op == "<" {
+                command.input = Some(target);
+            } else {
+                command.output = Some(target);
+            }
+            continue;
+        }
+        if command.words.is_empty() {
+            let redirected = command.input.is_some() || command.output.is_some();
+            if op == ";" && connector == Connector::Always && !redirected {
+                continue;
+            }
+            return Err(format!("syntax error near `{}`", op));
+        }
+        command.background = op == "&";
+        commands.push((connector, core::mem::take(&mut command)));
+        connector = match op {
+            "&&" => Connector::And,
+            "||" => Connector::Or,
+            _ => Connector::Always,
+        };
+    }
+    if !command.words.is_empty() {
+        commands.push((connector, command));
+    } else if connector != Connector::Always
+        || command.input.is_some()
+        || command.output.is_some()
+    {
+        return Err(String::from("syntax error: unexpected end of line"));
+    }
+    Ok(commands)
+}
+
+fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Split `NAME=value`
+fn assignment(arg: &str) -> Option<(&str, &str)> {
+    let (name, value) = arg.split_once('=')?;
+    if valid_name(name) {
+        Some((name, value))
+    } else {
+        None
+    }
+}
+
+fn c_string(s: &str) -> String {
+    let mut string = String::from(s);
+    string.push('\0');
+    string
+}
+
+fn read_file(path: &str) -> Option<String> {
+    let fd = open(c_string(path).as_str(), OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buffer);
+        if len <= 0 {
+            break;
+        }
+        data.extend_from_slice(&buffer[..len as usize]);
+    }
+    close(fd as usize);
+    String::from_utf8(data).ok()
+}
+
+/// Make `path` the file descriptor `fd` of this process
+fn redirect(path: &str, fd: usize, flags: OpenFlags) -> bool {
+    let file_fd = open(c_string(path).as_str(), flags);
+    if file_fd == -1 {
+        println!("Error when opening file {}", path);
+        return false;
+    }
+    let file_fd = file_fd as usize;
+    close(fd);
+    assert_eq!(dup(file_fd), fd as isize);
+    close(file_fd);
+    true
+}
+
+/// Erase `len` characters before the cursor
+fn erase(len: usize) {
+    for _ in 0..len {
+        print!("{}", BS as char);
+        print!(" ");
+        print!("{}", BS as char);
+    }
+    flush();
+}
+
+struct Variable {
+    value: String,
+    /// Exported variables are passed to commands as their environment
+    exported: bool,
+}
+
+struct Shell {
+    jobs: Jobs,
+    variables: BTreeMap<String, Variable>,
+    /// `$0`, `$1`, ...
+    params: Vec<String>,
+    history: Vec<String>,
+    /// Exit status of the last command, `$?`
+    status: i32,
+    /// Set by the `exit` builtin
+    exit: Option<i32>,
+}
+
+impl Shell {
+    fn new(params: Vec<String>, job_control: bool) -> Self {
+        let variables = environ()
+            .iter()
+            .filter_map(|var| assignment(var))
+            .map(|(name, value)| {
+                let variable = Variable {
+                    value: String::from(value),
+                    exported: true,
+                };
+                (String::from(name), variable)
+            })
+            .collect();
+        Self {
+            jobs: Jobs {
+                jobs: Vec::new(),
+                job_control,
+            },
+            variables,
+            params,
+            history: Vec::new(),
+            status: 0,
+            exit: None,
+        }
+    }
+
+    /// Read a line from the terminal, with the up and down arrows moving
+    /// through the history
+    fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        // `history.len()` is the line being typed, kept in `draft`
+        let mut recalled = self.history.len();
+        let mut draft = String::new();
+        print!("{}", LINE_START);
+        flush();
+        loop {
+            let c = getchar();
+            match c {
+                LF | CR => {
+                    println!("");
+                    return line;
+                }
+                BS | DL => {
+                    if !line.is_empty() {
+                        erase(1);
+                        line.pop();
+                    }
+                }
+                ESC => {
+                    if getchar() != b'[' {
+                        continue;
+                    }
+                    let target = match getchar() {
+                        b'A' if recalled > 0 => recalled - 1,
+                        b'B' if recalled < self.history.len() => recalled + 1,
+                        _ => continue,
+                    };
+                    if recalled == self.history.len() {
+                        draft = line.clone();
+                    }
+                    recalled = target;
+                    erase(line.chars().count());
+                    line = match self.history.get(recalled) {
+                        Some(entry) => entry.clone(),
+                        None => draft.clone(),
+                    };
+                    print!("{}", line);
+                    flush();
+                }
+                // the read was cut short by a signal
+                0 => {}
+                _ => {
+                    print!("{}", c as char);
+                    flush();
+                    line.push(c as char);
+                }
+            }
+        }
+    }
+
+    fn add_history(&mut self, line: &str) {
+        let line = line.trim();
+        if !line.is_empty() && self.history.last().map(|last| last.as_str()) != Some(line) {
+            self.history.push(String::from(line));
+        }
+    }
+
+    fn lookup(&self, name: &str) -> String {
+        if name == "?" {
+            return self.status.to_string();
+        }
+        if let Ok(n) = name.parse::<usize>() {
+            return self.params.get(n).cloned().unwrap_or_default();
+        }
+        self.variables
+            .get(name)
+            .map(|variable| variable.value.clone())
+            .unwrap_or_default()
+    }
+
+    fn expand(&self, word: &Word) -> String {
+        let mut expanded = String::new();
+        for piece in word.pieces.iter() {
+            match piece {
+                Piece::Text(text) => expanded.push_str(text),
+                Piece::Var(name) => expanded.push_str(&self.lookup(name)),
+            }
+        }
+        expanded
+    }
+
+    fn set(&mut self, name: &str, value: &str) {
+        match self.variables.get_mut(name) {
+            Some(variable) => variable.value = String::from(value),
+            None => {
+                let variable = Variable {
+                    value: String::from(value),
+                    exported: false,
+                };
+                self.variables.insert(String::from(name), variable);
+            }
+        }
+    }
+
+    /// `NAME=value\0` for each exported variable, with `overrides` on top
+    fn environment(&self, overrides: &[(String, String)]) -> Vec<String> {
+        let mut env: BTreeMap<&str, &str> = self
+            .variables
+            .iter()
+            .filter(|(_, variable)| variable.exported)
+            .map(|(name, variable)| (name.as_str(), variable.value.as_str()))
+            .collect();
+        for (name, value) in overrides.iter() {
+            env.insert(name.as_str(), value.as_str());
+        }
+        env.iter()
+            .map(|(name, value)| format!("{}={}\0", name, value))
+            .collect()
+    }
+
+    /// Run every command of `line`, setting `$?`
+    fn run_line(&mut self, line: &str) {
+        let commands = match tokenize(line).and_then(parse) {
+            Ok(commands) => commands,
+            Err(message) => {
+                println!("shell: {}", message);
+                self.status = 2;
+                return;
+            }
+        };
+        for (connector, command) in commands.iter() {
+            let run = match connector {
+                Connector::Always => true,
+                Connector::And => self.status == 0,
+                Connector::Or => self.status != 0,
+            };
+            if run {
+                self.status = self.run(command);
+            }
+            if self.exit.is_some() {
+                return;
+            }
+        }
+    }
+
+    /// Run the lines of the script `path`
+    fn source(&mut self, path: &str) -> i32 {
+        let script = match read_file(path) {
+            Some(script) => script,
+            None => {
+                println!("shell: cannot read {}", path);
+                return 1;
+            }
+        };
+        for line in script.lines() {
+            self.run_line(line);
+            if self.exit.is_some() {
+                break;
+            }
+        }
+        self.status
+    }
+
+    fn run(&mut self, command: &Command) -> i32 {
+        let mut args: Vec<String> = command
+            .words
+            .iter()
+            .map(|word| (word, self.expand(word)))
+            .filter(|(word, arg)| word.quoted || !arg.is_empty())
+            .map(|(_, arg)| arg)
+            .collect();
+        // leading `NAME=value` words set variables, only for the command if
+        // there is one
+        let assignments = args
+            .iter()
+            .take_while(|arg| assignment(arg).is_some())
+            .count();
+        let overrides: Vec<(String, String)> = args
+            .drain(..assignments)
+            .map(|arg| {
+                let (name, value) = assignment(&arg).unwrap();
+                (String::from(name), String::from(value))
+            })
+            .collect();
+        if args.is_empty() {
+            for (name, value) in overrides.iter() {
+                self.set(name, value);
+            }
+            return 0;
+        }
+        let input = command.input.as_ref().map(|word| self.expand(word));
+        let output = command.output.as_ref().map(|word| self.expand(word));
+        if BUILTINS.contains(&args[0].as_str()) {
+            return self.run_builtin(&args, output);
+        }
+        self.execute(args, &overrides, input, output, command.background)
+    }
+
+    fn run_builtin(&mut self, args: &[String], output: Option<String>) -> i32 {
+        // point our own stdout at the file for the time of the builtin
+        let saved = match output {
+            Some(output) => {
+                flush();
+                let saved = dup(1);
+                if !redirect(&output, 1, OpenFlags::CREATE | OpenFlags::WRONLY) {
+                    close(saved as usize);
+                    return 1;
+                }
+                Some(saved as usize)
+            }
+            None => None,
+        };
+        let status = self.builtin(args);
+        if let Some(saved) = saved {
+            flush();
+            close(1);
+            dup(saved);
+            close(saved);
+        }
+        status
+    }
+
+    fn builtin(&mut self, args: &[String]) -> i32 {
+        let rest = &args[1..];
+        let first = rest.first().map(|arg| arg.as_str());
+        match args[0].as_str() {
+            "echo" => {
+                println!("{}", rest.join(" "));
+                0
+            }
+            "export" => self.export(rest),
+            "unset" => {
+                for name in rest.iter() {
+                    self.variables.remove(name.as_str());
+                }
+                0
+            }
+            "exit" => {
+                let status = match first {
+                    Some(status) => status.parse().unwrap_or(2),
+                    None => self.status,
+                };
+                self.exit = Some(status);
+                status
+            }
+            "source" | "." => match first {
+                Some(path) => self.source(path),
+                None => {
+                    println!("source: missing file name");
+                    2
+                }
+            },
+            "history" => {
+                for (i, line) in self.history.iter().enumerate() {
+                    println!("{:>4}  {}", i + 1, line);
+                }
+                0
+            }
+            "jobs" => {
+                self.jobs.jobs();
+                0
+            }
+            "fg" => self.jobs.fg(first),
+            "bg" => self.jobs.bg(first),
+            "true" => 0,
+            _ => 1,
+        }
+    }
+
+    /// `export NAME=value` or `export NAME` passes the variable to commands,
+    /// `export` alone lists the exported variables
+    fn export(&mut self, args: &[String]) -> i32 {
+        if args.is_empty() {
+            for (name, variable) in self.variables.iter() {
+                if variable.exported {
+                    println!("export {}={}", name, variable.value);
+                }
+            }
+            return 0;
+        }
+        for arg in args.iter() {
+            let (name, value) = match assignment(arg) {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            if !valid_name(name) {
+                println!("export: `{}`: not a valid identifier", arg);
+                return 1;
+            }
+            if let Some(value) = value {
+                self.set(name, value);
+            }
+            self.variables
+                .entry(String::from(name))
+                .or_insert(Variable {
+                    value: String::new(),
+                    exported: true,
+                })
+                .exported = true;
+        }
+        0
+    }
+
+    /// Run a program in a child process, waiting for it unless `background`
+    fn execute(
+        &mut self,
+        args: Vec<String>,
+        overrides: &[(String, String)],
+        input: Option<String>,
+        output: Option<String>,
+        background: bool,
+    ) -> i32 {
+        let command = args.join(" ");
+        let args_copy: Vec<String> = args.iter().map(|arg| c_string(arg)).collect();
+        let mut args_addr: Vec<*const u8> = args_copy.iter().map(|arg| arg.as_ptr()).collect();
+        args_addr.push(core::ptr::null::<u8>());
+        let envs = self.environment(overrides);
+        let mut envs_addr: Vec<*const u8> = envs.iter().map(|var| var.as_ptr()).collect();
+        envs_addr.push(core::ptr::null::<u8>());
+        let pid = fork();
+        if pid == 0 {
+            if self.jobs.job_control {
+                // a job gets a process group of its own and the default
+                // handling of terminal signals back
+                setpgid(0, 0);
+                for signum in JOB_CONTROL_SIGNALS {
+                    sigaction(signum, Some(&SignalAction::default()), None);
+                }
+            }
+            if let Some(input) = input {
+                if !redirect(&input, 0, OpenFlags::RDONLY) {
+                    exit(-4);
+                }
+            }
+            if let Some(output) = output {
+                if !redirect(&output, 1, OpenFlags::CREATE | OpenFlags::WRONLY) {
+                    exit(-4);
+                }
+            }
+            if execve(args_copy[0].as_str(), &args_addr, &envs_addr) == -1 {
+                println!("Error when executing!");
+                exit(-4);
+            }
+            unreachable!();
+        }
+        if self.jobs.job_control {
+            // also done by the child, whichever of us runs first
+            setpgid(pid as usize, pid as usize);
+        }
+        let id = self.jobs.add(pid as usize, command);
+        if background {
+            println!("[{}] {}", id, pid);
+            0
+        } else {
+            self.jobs.wait_foreground(self.jobs.jobs.len() - 1)
+        }
+    }
+}
+
+/// Without arguments an interactive shell with job control; otherwise run
+/// the script `argv[1]`, with `$1`, `$2`, ... set from the rest of `argv`
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let params: Vec<String> = argv.iter().skip(1).map(|arg| String::from(*arg)).collect();
+    if argc > 1 {
+        let mut shell = Shell::new(params, false);
+        let status = shell.source(argv[1]);
+        return shell.exit.unwrap_or(status);
+    }
+    println!("Rust user shell");
+    // take the terminal in a process group of our own
+    setpgid(0, 0);
+    tcsetpgrp(0, getpid() as usize);
+    for signum in JOB_CONTROL_SIGNALS {
+        sigaction(signum, Some(&SignalAction::ignore()), None);
+    }
+    let mut shell = Shell::new(params, true);
+    while shell.exit.is_none() {
+        let line = shell.read_line();
+        shell.jobs.reap();
+        shell.add_history(&line);
+        shell.run_line(&line);
+    }
+    shell.exit.unwrap()
+}
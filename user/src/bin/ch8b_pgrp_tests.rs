@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    fork, getpgid, getpid, getsid, kill, killpg, setpgid, setsid, wait_stopped, waitpid_options,
+    yield_, SIGCONT, SIGINT, SIGTSTP, WUNTRACED,
+};
+
+fn wait_for(pid: usize) -> i32 {
+    let mut exit_code = 0;
+    loop {
+        match waitpid_options(pid as isize, &mut exit_code, WUNTRACED) {
+            -2 => {
+                yield_();
+            }
+            n => {
+                assert_eq!(n, pid as isize);
+                return exit_code;
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        loop {
+            yield_();
+        }
+    }
+    let pid = pid as usize;
+    // the child starts in our group and session
+    assert_eq!(getpgid(pid), getpgid(0));
+    assert_eq!(getsid(pid), getsid(0));
+    assert_eq!(setpgid(pid, 0), 0);
+    assert_eq!(getpgid(pid), pid as isize);
+    // a group leader cannot start a session
+    assert_eq!(setpgid(0, 0), 0);
+    assert_eq!(setsid(), -1);
+    assert_eq!(getpgid(0), getpid());
+
+    assert_eq!(killpg(pid, SIGTSTP), 0);
+    assert_eq!(wait_stopped(wait_for(pid)), Some(SIGTSTP));
+    assert_eq!(killpg(pid, SIGCONT), 0);
+    assert_eq!(killpg(pid, SIGINT), 0);
+    assert_eq!(wait_for(pid), -SIGINT);
+    assert_eq!(killpg(pid, SIGINT), -1);
+
+    // pid 0 names our own group
+    assert_eq!(kill(0, 0), 0);
+    let pid = fork();
+    if pid == 0 {
+        setpgid(0, 0);
+        kill(0, SIGINT);
+        loop {
+            yield_();
+        }
+    }
+    assert_eq!(wait_for(pid as usize), -SIGINT);
+    println!("process group tests passed!");
+    0
+}
@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{environ, getenv};
+
+/// Without arguments print the environment. `NAME` prints the variable and
+/// fails if it is not set, `NAME=value` fails unless it has that value.
+#[no_mangle]
+pub fn main(_argc: usize, argv: &[&str]) -> i32 {
+    if argv.len() <= 1 {
+        for var in environ() {
+            println!("{}", var);
+        }
+        return 0;
+    }
+    for arg in &argv[1..] {
+        let (name, expected) = match arg.find('=') {
+            Some(idx) => (&arg[..idx], Some(&arg[idx + 1..])),
+            None => (*arg, None),
+        };
+        match (getenv(name), expected) {
+            (Some(value), None) => println!("{}", value),
+            (Some(value), Some(expected)) if value == expected => {}
+            _ => return 1,
+        }
+    }
+    0
+}
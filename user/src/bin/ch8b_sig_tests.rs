@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicI32, Ordering};
+use user_lib::{
+    exit, fork, getpid, kill, sigaction, sigprocmask, waitpid, yield_, SignalAction, SignalFlags,
+    SIGKILL, SIGSEGV, SIGUSR1, SIGUSR2, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK,
+};
+
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn record(signum: i32) {
+    RECEIVED.store(signum, Ordering::SeqCst);
+}
+
+extern "C" fn on_segv(signum: i32) {
+    assert_eq!(signum, SIGSEGV);
+    exit(42);
+}
+
+fn user_handler() {
+    let action = SignalAction::new(record, SignalFlags::empty());
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    assert_eq!(kill(getpid() as usize, SIGUSR1), 0);
+    assert_eq!(RECEIVED.load(Ordering::SeqCst), SIGUSR1);
+    println!("user handler test passed!");
+}
+
+fn blocked_signal() {
+    RECEIVED.store(0, Ordering::SeqCst);
+    let action = SignalAction::new(record, SignalFlags::empty());
+    assert_eq!(sigaction(SIGUSR2, Some(&action), None), 0);
+    sigprocmask(SIG_BLOCK, SignalFlags::SIGUSR2);
+    kill(getpid() as usize, SIGUSR2);
+    yield_();
+    assert_eq!(RECEIVED.load(Ordering::SeqCst), 0);
+    sigprocmask(SIG_UNBLOCK, SignalFlags::SIGUSR2);
+    assert_eq!(RECEIVED.load(Ordering::SeqCst), SIGUSR2);
+    assert_eq!(sigprocmask(SIG_SETMASK, SignalFlags::empty()), 0);
+    println!("blocked signal test passed!");
+}
+
+fn uncatchable() {
+    let action = SignalAction::new(record, SignalFlags::empty());
+    assert_eq!(sigaction(SIGKILL, Some(&action), None), -1);
+    let pid = fork();
+    if pid == 0 {
+        loop {
+            yield_();
+        }
+    }
+    assert_eq!(kill(pid as usize, SIGKILL), 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGKILL);
+    println!("SIGKILL test passed!");
+}
+
+fn catch_page_fault() {
+    let pid = fork();
+    if pid == 0 {
+        let action = SignalAction::new(on_segv, SignalFlags::empty());
+        sigaction(SIGSEGV, Some(&action), None);
+        unsafe {
+            #[allow(clippy::zero_ptr)]
+            (0x0 as *mut u8).write_volatile(0);
+        }
+        exit(-1);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 42);
+    println!("SIGSEGV handler test passed!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    user_handler();
+    blocked_signal();
+    uncatchable();
+    catch_page_fault();
+    println!("signal tests passed!");
+    0
+}
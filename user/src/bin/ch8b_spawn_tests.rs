@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, getenv, open, posix_spawn, read, waitpid, OpenFlags, SpawnFileAction,
+};
+
+const OUTPUT: &str = "spawn_out\0";
+
+fn child() -> i32 {
+    // stdout is the file opened by the spawn file action
+    println!("{}", getenv("SPAWN_TEST").unwrap());
+    7
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 && argv[1] == "child" {
+        return child();
+    }
+    let path = "ch8b_spawn_tests\0";
+    let args = [path.as_ptr(), "child\0".as_ptr(), core::ptr::null()];
+    let envs = ["SPAWN_TEST=redirected\0".as_ptr(), core::ptr::null()];
+
+    // a failing action creates no child
+    let bad = [SpawnFileAction::close(100)];
+    assert_eq!(posix_spawn(path, &args, &envs, &bad), -1);
+
+    let actions = [
+        SpawnFileAction::open(3, OUTPUT, OpenFlags::CREATE | OpenFlags::WRONLY),
+        SpawnFileAction::dup2(3, 1),
+        SpawnFileAction::close(3),
+    ];
+    let pid = posix_spawn(path, &args, &envs, &actions);
+    assert!(pid > 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 7);
+
+    let fd = open(OUTPUT, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut buffer = [0u8; 64];
+    let len = read(fd as usize, &mut buffer) as usize;
+    close(fd as usize);
+    assert_eq!(core::str::from_utf8(&buffer[..len]).unwrap(), "redirected\n");
+    println!("spawn tests passed!");
+    0
+}
@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fstat_ex, futimens, open, read, utimens, write, OpenFlags, StatEx, StatMode, TimeSpec,
+    UTIME_NOW, UTIME_OMIT,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("time_file\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let stat = StatEx::new();
+    assert_eq!(fstat_ex(fd, &stat), 0);
+    assert_eq!(stat.mode - StatMode::PERM, StatMode::FILE);
+    assert_eq!((stat.mode & StatMode::PERM).bits(), 0o644);
+    assert_eq!((stat.uid, stat.gid), (0, 0));
+    // the wall clock, well after 2001
+    assert!(stat.mtime.sec > 1_000_000_000);
+    assert_eq!(stat.mtime, stat.ctime);
+
+    write(fd, b"timestamps");
+    fstat_ex(fd, &stat);
+    assert_eq!(stat.size, 10);
+    assert_eq!(stat.blocks, 1);
+
+    // set the modification time, keep the access time
+    let old = TimeSpec { sec: 1_000, nsec: 500 };
+    let times = [TimeSpec { sec: 0, nsec: UTIME_OMIT }, old];
+    assert_eq!(utimens("time_file\0", Some(&times)), 0);
+    let before = StatEx::new();
+    fstat_ex(fd, &before);
+    assert_eq!(before.mtime, old);
+    assert_eq!(before.atime, stat.atime);
+    assert!(before.ctime.sec >= stat.ctime.sec);
+
+    // reading updates the access time only
+    close(fd);
+    let fd = open("time_file\0", OpenFlags::RDONLY) as usize;
+    let mut buf = [0u8; 16];
+    assert_eq!(read(fd, &mut buf), 10);
+    let after = StatEx::new();
+    fstat_ex(fd, &after);
+    assert_eq!(after.mtime, old);
+    assert!(after.atime.sec > old.sec);
+
+    // both to now
+    let times = [TimeSpec { sec: 0, nsec: UTIME_NOW }; 2];
+    assert_eq!(futimens(fd, Some(&times)), 0);
+    fstat_ex(fd, &after);
+    assert!(after.mtime.sec > old.sec);
+    assert_eq!(futimens(fd, None), 0);
+    assert_eq!(utimens("no_such_file\0", None), -1);
+    let bad = [TimeSpec { sec: 0, nsec: 1_000_000_000 }; 2];
+    assert_eq!(futimens(fd, Some(&bad)), -1);
+    close(fd);
+    println!("time tests passed!");
+    0
+}
@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fstat, link, mkdir, open, read, rmdir, unlink, write, OpenFlags, Stat, StatMode,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdir("unlink_dir\0"), 0);
+    let fd = open("unlink_dir/file\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    write(fd, b"still here");
+    let stat = Stat::new();
+    assert_eq!(fstat(fd, &stat), 0);
+    assert_eq!(stat.mode, StatMode::FILE);
+    assert_eq!(stat.nlink, 1);
+
+    // a link in another directory
+    assert_eq!(link("unlink_dir/file\0", "file_link\0"), 0);
+    fstat(fd, &stat);
+    assert_eq!(stat.nlink, 2);
+    // directories cannot be linked or unlinked
+    assert_eq!(link("unlink_dir\0", "dir_link\0"), -1);
+    assert_eq!(unlink("unlink_dir\0"), -1);
+
+    let reader = open("file_link\0", OpenFlags::RDONLY);
+    assert!(reader > 0);
+    assert_eq!(unlink("unlink_dir/file\0"), 0);
+    assert_eq!(unlink("file_link\0"), 0);
+    assert!(open("file_link\0", OpenFlags::RDONLY) < 0);
+    // the open file outlives its last link
+    fstat(fd, &stat);
+    assert_eq!(stat.nlink, 0);
+    let mut buffer = [0u8; 16];
+    let len = read(reader as usize, &mut buffer) as usize;
+    assert_eq!(&buffer[..len], b"still here");
+    close(reader as usize);
+    let read_fd = open("unlink_dir\0", OpenFlags::RDONLY);
+    assert!(read_fd > 0);
+    let dir_stat = Stat::new();
+    fstat(read_fd as usize, &dir_stat);
+    assert_eq!(dir_stat.mode, StatMode::DIR);
+    close(read_fd as usize);
+    assert_eq!(rmdir("unlink_dir\0"), 0);
+    close(fd);
+    println!("unlink tests passed!");
+    0
+}
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    set_log_level, syslog, write, STDOUT, SYSLOG_ACTION_CLEAR, SYSLOG_ACTION_READ_ALL,
+    SYSLOG_ACTION_READ_CLEAR, SYSLOG_ACTION_SIZE_BUFFER,
+};
+
+/// 用户堆只有16KiB，读缓冲区放在静态区
+static mut LOG: [u8; 16 * 1024] = [0; 16 * 1024];
+
+const LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+fn usage() -> i32 {
+    println!("usage: dmesg            print the kernel log");
+    println!("       dmesg -c         print and clear the kernel log");
+    println!("       dmesg -C         clear the kernel log");
+    println!("       dmesg -l LEVEL [MODULE]");
+    println!("                        record LEVEL (off|error|warn|info|debug|trace) and above");
+    println!("                        from MODULE, or from modules without their own level");
+    1
+}
+
+fn print_log(action: usize) -> i32 {
+    let log = unsafe { &mut LOG };
+    let size = syslog(SYSLOG_ACTION_SIZE_BUFFER, &mut []);
+    if size > log.len() as isize {
+        println!("dmesg: kernel log buffer ({} bytes) is larger than ours, showing the newest part", size);
+    }
+    let n = syslog(action, log);
+    if n < 0 {
+        println!("dmesg: cannot read the kernel log");
+        return 1;
+    }
+    write(STDOUT, &log[..n as usize]);
+    0
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    match argc {
+        0 | 1 => print_log(SYSLOG_ACTION_READ_ALL),
+        2 if argv[1] == "-c" => print_log(SYSLOG_ACTION_READ_CLEAR),
+        2 if argv[1] == "-C" => {
+            syslog(SYSLOG_ACTION_CLEAR, &mut []);
+            0
+        }
+        3 | 4 if argv[1] == "-l" => {
+            let level = match LEVELS.iter().position(|l| l.eq_ignore_ascii_case(argv[2])) {
+                Some(level) => level,
+                None => return usage(),
+            };
+            let module = if argc == 4 { argv[3] } else { "" };
+            if set_log_level(module, level) != 0 {
+                println!("dmesg: cannot set the log level of `{}`", module);
+                return 1;
+            }
+            0
+        }
+        _ => usage(),
+    }
+}
@@ -46,7 +46,7 @@ fn clear_bss() {
 
 #[no_mangle]
 #[link_section = ".text.entry"]
-pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
+pub extern "C" fn _start(argc: usize, argv: usize, envp: usize) -> ! {
     clear_bss();
     unsafe {
         HEAP.lock()
@@ -56,19 +56,49 @@ pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
     for i in 0..argc {
         let str_start =
             unsafe { ((argv + i * core::mem::size_of::<usize>()) as *const usize).read_volatile() };
-        let len = (0usize..)
-            .find(|i| unsafe { ((str_start + *i) as *const u8).read_volatile() == 0 })
-            .unwrap();
-        v.push(
-            core::str::from_utf8(unsafe {
-                core::slice::from_raw_parts(str_start as *const u8, len)
-            })
-            .unwrap(),
-        );
+        v.push(c_str(str_start));
+    }
+    // kernels without environment support leave a2 zero
+    if envp != 0 {
+        let mut i = 0;
+        loop {
+            let str_start = unsafe {
+                ((envp + i * core::mem::size_of::<usize>()) as *const usize).read_volatile()
+            };
+            if str_start == 0 {
+                break;
+            }
+            unsafe {
+                ENVIRON.push(c_str(str_start));
+            }
+            i += 1;
+        }
     }
     exit(main(argc, v.as_slice()));
 }
 
+fn c_str(start: usize) -> &'static str {
+    let len = (0usize..)
+        .find(|i| unsafe { ((start + *i) as *const u8).read_volatile() == 0 })
+        .unwrap();
+    core::str::from_utf8(unsafe { core::slice::from_raw_parts(start as *const u8, len) }).unwrap()
+}
+
+/// 启动时内核传入的环境变量，每项形如`NAME=value`
+static mut ENVIRON: Vec<&'static str> = Vec::new();
+
+pub fn environ() -> &'static [&'static str] {
+    unsafe { ENVIRON.as_slice() }
+}
+
+/// 查找环境变量`name`的值
+pub fn getenv(name: &str) -> Option<&'static str> {
+    environ().iter().find_map(|var| {
+        var.strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix('='))
+    })
+}
+
 #[linkage = "weak"]
 #[no_mangle]
 fn main(_argc: usize, _argv: &[&str]) -> i32 {
@@ -98,6 +128,19 @@ impl TimeVal {
     }
 }
 
+/// 自Unix纪元起的时间
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+/// `utimens`中表示当前时间的纳秒数
+pub const UTIME_NOW: usize = (1 << 30) - 1;
+/// `utimens`中表示不修改该时间的纳秒数
+pub const UTIME_OMIT: usize = (1 << 30) - 2;
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TaskStatus {
     UnInit,
@@ -114,11 +157,13 @@ pub struct SyscallInfo {
 
 const MAX_SYSCALL_NUM: usize = 500;
 
+/// 字段按rustc默认布局的顺序排列，不带`#[repr(C)]`的旧程序看到的布局不变
+#[repr(C)]
 #[derive(Debug)]
 pub struct TaskInfo {
-    pub status: TaskStatus,
-    pub syscall_times: [u32; MAX_SYSCALL_NUM],
     pub time: usize,
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    pub status: TaskStatus,
 }
 
 impl TaskInfo {
@@ -131,6 +176,60 @@ impl TaskInfo {
     }
 }
 
+/// 任务的资源使用统计，时间单位为微秒
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TaskUsage {
+    pub user_time_us: usize,
+    pub kernel_time_us: usize,
+    pub voluntary_switches: usize,
+    pub involuntary_switches: usize,
+    pub page_faults: usize,
+    pub peak_frames: usize,
+}
+
+/// 扩展的`TaskInfo`，只有支持它的内核（ch4）会填写`usage`
+#[repr(C)]
+#[derive(Debug)]
+pub struct TaskInfoEx {
+    pub info: TaskInfo,
+    pub usage: TaskUsage,
+}
+
+impl TaskInfoEx {
+    pub fn new() -> Self {
+        TaskInfoEx {
+            info: TaskInfo::new(),
+            usage: TaskUsage::default(),
+        }
+    }
+}
+
+/// 与Linux `struct rusage`布局相同
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct RUsage {
+    pub utime: TimeVal,
+    pub stime: TimeVal,
+    /// 峰值驻留内存，单位KiB
+    pub maxrss: isize,
+    pub ixrss: isize,
+    pub idrss: isize,
+    pub isrss: isize,
+    pub minflt: isize,
+    pub majflt: isize,
+    pub nswap: isize,
+    pub inblock: isize,
+    pub oublock: isize,
+    pub msgsnd: isize,
+    pub msgrcv: isize,
+    pub nsignals: isize,
+    pub nvcsw: isize,
+    pub nivcsw: isize,
+}
+
+pub const RUSAGE_SELF: isize = 0;
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct Stat {
@@ -164,6 +263,70 @@ impl Default for Stat {
     }
 }
 
+/// `fstat_ex`得到的文件状态，比`Stat`多出权限位、所有者、大小和时间戳
+#[repr(C)]
+#[derive(Debug)]
+pub struct StatEx {
+    /// 所在设备的ID
+    pub dev: u64,
+    /// inode编号
+    pub ino: u64,
+    /// 文件类型和权限位
+    pub mode: StatMode,
+    /// 硬链接数
+    pub nlink: u32,
+    /// 所有者的用户ID
+    pub uid: u32,
+    /// 所有者的组ID
+    pub gid: u32,
+    /// 特殊文件的设备ID
+    pub rdev: u64,
+    pad1: u64,
+    /// 以字节计的大小
+    pub size: i64,
+    /// 文件系统I/O的块大小
+    pub blksize: i32,
+    pad2: i32,
+    /// 占用的512字节块数
+    pub blocks: i64,
+    /// 最后访问时间
+    pub atime: TimeSpec,
+    /// 最后修改时间
+    pub mtime: TimeSpec,
+    /// 最后状态改变时间
+    pub ctime: TimeSpec,
+    unused: [u32; 2],
+}
+
+impl StatEx {
+    pub fn new() -> Self {
+        StatEx {
+            dev: 0,
+            ino: 0,
+            mode: StatMode::NULL,
+            nlink: 0,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            pad1: 0,
+            size: 0,
+            blksize: 0,
+            pad2: 0,
+            blocks: 0,
+            atime: TimeSpec::default(),
+            mtime: TimeSpec::default(),
+            ctime: TimeSpec::default(),
+            unused: [0; 2],
+        }
+    }
+}
+
+impl Default for StatEx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 bitflags! {
     pub struct StatMode: u32 {
         const NULL  = 0;
@@ -171,6 +334,8 @@ bitflags! {
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// 权限位，只出现在`StatEx`中
+        const PERM  = 0o7777;
     }
 }
 
@@ -203,10 +368,43 @@ pub fn unlink(path: &str) -> isize {
     sys_unlinkat(AT_FDCWD as usize, path, 0)
 }
 
+/// `unlinkat`的标志：删除目录
+const AT_REMOVEDIR: usize = 0x200;
+
+/// 创建目录，路径可以有多级，如`a/b`
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdirat(AT_FDCWD as usize, path, 0)
+}
+
+/// 删除空目录
+pub fn rmdir(path: &str) -> isize {
+    sys_unlinkat(AT_FDCWD as usize, path, AT_REMOVEDIR)
+}
+
 pub fn fstat(fd: usize, st: &Stat) -> isize {
     sys_fstat(fd, st)
 }
 
+/// 与`fstat`相同，但得到包含权限位、所有者和时间戳的`StatEx`
+pub fn fstat_ex(fd: usize, st: &StatEx) -> isize {
+    sys_fstat_ex(fd, st)
+}
+
+fn utimensat(fd: usize, path: *const u8, times: Option<&[TimeSpec; 2]>) -> isize {
+    let times = times.map_or(core::ptr::null(), |times| times as *const _);
+    sys_utimensat(fd, path, times, 0)
+}
+
+/// 设置文件的访问时间和修改时间，`times`为`None`时均设为当前时间
+pub fn utimens(path: &str, times: Option<&[TimeSpec; 2]>) -> isize {
+    utimensat(AT_FDCWD as usize, path.as_ptr(), times)
+}
+
+/// 设置已打开文件的访问时间和修改时间，`times`为`None`时均设为当前时间
+pub fn futimens(fd: usize, times: Option<&[TimeSpec; 2]>) -> isize {
+    utimensat(fd, core::ptr::null(), times)
+}
+
 pub fn mail_read(buf: &mut [u8]) -> isize {
     sys_mail_read(buf)
 }
@@ -244,6 +442,11 @@ pub fn exec(path: &str, args: &[*const u8]) -> isize {
     sys_exec(path, args)
 }
 
+/// 同`exec`，并以`envs`（以空指针结尾）作为新程序的环境变量
+pub fn execve(path: &str, args: &[*const u8], envs: &[*const u8]) -> isize {
+    sys_execve(path, args, envs)
+}
+
 pub fn set_priority(prio: isize) -> isize {
     sys_set_priority(prio)
 }
@@ -274,6 +477,27 @@ pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     }
 }
 
+/// `waitpid_options`的选项：没有子进程结束时立即返回-2，内核的`waitpid`本来就不阻塞
+pub const WNOHANG: usize = 1;
+/// `waitpid_options`的选项：同时报告被暂停的子进程
+pub const WUNTRACED: usize = 2;
+const WAIT_STOPPED: i32 = 0x7f00_0000;
+
+/// 不循环等待的`waitpid`，`pid`为-1时等待任意子进程。没有符合条件的子进程
+/// 时返回-1，子进程都在运行时返回-2
+pub fn waitpid_options(pid: isize, exit_code: &mut i32, options: usize) -> isize {
+    sys_waitpid_options(pid, exit_code as *mut _, options)
+}
+
+/// 若`exit_code`表示子进程被暂停，返回暂停它的信号
+pub fn wait_stopped(exit_code: i32) -> Option<i32> {
+    if exit_code & !0xff == WAIT_STOPPED {
+        Some(exit_code & 0xff)
+    } else {
+        None
+    }
+}
+
 pub fn sleep_blocking(sleep_ms: usize) {
     sys_sleep(sleep_ms);
 }
@@ -296,6 +520,71 @@ pub fn spawn(path: &str) -> isize {
     sys_spawn(path)
 }
 
+/// `posix_spawn`的文件操作，在子进程运行前依次作用于它的文件描述符表
+pub const SPAWN_CLOSE: usize = 0;
+pub const SPAWN_DUP2: usize = 1;
+pub const SPAWN_OPEN: usize = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SpawnFileAction {
+    pub op: usize,
+    pub fd: usize,
+    pub arg: usize,
+    pub path: usize,
+}
+
+impl SpawnFileAction {
+    /// 关闭`fd`
+    pub fn close(fd: usize) -> Self {
+        Self {
+            op: SPAWN_CLOSE,
+            fd,
+            arg: 0,
+            path: 0,
+        }
+    }
+    /// 让`new_fd`指向`fd`打开的文件
+    pub fn dup2(fd: usize, new_fd: usize) -> Self {
+        Self {
+            op: SPAWN_DUP2,
+            fd,
+            arg: new_fd,
+            path: 0,
+        }
+    }
+    /// 以`flags`打开`path`（以`\0`结尾）作为`fd`
+    pub fn open(fd: usize, path: &str, flags: OpenFlags) -> Self {
+        Self {
+            op: SPAWN_OPEN,
+            fd,
+            arg: flags.bits as usize,
+            path: path.as_ptr() as usize,
+        }
+    }
+}
+
+#[repr(C)]
+pub struct SpawnFileActions {
+    pub actions: *const SpawnFileAction,
+    pub len: usize,
+}
+
+/// 不复制地址空间，直接以`args`和`envs`（均以空指针结尾）运行`path`，
+/// 返回子进程pid；任一文件操作失败时不创建子进程并返回-1
+pub fn posix_spawn(
+    path: &str,
+    args: &[*const u8],
+    envs: &[*const u8],
+    file_actions: &[SpawnFileAction],
+) -> isize {
+    let file_actions = SpawnFileActions {
+        actions: file_actions.as_ptr(),
+        len: file_actions.len(),
+    };
+    sys_posix_spawn(path, args, envs, &file_actions)
+}
+
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
@@ -307,6 +596,241 @@ pub fn task_info(info: &TaskInfo) -> isize {
     sys_task_info(info)
 }
 
+pub fn task_info_ex(info: &TaskInfoEx) -> isize {
+    sys_task_info_ex(info)
+}
+
+pub fn getrusage(who: isize, usage: &mut RUsage) -> isize {
+    sys_getrusage(who, usage)
+}
+
+/// `syslog`的操作编号，与Linux相同
+pub const SYSLOG_ACTION_READ_ALL: usize = 3;
+pub const SYSLOG_ACTION_READ_CLEAR: usize = 4;
+pub const SYSLOG_ACTION_CLEAR: usize = 5;
+pub const SYSLOG_ACTION_SIZE_BUFFER: usize = 10;
+
+pub fn syslog(action: usize, buf: &mut [u8]) -> isize {
+    sys_syslog(action, buf)
+}
+/// 设置内核模块`module`的日志级别，0为关闭，1到5依次为ERROR到TRACE；
+/// `module`为空时设置默认级别
+pub fn set_log_level(module: &str, level: usize) -> isize {
+    sys_log_filter(module, level)
+}
+
+/// 系统调用跟踪的输出位置
+pub const TRACE_OFF: usize = 0;
+pub const TRACE_CONSOLE: usize = 1;
+pub const TRACE_BUFFER: usize = 2;
+
+/// 设置任务`task`（-1为自己）的系统调用跟踪，返回原来的设置
+pub fn trace(task: isize, mode: usize) -> isize {
+    sys_trace(task, mode)
+}
+
+/// 内核以`PROFILE=1`构建时，时钟中断采样到的一个用户pc
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProfileSample {
+    pub pc: usize,
+    pub count: usize,
+}
+
+/// 读取任务`task`（-1为自己）的采样结果，返回采样点总数，内核不支持时返回-1
+pub fn profile(task: isize, samples: &mut [ProfileSample]) -> isize {
+    sys_profile(task, samples)
+}
+
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
+pub const SIGILL: i32 = 4;
+pub const SIGTRAP: i32 = 5;
+pub const SIGABRT: i32 = 6;
+pub const SIGBUS: i32 = 7;
+pub const SIGFPE: i32 = 8;
+pub const SIGKILL: i32 = 9;
+pub const SIGUSR1: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGUSR2: i32 = 12;
+pub const SIGPIPE: i32 = 13;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGSTKFLT: i32 = 16;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGSTOP: i32 = 19;
+pub const SIGTSTP: i32 = 20;
+pub const SIGTTIN: i32 = 21;
+pub const SIGTTOU: i32 = 22;
+pub const SIGURG: i32 = 23;
+pub const SIGXCPU: i32 = 24;
+pub const SIGXFSZ: i32 = 25;
+pub const SIGVTALRM: i32 = 26;
+pub const SIGPROF: i32 = 27;
+pub const SIGWINCH: i32 = 28;
+pub const SIGIO: i32 = 29;
+pub const SIGPWR: i32 = 30;
+pub const SIGSYS: i32 = 31;
+
+bitflags! {
+    /// 信号集合，第`n`位对应信号`n`
+    pub struct SignalFlags: u32 {
+        const SIGHUP = 1 << SIGHUP;
+        const SIGINT = 1 << SIGINT;
+        const SIGQUIT = 1 << SIGQUIT;
+        const SIGILL = 1 << SIGILL;
+        const SIGTRAP = 1 << SIGTRAP;
+        const SIGABRT = 1 << SIGABRT;
+        const SIGBUS = 1 << SIGBUS;
+        const SIGFPE = 1 << SIGFPE;
+        const SIGKILL = 1 << SIGKILL;
+        const SIGUSR1 = 1 << SIGUSR1;
+        const SIGSEGV = 1 << SIGSEGV;
+        const SIGUSR2 = 1 << SIGUSR2;
+        const SIGPIPE = 1 << SIGPIPE;
+        const SIGALRM = 1 << SIGALRM;
+        const SIGTERM = 1 << SIGTERM;
+        const SIGSTKFLT = 1 << SIGSTKFLT;
+        const SIGCHLD = 1 << SIGCHLD;
+        const SIGCONT = 1 << SIGCONT;
+        const SIGSTOP = 1 << SIGSTOP;
+        const SIGTSTP = 1 << SIGTSTP;
+        const SIGTTIN = 1 << SIGTTIN;
+        const SIGTTOU = 1 << SIGTTOU;
+        const SIGURG = 1 << SIGURG;
+        const SIGXCPU = 1 << SIGXCPU;
+        const SIGXFSZ = 1 << SIGXFSZ;
+        const SIGVTALRM = 1 << SIGVTALRM;
+        const SIGPROF = 1 << SIGPROF;
+        const SIGWINCH = 1 << SIGWINCH;
+        const SIGIO = 1 << SIGIO;
+        const SIGPWR = 1 << SIGPWR;
+        const SIGSYS = 1 << SIGSYS;
+    }
+}
+
+/// 默认处理
+pub const SIG_DFL: usize = 0;
+/// 忽略信号
+pub const SIG_IGN: usize = 1;
+
+/// `sigprocmask`的`how`
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// 信号的处理方式，与内核的布局相同
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SignalAction {
+    /// `SIG_DFL`、`SIG_IGN`或处理函数的地址
+    pub handler: usize,
+    /// 处理函数运行期间额外屏蔽的信号，信号本身总是被屏蔽
+    pub mask: SignalFlags,
+    /// 处理函数返回到这里，由它调用`sigreturn`
+    pub restorer: usize,
+}
+
+impl SignalAction {
+    /// 用`handler`处理信号，返回时自动`sigreturn`
+    pub fn new(handler: extern "C" fn(i32), mask: SignalFlags) -> Self {
+        Self {
+            handler: handler as usize,
+            mask,
+            restorer: sigreturn_restorer as usize,
+        }
+    }
+    pub fn ignore() -> Self {
+        Self {
+            handler: SIG_IGN,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+            restorer: 0,
+        }
+    }
+}
+
+extern "C" fn sigreturn_restorer() -> ! {
+    sys_sigreturn();
+    panic!("sigreturn outside of a signal handler");
+}
+
+/// 向进程`pid`发送信号`signum`，`signum`为0时只检查进程是否存在
+pub fn kill(pid: usize, signum: i32) -> isize {
+    sys_kill(pid, signum)
+}
+
+/// 向进程组`pgid`中的每个进程发送信号`signum`
+pub fn killpg(pgid: usize, signum: i32) -> isize {
+    sys_kill(-(pgid as isize) as usize, signum)
+}
+
+/// 把进程`pid`（0为自己）移入进程组`pgid`（0表示以`pid`为组号新建进程组）
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+
+/// 进程`pid`（0为自己）所在的进程组
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+
+/// 进程`pid`（0为自己）所在的会话
+pub fn getsid(pid: usize) -> isize {
+    sys_getsid(pid)
+}
+
+/// 新建以自己为首的会话和进程组
+pub fn setsid() -> isize {
+    sys_setsid()
+}
+
+const TIOCGPGRP: usize = 0x540f;
+const TIOCSPGRP: usize = 0x5410;
+
+/// 终端`fd`的前台进程组
+pub fn tcgetpgrp(fd: usize) -> isize {
+    sys_ioctl(fd, TIOCGPGRP, 0)
+}
+
+/// 把进程组`pgid`设为终端`fd`的前台进程组，它将收到Ctrl-C和Ctrl-Z产生的信号
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> isize {
+    sys_ioctl(fd, TIOCSPGRP, pgid)
+}
+
+/// 设置信号`signum`的处理方式，并取回原来的处理方式
+pub fn sigaction(
+    signum: i32,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    sys_sigaction(
+        signum,
+        action.map_or(core::ptr::null(), |action| action as *const _),
+        old_action.map_or(core::ptr::null_mut(), |action| action as *mut _),
+    )
+}
+
+/// 按`how`修改屏蔽的信号，返回原来的屏蔽集合
+pub fn sigprocmask(how: usize, set: SignalFlags) -> isize {
+    sys_sigprocmask(how, set.bits())
+}
+
+/// 从信号处理函数返回被打断的地方，通常由`SignalAction::new`自动完成
+pub fn sigreturn() -> isize {
+    sys_sigreturn()
+}
+
 pub fn thread_create(entry: usize, arg: usize) -> isize {
     sys_thread_create(entry, arg)
 }
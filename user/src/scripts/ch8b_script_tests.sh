@@ -0,0 +1,19 @@
+# Checks of the shell's scripting, run as `ch8b_job_shell ch8b_script_tests a b`
+export GREETING=hello
+ch8b_printenv GREETING=hello || exit 1
+# only exported variables reach commands
+NAME=world
+echo "OUT='$GREETING, ${NAME}!'" > script_out
+ch8b_printenv NAME && exit 1
+# the redirected output reads back as a script
+source script_out
+OUT="$OUT" ch8b_printenv 'OUT=hello, world!' || exit 1
+ONCE=1 ch8b_printenv ONCE=1 || exit 1
+ch8b_printenv ONCE && exit 1
+unset GREETING; ch8b_printenv GREETING && exit 1
+# sequencing and exit status
+false && exit 1
+false || true || exit 1
+false; STATUS=$? ch8b_printenv STATUS=1 || exit 1
+ARGS="$1 $2" ch8b_printenv 'ARGS=a b' || exit 1
+echo 'shell script tests passed!'
@@ -1,17 +1,28 @@
-use crate::TaskInfo;
+use crate::{RUsage, SignalAction, SpawnFileActions, TaskInfo, TaskInfoEx};
 
-use super::{Stat, TimeVal};
+use super::{ProfileSample, Stat, StatEx, TimeSpec, TimeVal};
 
 pub const SYSCALL_OPENAT: usize = 56;
 pub const SYSCALL_CLOSE: usize = 57;
 pub const SYSCALL_READ: usize = 63;
 pub const SYSCALL_WRITE: usize = 64;
+pub const SYSCALL_MKDIRAT: usize = 34;
 pub const SYSCALL_UNLINKAT: usize = 35;
 pub const SYSCALL_LINKAT: usize = 37;
 pub const SYSCALL_FSTAT: usize = 80;
+pub const SYSCALL_UTIMENSAT: usize = 88;
 pub const SYSCALL_EXIT: usize = 93;
 pub const SYSCALL_SLEEP: usize = 101;
 pub const SYSCALL_YIELD: usize = 124;
+pub const SYSCALL_KILL: usize = 129;
+pub const SYSCALL_SIGACTION: usize = 134;
+pub const SYSCALL_SIGPROCMASK: usize = 135;
+pub const SYSCALL_SIGRETURN: usize = 139;
+pub const SYSCALL_SETPGID: usize = 154;
+pub const SYSCALL_GETPGID: usize = 155;
+pub const SYSCALL_GETSID: usize = 156;
+pub const SYSCALL_SETSID: usize = 157;
+pub const SYSCALL_IOCTL: usize = 29;
 pub const SYSCALL_GETTIMEOFDAY: usize = 169;
 pub const SYSCALL_GETPID: usize = 172;
 pub const SYSCALL_GETTID: usize = 178;
@@ -24,9 +35,16 @@ pub const SYSCALL_MMAP: usize = 222;
 pub const SYSCALL_SPAWN: usize = 400;
 pub const SYSCALL_MAIL_READ: usize = 401;
 pub const SYSCALL_MAIL_WRITE: usize = 402;
+pub const SYSCALL_POSIX_SPAWN: usize = 403;
 pub const SYSCALL_DUP: usize = 24;
 pub const SYSCALL_PIPE: usize = 59;
 pub const SYSCALL_TASK_INFO: usize = 410;
+pub const SYSCALL_SYSLOG: usize = 116;
+pub const SYSCALL_LOG_FILTER: usize = 411;
+pub const SYSCALL_TRACE: usize = 412;
+pub const SYSCALL_PROFILE: usize = 413;
+pub const SYSCALL_FSTAT_EX: usize = 414;
+pub const SYSCALL_GETRUSAGE: usize = 165;
 pub const SYSCALL_THREAD_CREATE: usize = 460;
 pub const SYSCALL_WAITTID: usize = 462;
 pub const SYSCALL_MUTEX_CREATE: usize = 463;
@@ -119,6 +137,10 @@ pub fn sys_linkat(
     )
 }
 
+pub fn sys_mkdirat(dirfd: usize, path: &str, mode: u32) -> isize {
+    syscall(SYSCALL_MKDIRAT, [dirfd, path.as_ptr() as usize, mode as usize])
+}
+
 pub fn sys_unlinkat(dirfd: usize, path: &str, flags: usize) -> isize {
     syscall(SYSCALL_UNLINKAT, [dirfd, path.as_ptr() as usize, flags])
 }
@@ -127,6 +149,22 @@ pub fn sys_fstat(fd: usize, st: &Stat) -> isize {
     syscall(SYSCALL_FSTAT, [fd, st as *const _ as usize, 0])
 }
 
+pub fn sys_fstat_ex(fd: usize, st: &StatEx) -> isize {
+    syscall(SYSCALL_FSTAT_EX, [fd, st as *const _ as usize, 0])
+}
+
+pub fn sys_utimensat(
+    dirfd: usize,
+    path: *const u8,
+    times: *const [TimeSpec; 2],
+    flags: usize,
+) -> isize {
+    syscall6(
+        SYSCALL_UTIMENSAT,
+        [dirfd, path as usize, times as usize, flags, 0, 0],
+    )
+}
+
 pub fn sys_mail_read(buffer: &mut [u8]) -> isize {
     syscall(
         SYSCALL_MAIL_READ,
@@ -173,10 +211,25 @@ pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
     )
 }
 
+pub fn sys_execve(path: &str, args: &[*const u8], envs: &[*const u8]) -> isize {
+    syscall(
+        SYSCALL_EXEC,
+        [
+            path.as_ptr() as usize,
+            args.as_ptr() as usize,
+            envs.as_ptr() as usize,
+        ],
+    )
+}
+
 pub fn sys_waitpid(pid: isize, xstatus: *mut i32) -> isize {
     syscall(SYSCALL_WAITPID, [pid as usize, xstatus as usize, 0])
 }
 
+pub fn sys_waitpid_options(pid: isize, xstatus: *mut i32, options: usize) -> isize {
+    syscall(SYSCALL_WAITPID, [pid as usize, xstatus as usize, options])
+}
+
 pub fn sys_set_priority(prio: isize) -> isize {
     syscall(SYSCALL_SET_PRIORITY, [prio as usize, 0, 0])
 }
@@ -193,6 +246,25 @@ pub fn sys_spawn(path: &str) -> isize {
     syscall(SYSCALL_SPAWN, [path.as_ptr() as usize, 0, 0])
 }
 
+pub fn sys_posix_spawn(
+    path: &str,
+    args: &[*const u8],
+    envs: &[*const u8],
+    file_actions: &SpawnFileActions,
+) -> isize {
+    syscall6(
+        SYSCALL_POSIX_SPAWN,
+        [
+            path.as_ptr() as usize,
+            args.as_ptr() as usize,
+            envs.as_ptr() as usize,
+            file_actions as *const _ as usize,
+            0,
+            0,
+        ],
+    )
+}
+
 pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
@@ -205,6 +277,40 @@ pub fn sys_task_info(info: &TaskInfo) -> isize {
     syscall(SYSCALL_TASK_INFO, [info as *const _ as usize, 0, 0])
 }
 
+/// 第二个参数告诉内核结构体的大小，内核据此决定是否填写扩展部分
+pub fn sys_task_info_ex(info: &TaskInfoEx) -> isize {
+    syscall(
+        SYSCALL_TASK_INFO,
+        [info as *const _ as usize, core::mem::size_of::<TaskInfoEx>(), 0],
+    )
+}
+
+pub fn sys_getrusage(who: isize, usage: &mut RUsage) -> isize {
+    syscall(SYSCALL_GETRUSAGE, [who as usize, usage as *mut _ as usize, 0])
+}
+
+pub fn sys_syslog(action: usize, buffer: &mut [u8]) -> isize {
+    syscall(
+        SYSCALL_SYSLOG,
+        [action, buffer.as_mut_ptr() as usize, buffer.len()],
+    )
+}
+
+pub fn sys_log_filter(module: &str, level: usize) -> isize {
+    syscall(SYSCALL_LOG_FILTER, [module.as_ptr() as usize, module.len(), level])
+}
+
+pub fn sys_trace(task: isize, mode: usize) -> isize {
+    syscall(SYSCALL_TRACE, [task as usize, mode, 0])
+}
+
+pub fn sys_profile(task: isize, samples: &mut [ProfileSample]) -> isize {
+    syscall(
+        SYSCALL_PROFILE,
+        [task as usize, samples.as_mut_ptr() as usize, samples.len()],
+    )
+}
+
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     syscall(SYSCALL_THREAD_CREATE, [entry, arg, 0])
 }
@@ -256,3 +362,46 @@ pub fn sys_condvar_signal(condvar_id: usize) -> isize {
 pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
     syscall(SYSCALL_CONDVAR_WAIT, [condvar_id, mutex_id, 0])
 }
+
+pub fn sys_kill(pid: usize, signum: i32) -> isize {
+    syscall(SYSCALL_KILL, [pid, signum as usize, 0])
+}
+
+pub fn sys_sigaction(
+    signum: i32,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    syscall(
+        SYSCALL_SIGACTION,
+        [signum as usize, action as usize, old_action as usize],
+    )
+}
+
+pub fn sys_sigprocmask(how: usize, set: u32) -> isize {
+    syscall(SYSCALL_SIGPROCMASK, [how, set as usize, 0])
+}
+
+pub fn sys_sigreturn() -> isize {
+    syscall(SYSCALL_SIGRETURN, [0, 0, 0])
+}
+
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(SYSCALL_SETPGID, [pid, pgid, 0])
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(SYSCALL_GETPGID, [pid, 0, 0])
+}
+
+pub fn sys_getsid(pid: usize) -> isize {
+    syscall(SYSCALL_GETSID, [pid, 0, 0])
+}
+
+pub fn sys_setsid() -> isize {
+    syscall(SYSCALL_SETSID, [0, 0, 0])
+}
+
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, request, arg])
+}